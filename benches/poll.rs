@@ -0,0 +1,172 @@
+use chrono::{TimeDelta, Utc};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use jamesporal::core::activity::{
+    Activity, ActivityContext, ActivityEvent, ActivityEventType, ActivityId, ActivityName,
+    ActivityRunId,
+};
+use jamesporal::core::workflow::{
+    Workflow, WorkflowContext, WorkflowEvent, WorkflowEventType, WorkflowId, WorkflowName,
+    WorkflowRunId,
+};
+use jamesporal::core::{AbstractActivityHandler, AbstractWorkflowHandler};
+use jamesporal::inmemory_db::Db;
+use jamesporal::store::Store;
+use tokio::runtime::Runtime;
+
+/// Number of finished runs recorded before polling.
+const HISTORY_SIZES: [usize; 3] = [1_000, 100_000, 1_000_000];
+
+struct BenchActivity;
+
+#[async_trait::async_trait]
+impl AbstractActivityHandler for BenchActivity {
+    async fn run(&self, _context: ActivityContext, input: String) -> Result<String, String> {
+        Ok(input)
+    }
+}
+
+struct BenchWorkflow;
+
+#[async_trait::async_trait]
+impl AbstractWorkflowHandler for BenchWorkflow {
+    async fn run(&self, _context: WorkflowContext, input: String) -> Result<String, String> {
+        Ok(input)
+    }
+}
+
+fn activity_event(activity: &Activity, event_type: ActivityEventType) -> ActivityEvent {
+    ActivityEvent {
+        activity_id: activity.id,
+        activity_run_id: ActivityRunId::new(),
+        workflow_run_id: WorkflowRunId::new(),
+        event_type,
+        payload: "".to_string(),
+        created_at: Utc::now(),
+        attempt_number: 1,
+        sequence: 1,
+        options: Default::default(),
+        scheduled_for: None,
+    }
+}
+
+fn workflow_event(workflow: &Workflow, event_type: WorkflowEventType) -> WorkflowEvent {
+    WorkflowEvent {
+        workflow_id: workflow.id,
+        run_id: WorkflowRunId::new(),
+        event_type,
+        payload: "".to_string(),
+        rerun_of: None,
+        rerun_checkpoint: None,
+        created_at: Utc::now(),
+        options: Default::default(),
+        fire_at: None,
+        child_run_id: None,
+        parent_run_id: None,
+    }
+}
+
+/// Enqueues one run and polls it, with `history` finished runs of the same name already
+/// in the `Db`. Latency should not depend on `history`.
+fn poll_activity(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("poll_activity");
+
+    for history in HISTORY_SIZES {
+        let db = Db::new();
+        let name = ActivityName::from(&BenchActivity);
+        let activity = Activity {
+            id: ActivityId::new(),
+            name: name.clone(),
+        };
+        runtime.block_on(async {
+            db.add_activity(activity.clone()).await;
+            for _ in 0..history {
+                db.add_activity_event(activity_event(&activity, ActivityEventType::Succeeeded))
+                    .await;
+            }
+        });
+
+        group.bench_with_input(BenchmarkId::from_parameter(history), &history, |b, _| {
+            b.to_async(&runtime).iter(|| async {
+                db.add_activity_event(activity_event(&activity, ActivityEventType::Pending))
+                    .await;
+                db.claim_pending_activity(name.clone()).await.unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+/// Like `poll_activity`, with `retries` attempts of the same name waiting out a long
+/// backoff ahead of the polled one. Latency should not depend on `retries` either.
+fn poll_activity_behind_scheduled_retries(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("poll_activity_behind_scheduled_retries");
+
+    for retries in HISTORY_SIZES {
+        let db = Db::new();
+        let name = ActivityName::from(&BenchActivity);
+        let activity = Activity {
+            id: ActivityId::new(),
+            name: name.clone(),
+        };
+        runtime.block_on(async {
+            db.add_activity(activity.clone()).await;
+            for _ in 0..retries {
+                db.add_activity_event(ActivityEvent {
+                    attempt_number: 2,
+                    scheduled_for: Some(Utc::now() + TimeDelta::days(1)),
+                    ..activity_event(&activity, ActivityEventType::Pending)
+                })
+                .await;
+            }
+        });
+
+        group.bench_with_input(BenchmarkId::from_parameter(retries), &retries, |b, _| {
+            b.to_async(&runtime).iter(|| async {
+                db.add_activity_event(activity_event(&activity, ActivityEventType::Pending))
+                    .await;
+                db.claim_pending_activity(name.clone()).await.unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+fn poll_workflow(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("poll_workflow");
+
+    for history in HISTORY_SIZES {
+        let db = Db::new();
+        let name = WorkflowName::from(&BenchWorkflow);
+        let workflow = Workflow {
+            id: WorkflowId::new(),
+            name: name.clone(),
+        };
+        runtime.block_on(async {
+            db.add_workflow(workflow.clone()).await;
+            for _ in 0..history {
+                db.add_workflow_event(workflow_event(&workflow, WorkflowEventType::Succeeeded))
+                    .await;
+            }
+        });
+
+        group.bench_with_input(BenchmarkId::from_parameter(history), &history, |b, _| {
+            b.to_async(&runtime).iter(|| async {
+                db.add_workflow_event(workflow_event(&workflow, WorkflowEventType::Pending))
+                    .await;
+                db.claim_pending_workflow(name.clone()).await.unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    poll_activity,
+    poll_activity_behind_scheduled_retries,
+    poll_workflow
+);
+criterion_main!(benches);
@@ -1,10 +1,19 @@
 use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
 
 use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::watch;
 use uuid::Uuid;
 
-use crate::core::workflow::WorkflowRunId;
+use crate::core::client::Client;
+use crate::core::payload::{
+    from_error_payload, from_payload, to_error_payload, to_payload, ErrorKind, SERIALIZATION_ERROR,
+};
+use crate::core::workflow::{ActivityOptions, WorkflowRunId};
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
 #[serde(transparent)]
@@ -57,22 +66,50 @@ impl ActivityRunId {
         ActivityRunId(Uuid::new_v4())
     }
 }
+impl fmt::Display for ActivityRunId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+impl FromStr for ActivityRunId {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Uuid::parse_str(s)
+            .map(ActivityRunId)
+            .map_err(|e| format!("Invalid run id {s}: {e}"))
+    }
+}
 
-#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Activity {
     pub id: ActivityId,
     pub name: ActivityName,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum ActivityEventType {
     Pending,
     Started,
     Succeeeded,
     Failed,
+    TimedOut,
+    /// Stopped because its workflow run was cancelled or timed out.
+    Cancelled,
 }
 
-#[derive(Debug, Clone)]
+impl ActivityEventType {
+    /// Whether a run whose last event has this type is done. A failed attempt that is
+    /// retried is followed by a new `Pending` event, so it doesn't finish its run.
+    pub fn is_finished(&self) -> bool {
+        !matches!(
+            self,
+            ActivityEventType::Pending | ActivityEventType::Started
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ActivityEvent {
     pub activity_id: ActivityId,
     pub activity_run_id: ActivityRunId,
@@ -81,10 +118,166 @@ pub struct ActivityEvent {
     pub payload: String,
     pub created_at: DateTime<Utc>,
     pub attempt_number: i64,
-    pub max_attempts: i64,
+    /// Position of the command that scheduled this activity in its workflow run.
+    pub sequence: i64,
+    /// Options the activity was scheduled with; retries are decided from these.
+    pub options: ActivityOptions,
+    /// Pending events are not handed to workers before this time (used for retry backoff).
+    pub scheduled_for: Option<DateTime<Utc>>,
+}
+
+/// Activity errors are plain strings. By convention the text before the first `:` is the
+/// error kind, e.g. `"ValidationError: not a number"` has the kind `ValidationError`.
+pub fn error_kind(error: &str) -> &str {
+    error.split_once(':').map_or(error, |(kind, _)| kind).trim()
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ActivityHeartbeat {
+    pub activity_run_id: ActivityRunId,
+    pub attempt_number: i64,
+    pub details: String,
+    pub created_at: DateTime<Utc>,
+}
+
+pub struct ActivityContext {
+    pub activity_run_id: ActivityRunId,
+    pub attempt_number: i64,
+    /// Details of the last heartbeat recorded by a previous attempt, if any, so the
+    /// activity can resume from its progress instead of starting over.
+    pub heartbeat_details: Option<String>,
+    pub client: Client,
+    pub cancellation: CancellationToken,
+}
+
+/// Fires once the attempt is no longer wanted: its workflow run was cancelled, or the
+/// attempt timed out (or was otherwise ended) on the server. Whatever the activity
+/// returns afterwards is discarded, so it may as well stop early.
+#[derive(Clone, Default)]
+pub struct CancellationToken(Arc<watch::Sender<bool>>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.send_replace(true);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    /// Resolves once the token fires, e.g. to race a long-running step in a
+    /// `tokio::select!`.
+    pub async fn cancelled(&self) {
+        // Can't fail, since the token holds on to the sender.
+        let _ = self.0.subscribe().wait_for(|cancelled| *cancelled).await;
+    }
+}
+
+impl ActivityContext {
+    /// Reports that the activity is still alive, along with its progress so far.
+    /// Fails if this attempt is no longer running (e.g. it already timed out), and
+    /// then fires the cancellation token too.
+    pub async fn heartbeat(&self, details: impl Into<String>) -> Result<(), String> {
+        let recorded = self
+            .client
+            .record_activity_heartbeat(self.activity_run_id, self.attempt_number, details.into())
+            .await;
+        if let Err(e) = &recorded {
+            if matches!(error_kind(e), "Cancelled" | "NotRunning") {
+                self.cancellation.cancel();
+            }
+        }
+        recorded
+    }
 }
 
 #[async_trait::async_trait]
 pub trait AbstractActivityHandler: Send + Sync {
-    async fn run(&self, input: String) -> Result<String, String>;
+    async fn run(&self, context: ActivityContext, input: String) -> Result<String, String>;
+}
+
+/// An activity with typed input, output and error. Inputs and outputs travel as JSON
+/// and errors tagged with their kind (see [`to_error_payload`]), so every
+/// `ActivityHandler` is an `AbstractActivityHandler` too.
+#[async_trait::async_trait]
+pub trait ActivityHandler: Send + Sync {
+    type Input: Serialize + DeserializeOwned + Send;
+    type Output: Serialize + DeserializeOwned + Send;
+    type Error: Serialize + DeserializeOwned + ErrorKind + Send;
+
+    async fn run(
+        &self,
+        context: ActivityContext,
+        input: Self::Input,
+    ) -> Result<Self::Output, Self::Error>;
+}
+
+#[async_trait::async_trait]
+impl<H: ActivityHandler> AbstractActivityHandler for H {
+    async fn run(&self, context: ActivityContext, input: String) -> Result<String, String> {
+        let input = from_payload(&input)
+            .map_err(|e| format!("{SERIALIZATION_ERROR}: invalid activity input: {e}"))?;
+        match ActivityHandler::run(self, context, input).await {
+            Ok(output) => to_payload(&output)
+                .map_err(|e| format!("{SERIALIZATION_ERROR}: invalid activity output: {e}")),
+            Err(error) => Err(to_error_payload(&error)
+                .map_err(|e| format!("{SERIALIZATION_ERROR}: invalid activity error: {e}"))?),
+        }
+    }
+}
+
+/// How a typed activity or child workflow failed, as seen by the workflow that ran it.
+#[derive(Debug)]
+pub enum HandlerError<E> {
+    /// The error the handler returned.
+    Handler(E),
+    /// The input, output or error didn't (de)serialize.
+    Serialization(String),
+    /// Anything that isn't an `E`, e.g. a timeout, a cancellation or a non-determinism
+    /// error, as a `"Kind: msg"` string.
+    Other(String),
+}
+
+impl<E: DeserializeOwned> HandlerError<E> {
+    /// Sorts an error string as reported for the run.
+    pub fn from_payload(error: String) -> Self {
+        if error_kind(&error) == SERIALIZATION_ERROR {
+            return HandlerError::Serialization(error);
+        }
+        match from_error_payload(&error) {
+            Ok(error) => HandlerError::Handler(error),
+            Err(_) => HandlerError::Other(error),
+        }
+    }
+}
+
+/// Handler errors that serialize to a JSON string (e.g. `String` ones) are shown as that
+/// string, and others as they travel.
+impl<E: Serialize + ErrorKind> fmt::Display for HandlerError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HandlerError::Handler(error) => match serde_json::to_value(error) {
+                Ok(Value::String(error)) => write!(f, "{error}"),
+                Ok(_) => match to_error_payload(error) {
+                    Ok(error) => write!(f, "{error}"),
+                    Err(e) => write!(f, "{SERIALIZATION_ERROR}: invalid handler error: {e}"),
+                },
+                Err(e) => write!(f, "{SERIALIZATION_ERROR}: invalid handler error: {e}"),
+            },
+            HandlerError::Serialization(error) | HandlerError::Other(error) => {
+                write!(f, "{error}")
+            }
+        }
+    }
+}
+
+/// So workflows returning string errors can use `?` on typed activities and children.
+impl<E: Serialize + ErrorKind> From<HandlerError<E>> for String {
+    fn from(error: HandlerError<E>) -> Self {
+        error.to_string()
+    }
 }
@@ -1,10 +1,17 @@
+use chrono::{DateTime, Utc};
+use futures::{Stream, StreamExt};
+
 use crate::core::{
     activity::{ActivityId, ActivityName, ActivityRunId},
+    payload::{to_payload, SERIALIZATION_ERROR},
     worker_events::{
         PollActivityCompletion, PollActivityResponse, PollWorkflowCompletion, PollWorkflowResponse,
-        ServerEvent, WorkerEvent,
+        RunUpdate, ServerEvent, WorkerEvent, WorkflowQuery,
+    },
+    workflow::{
+        ActivityOptions, QueryId, WorkflowHandler, WorkflowId, WorkflowName, WorkflowOptions,
+        WorkflowRunId,
     },
-    workflow::{WorkflowId, WorkflowName, WorkflowRunId},
 };
 
 #[derive(Clone)]
@@ -47,6 +54,7 @@ impl Client {
         &mut self,
         name: WorkflowName,
         input: String,
+        options: WorkflowOptions,
     ) -> Result<WorkflowRunId, String> {
         let workflow_run_id = WorkflowRunId::new();
 
@@ -54,6 +62,7 @@ impl Client {
             name,
             input,
             workflow_run_id,
+            options,
         };
 
         let _res = self
@@ -70,24 +79,45 @@ impl Client {
         Ok(workflow_run_id)
     }
 
+    /// Like `execute_workflow`, encoding `input` like the input of a `WorkflowHandler`.
+    pub async fn execute_typed_workflow<W: WorkflowHandler>(
+        &mut self,
+        workflow: &W,
+        input: &W::Input,
+        options: WorkflowOptions,
+    ) -> Result<WorkflowRunId, String> {
+        let name = WorkflowName::from(workflow);
+        let input = to_payload(input).map_err(|e| {
+            format!("{SERIALIZATION_ERROR}: invalid input for workflow {name}: {e}")
+        })?;
+        self.execute_workflow(name, input, options).await
+    }
+
     pub async fn execute_activity(
         &self,
         workflow_run_id: WorkflowRunId,
+        started_at: DateTime<Utc>,
+        sequence: i64,
         name: ActivityName,
         input: String,
-        max_attempts: i64,
+        options: ActivityOptions,
     ) -> Result<ActivityRunId, String> {
+        // Checked here too, since options that don't serialize (e.g. a NaN) never reach
+        // the server.
+        options.retry_policy.validate()?;
         let activity_run_id = ActivityRunId::new();
 
         let event = WorkerEvent::EnqueuActivity {
             name,
             input,
             workflow_run_id,
+            started_at,
             activity_run_id,
-            max_attempts,
+            sequence,
+            options,
         };
 
-        let _res = self
+        let text_res = self
             .client
             .post(format!("{}/worker_event", &self.base_url))
             .json(&event)
@@ -98,7 +128,13 @@ impl Client {
             .await
             .map_err(|e| e.to_string())?;
 
-        Ok(activity_run_id)
+        match serde_json::from_str::<ServerEvent>(&text_res) {
+            Ok(ServerEvent::GeneralSuccess { success: false }) => Err(format!(
+                "Workflow run {workflow_run_id} is no longer running"
+            )),
+            Ok(ServerEvent::Rejected { error }) => Err(error),
+            _ => Ok(activity_run_id),
+        }
     }
 
     pub async fn poll_workflow_completion(
@@ -123,7 +159,417 @@ impl Client {
 
         match server_event {
             ServerEvent::PollWorkflowCompletion(poll_response) => Ok(Some(poll_response)),
-            _ => Ok(None),
+            ServerEvent::Empty => Ok(None),
+            _ => Err("Unexpected response to PollWorkflowCompletion".to_string()),
+        }
+    }
+
+    /// Subscribes to the events of the given runs. The stream starts with the current state
+    /// of each run and ends once all of them have finished.
+    pub async fn watch_runs(
+        &self,
+        workflow_run_ids: &[WorkflowRunId],
+        activity_run_ids: &[ActivityRunId],
+    ) -> Result<impl Stream<Item = Result<RunUpdate, String>>, String> {
+        fn join(ids: &[impl ToString]) -> String {
+            ids.iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(",")
+        }
+
+        let response = self
+            .client
+            .get(format!("{}/watch", &self.base_url))
+            .query(&[
+                ("workflow_run_ids", join(workflow_run_ids)),
+                ("activity_run_ids", join(activity_run_ids)),
+            ])
+            .send()
+            .await
+            .map_err(|e| e.to_string())?
+            .error_for_status()
+            .map_err(|e| e.to_string())?;
+
+        let state = (response.bytes_stream(), Vec::new());
+        Ok(futures::stream::unfold(
+            state,
+            |(mut bytes, mut buffer)| async move {
+                loop {
+                    // Server-sent events are separated by a blank line.
+                    if let Some(end) = buffer.windows(2).position(|w| w == b"\n\n") {
+                        let message: Vec<u8> = buffer.drain(..end + 2).collect();
+                        let message = String::from_utf8_lossy(&message);
+                        let data = message
+                            .lines()
+                            .filter_map(|line| line.strip_prefix("data:"))
+                            .map(|data| data.strip_prefix(' ').unwrap_or(data))
+                            .collect::<Vec<_>>()
+                            .join("\n");
+                        if data.is_empty() {
+                            // A keep-alive.
+                            continue;
+                        }
+                        let update = serde_json::from_str(&data).map_err(|e| e.to_string());
+                        return Some((update, (bytes, buffer)));
+                    }
+                    match bytes.next().await {
+                        Some(Ok(chunk)) => buffer.extend_from_slice(&chunk),
+                        Some(Err(e)) => return Some((Err(e.to_string()), (bytes, buffer))),
+                        None => return None,
+                    }
+                }
+            },
+        ))
+    }
+
+    pub async fn signal_workflow(
+        &self,
+        workflow_run_id: WorkflowRunId,
+        signal_name: String,
+        payload: String,
+    ) -> Result<(), String> {
+        let event = WorkerEvent::SignalWorkflow {
+            workflow_run_id,
+            signal_name,
+            payload,
+        };
+
+        let text_res = self
+            .client
+            .post(format!("{}/worker_event", &self.base_url))
+            .json(&event)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?
+            .text()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let server_event =
+            serde_json::from_str::<ServerEvent>(&text_res).map_err(|e| e.to_string())?;
+
+        match server_event {
+            ServerEvent::GeneralSuccess { success: true } => Ok(()),
+            ServerEvent::GeneralSuccess { success: false } => Err(format!(
+                "Workflow run {workflow_run_id} has already finished"
+            )),
+            ServerEvent::NotFound => Err(format!("Workflow run {workflow_run_id} not found")),
+            _ => Err("Unexpected response to SignalWorkflow".to_string()),
+        }
+    }
+
+    /// Asks the run to stop. The workflow sees the request and may clean up before it
+    /// ends as `Cancelled`.
+    pub async fn cancel_workflow(&self, workflow_run_id: WorkflowRunId) -> Result<(), String> {
+        let event = WorkerEvent::CancelWorkflow { workflow_run_id };
+
+        let text_res = self
+            .client
+            .post(format!("{}/worker_event", &self.base_url))
+            .json(&event)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?
+            .text()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let server_event =
+            serde_json::from_str::<ServerEvent>(&text_res).map_err(|e| e.to_string())?;
+
+        match server_event {
+            ServerEvent::GeneralSuccess { success: true } => Ok(()),
+            ServerEvent::GeneralSuccess { success: false } => Err(format!(
+                "Workflow run {workflow_run_id} has already finished"
+            )),
+            ServerEvent::NotFound => Err(format!("Workflow run {workflow_run_id} not found")),
+            _ => Err("Unexpected response to CancelWorkflow".to_string()),
+        }
+    }
+
+    pub async fn receive_signals(
+        &self,
+        workflow_run_id: WorkflowRunId,
+        started_at: DateTime<Utc>,
+        sequence: i64,
+        signal_name: String,
+        wait: bool,
+    ) -> Result<Option<Vec<String>>, String> {
+        let event = WorkerEvent::ReceiveSignals {
+            workflow_run_id,
+            started_at,
+            sequence,
+            signal_name,
+            wait,
+        };
+
+        let text_res = self
+            .client
+            .post(format!("{}/worker_event", &self.base_url))
+            .json(&event)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?
+            .text()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let server_event =
+            serde_json::from_str::<ServerEvent>(&text_res).map_err(|e| e.to_string())?;
+
+        match server_event {
+            ServerEvent::Signals { payloads } => Ok(Some(payloads)),
+            ServerEvent::Empty => Ok(None),
+            ServerEvent::Cancelled => Err(format!(
+                "Cancelled: workflow run {workflow_run_id} was cancelled"
+            )),
+            ServerEvent::GeneralSuccess { success: false } => Err(format!(
+                "Workflow run {workflow_run_id} is no longer running"
+            )),
+            _ => Err("Unexpected response to ReceiveSignals".to_string()),
+        }
+    }
+
+    pub async fn start_child_workflow(
+        &self,
+        workflow_run_id: WorkflowRunId,
+        started_at: DateTime<Utc>,
+        sequence: i64,
+        name: WorkflowName,
+        input: String,
+        options: WorkflowOptions,
+    ) -> Result<WorkflowRunId, String> {
+        let event = WorkerEvent::StartChildWorkflow {
+            workflow_run_id,
+            started_at,
+            sequence,
+            name: name.clone(),
+            input,
+            options,
+        };
+
+        let text_res = self
+            .client
+            .post(format!("{}/worker_event", &self.base_url))
+            .json(&event)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?
+            .text()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let server_event =
+            serde_json::from_str::<ServerEvent>(&text_res).map_err(|e| e.to_string())?;
+
+        match server_event {
+            ServerEvent::ChildWorkflowStarted { child_run_id } => Ok(child_run_id),
+            ServerEvent::NotFound => Err(format!("Workflow {name} is not registered")),
+            ServerEvent::GeneralSuccess { success: false } => Err(format!(
+                "Workflow run {workflow_run_id} is no longer running"
+            )),
+            _ => Err("Unexpected response to StartChildWorkflow".to_string()),
+        }
+    }
+
+    /// The result of the run's child once it has finished. Until then, the server holds
+    /// the run and `None` tells the worker to let go of it.
+    pub async fn wait_for_child_workflow(
+        &self,
+        workflow_run_id: WorkflowRunId,
+        child_run_id: WorkflowRunId,
+    ) -> Result<Option<PollWorkflowCompletion>, String> {
+        let event = WorkerEvent::WaitForChildWorkflow {
+            workflow_run_id,
+            child_run_id,
+        };
+
+        let text_res = self
+            .client
+            .post(format!("{}/worker_event", &self.base_url))
+            .json(&event)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?
+            .text()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let server_event =
+            serde_json::from_str::<ServerEvent>(&text_res).map_err(|e| e.to_string())?;
+
+        match server_event {
+            ServerEvent::PollWorkflowCompletion(completion) => Ok(Some(completion)),
+            ServerEvent::WaitingForChild => Ok(None),
+            ServerEvent::Cancelled => Err(format!(
+                "Cancelled: workflow run {workflow_run_id} was cancelled"
+            )),
+            ServerEvent::NotFound => Err(format!("Workflow run {workflow_run_id} not found")),
+            ServerEvent::GeneralSuccess { success: false } => Err(format!(
+                "Workflow run {workflow_run_id} is no longer running"
+            )),
+            _ => Err("Unexpected response to WaitForChildWorkflow".to_string()),
+        }
+    }
+
+    pub async fn start_timer(
+        &self,
+        workflow_run_id: WorkflowRunId,
+        started_at: DateTime<Utc>,
+        sequence: i64,
+        fire_at: DateTime<Utc>,
+    ) -> Result<bool, String> {
+        let event = WorkerEvent::StartTimer {
+            workflow_run_id,
+            started_at,
+            sequence,
+            fire_at,
+        };
+
+        let text_res = self
+            .client
+            .post(format!("{}/worker_event", &self.base_url))
+            .json(&event)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?
+            .text()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let server_event =
+            serde_json::from_str::<ServerEvent>(&text_res).map_err(|e| e.to_string())?;
+
+        match server_event {
+            ServerEvent::TimerStarted { sleeping } => Ok(sleeping),
+            ServerEvent::Cancelled => Err(format!(
+                "Cancelled: workflow run {workflow_run_id} was cancelled"
+            )),
+            ServerEvent::GeneralSuccess { success: false } => Err(format!(
+                "Workflow run {workflow_run_id} is no longer running"
+            )),
+            _ => Err("Unexpected response to StartTimer".to_string()),
+        }
+    }
+
+    /// Asks the worker executing the run a question about its current state.
+    pub async fn query_workflow(
+        &self,
+        workflow_run_id: WorkflowRunId,
+        query_name: String,
+        args: String,
+    ) -> Result<String, String> {
+        let event = WorkerEvent::QueryWorkflow {
+            workflow_run_id,
+            query_name,
+            args,
+        };
+
+        let text_res = self
+            .client
+            .post(format!("{}/worker_event", &self.base_url))
+            .json(&event)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?
+            .text()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let server_event =
+            serde_json::from_str::<ServerEvent>(&text_res).map_err(|e| e.to_string())?;
+
+        match server_event {
+            ServerEvent::QueryResult { result, error } if error.is_empty() => Ok(result),
+            ServerEvent::QueryResult { error, .. } => Err(error),
+            ServerEvent::NotFound => Err(format!("Workflow run {workflow_run_id} not found")),
+            _ => Err("Unexpected response to QueryWorkflow".to_string()),
+        }
+    }
+
+    pub async fn poll_workflow_queries(
+        &self,
+        workflow_run_id: WorkflowRunId,
+    ) -> Result<Option<Vec<WorkflowQuery>>, String> {
+        let event = WorkerEvent::PollWorkflowQueries { workflow_run_id };
+
+        let text_res = self
+            .client
+            .post(format!("{}/worker_event", &self.base_url))
+            .json(&event)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?
+            .text()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let server_event =
+            serde_json::from_str::<ServerEvent>(&text_res).map_err(|e| e.to_string())?;
+
+        match server_event {
+            ServerEvent::WorkflowQueries(queries) => Ok(Some(queries)),
+            ServerEvent::Empty => Ok(None),
+            _ => Err("Unexpected response to PollWorkflowQueries".to_string()),
+        }
+    }
+
+    pub async fn answer_workflow_query(
+        &self,
+        query_id: QueryId,
+        result: String,
+        error: String,
+    ) -> Result<(), String> {
+        let event = WorkerEvent::AnswerWorkflowQuery {
+            query_id,
+            result,
+            error,
+        };
+
+        let _res = self
+            .client
+            .post(format!("{}/worker_event", &self.base_url))
+            .json(&event)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        Ok(())
+    }
+
+    /// Returns whether the run has been asked to cancel, or a `NotRunning` error once it
+    /// finished without the worker (e.g. it was terminated).
+    pub async fn record_workflow_heartbeat(
+        &self,
+        workflow_run_id: WorkflowRunId,
+        started_at: DateTime<Utc>,
+    ) -> Result<bool, String> {
+        let event = WorkerEvent::RecordWorkflowHeartbeat {
+            workflow_run_id,
+            started_at,
+        };
+
+        let text_res = self
+            .client
+            .post(format!("{}/worker_event", &self.base_url))
+            .json(&event)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?
+            .text()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let server_event =
+            serde_json::from_str::<ServerEvent>(&text_res).map_err(|e| e.to_string())?;
+
+        match server_event {
+            ServerEvent::Cancelled => Ok(true),
+            ServerEvent::GeneralSuccess { success: false } | ServerEvent::NotFound => Err(format!(
+                "NotRunning: workflow run {workflow_run_id} is no longer running"
+            )),
+            _ => Ok(false),
         }
     }
 
@@ -131,7 +577,7 @@ impl Client {
         &self,
         name: WorkflowName,
     ) -> Result<Option<PollWorkflowResponse>, String> {
-        let event = WorkerEvent::PollWorkflow { name };
+        let event = WorkerEvent::PollWorkflow { name: name.clone() };
 
         let text_res = self
             .client
@@ -149,7 +595,9 @@ impl Client {
 
         match server_event {
             ServerEvent::PollWorkflowResponse(poll_response) => Ok(Some(poll_response)),
-            _ => Ok(None),
+            ServerEvent::Empty => Ok(None),
+            ServerEvent::NotFound => Err(format!("Workflow {name} is not registered")),
+            _ => Err("Unexpected response to PollWorkflow".to_string()),
         }
     }
 
@@ -158,6 +606,7 @@ impl Client {
         workflow_id: WorkflowId,
         workflow_run_id: WorkflowRunId,
         rerun_of_workflow_run_id: Option<WorkflowRunId>,
+        started_at: DateTime<Utc>,
         result: String,
         error: String,
     ) -> Result<String, String> {
@@ -167,6 +616,7 @@ impl Client {
             workflow_id,
             workflow_run_id,
             rerun_of_workflow_run_id,
+            started_at,
         };
 
         let res = self
@@ -205,7 +655,38 @@ impl Client {
 
         match server_event {
             ServerEvent::PollActivityCompletion(poll_response) => Ok(Some(poll_response)),
-            _ => Ok(None),
+            ServerEvent::Empty => Ok(None),
+            _ => Err("Unexpected response to PollActivityCompletion".to_string()),
+        }
+    }
+
+    /// Waits until at least one of `activity_run_ids` has finished and returns the
+    /// completions of all that have. Empty if none finished before the long poll's
+    /// deadline.
+    pub async fn get_activity_completions(
+        &self,
+        activity_run_ids: Vec<ActivityRunId>,
+    ) -> Result<Vec<PollActivityCompletion>, String> {
+        let event = WorkerEvent::GetActivityCompletions { activity_run_ids };
+
+        let text_res = self
+            .client
+            .post(format!("{}/worker_event", &self.base_url))
+            .json(&event)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?
+            .text()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let server_event =
+            serde_json::from_str::<ServerEvent>(&text_res).map_err(|e| e.to_string())?;
+
+        match server_event {
+            ServerEvent::ActivityCompletions(completions) => Ok(completions),
+            ServerEvent::Empty => Ok(vec![]),
+            _ => Err("Unexpected response to GetActivityCompletions".to_string()),
         }
     }
 
@@ -213,7 +694,7 @@ impl Client {
         &self,
         name: ActivityName,
     ) -> Result<Option<PollActivityResponse>, String> {
-        let event = WorkerEvent::PollActivity { name };
+        let event = WorkerEvent::PollActivity { name: name.clone() };
 
         let text_res = self
             .client
@@ -231,7 +712,48 @@ impl Client {
 
         match server_event {
             ServerEvent::PollActivityResponse(poll_response) => Ok(Some(poll_response)),
-            _ => Ok(None),
+            ServerEvent::Empty => Ok(None),
+            ServerEvent::NotFound => Err(format!("Activity {name} is not registered")),
+            _ => Err("Unexpected response to PollActivity".to_string()),
+        }
+    }
+
+    pub async fn record_activity_heartbeat(
+        &self,
+        activity_run_id: ActivityRunId,
+        attempt_number: i64,
+        details: String,
+    ) -> Result<(), String> {
+        let event = WorkerEvent::RecordActivityHeartbeat {
+            activity_run_id,
+            attempt_number,
+            details,
+        };
+
+        let text_res = self
+            .client
+            .post(format!("{}/worker_event", &self.base_url))
+            .json(&event)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?
+            .text()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let server_event =
+            serde_json::from_str::<ServerEvent>(&text_res).map_err(|e| e.to_string())?;
+
+        match server_event {
+            ServerEvent::GeneralSuccess { success: true } => Ok(()),
+            ServerEvent::Cancelled => Err(format!(
+                "Cancelled: the workflow run of activity run {activity_run_id} was cancelled"
+            )),
+            ServerEvent::GeneralSuccess { success: false } => Err(format!(
+                "NotRunning: activity attempt {attempt_number} is no longer running. RunId = {activity_run_id}"
+            )),
+            ServerEvent::NotFound => Err(format!("Activity run {activity_run_id} not found")),
+            _ => Err("Unexpected response to RecordActivityHeartbeat".to_string()),
         }
     }
 
@@ -242,7 +764,6 @@ impl Client {
         workflow_run_id: WorkflowRunId,
         result: String,
         error: String,
-        max_attempts: i64,
         attempt_number: i64,
     ) -> Result<String, String> {
         let event = WorkerEvent::CompleteActivity {
@@ -251,7 +772,6 @@ impl Client {
             activity_id,
             activity_run_id,
             workflow_run_id,
-            max_attempts,
             attempt_number,
         };
 
@@ -1,5 +1,6 @@
 pub mod activity;
 pub mod client;
+pub mod payload;
 /// This event-registry is based on Type-Driven API Design in Rust.
 /// see: https://willcrichton.net/rust-api-type-patterns/registries.html
 /// Only major change is the support of dependency injection via a single Arc.
@@ -7,7 +8,11 @@ pub mod worker;
 pub mod worker_events;
 pub mod workflow;
 
-pub use activity::AbstractActivityHandler;
+pub use activity::{AbstractActivityHandler, ActivityContext, ActivityHandler, HandlerError};
 pub use client::Client;
+pub use payload::ErrorKind;
 pub use worker::Worker;
-pub use workflow::{AbstractWorkflowHandler, ActivityOptions, RetryOptions, WorkflowContext};
+pub use workflow::{
+    AbstractWorkflowHandler, ActivityHandle, ActivityOptions, RetryOptions, WorkflowContext,
+    WorkflowHandler, WorkflowOptions,
+};
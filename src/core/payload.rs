@@ -0,0 +1,51 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::core::activity::error_kind;
+
+/// Error kind of inputs, outputs and errors of typed handlers that don't (de)serialize.
+/// Never retried, since another attempt would get the same input.
+pub const SERIALIZATION_ERROR: &str = "SerializationError";
+
+/// The kind of a handler error, which retry policies match against (see
+/// `RetryOptions::non_retryable_error_kinds`). It must not contain a `:`.
+pub trait ErrorKind {
+    fn kind(&self) -> &str;
+}
+
+/// `"Kind: msg"` strings, whose kind is the text before the first `:`.
+impl ErrorKind for String {
+    fn kind(&self) -> &str {
+        error_kind(self)
+    }
+}
+
+/// Encodes a typed input or output as a payload, which is always JSON.
+pub fn to_payload<T: Serialize>(value: &T) -> Result<String, serde_json::Error> {
+    serde_json::to_string(value)
+}
+
+/// Decodes a payload written by `to_payload`.
+pub fn from_payload<T: DeserializeOwned>(payload: &str) -> Result<T, serde_json::Error> {
+    serde_json::from_str(payload)
+}
+
+/// Encodes a typed handler error so the server and retry policies can tell its kind
+/// without knowing its type. Errors that serialize to a string starting with their kind,
+/// like `"Kind: msg"` `String` ones, travel as that string; others as `"{kind}: {json}"`.
+pub fn to_error_payload<E: Serialize + ErrorKind>(error: &E) -> Result<String, serde_json::Error> {
+    match serde_json::to_value(error)? {
+        Value::String(message) if error_kind(&message) == error.kind() => Ok(message),
+        json => Ok(format!("{}: {json}", error.kind())),
+    }
+}
+
+/// Decodes an error written by `to_error_payload`.
+pub fn from_error_payload<E: DeserializeOwned>(error: &str) -> Result<E, serde_json::Error> {
+    if let Ok(error) = serde_json::from_value(Value::String(error.to_string())) {
+        return Ok(error);
+    }
+    let json = error.split_once(':').map_or(error, |(_, json)| json);
+    from_payload(json.trim_start())
+}
@@ -1,13 +1,32 @@
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
 use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::Duration;
+use tokio::sync::{watch, Notify, RwLock};
 
 use crate::core::{
-    activity::{AbstractActivityHandler, ActivityName},
+    activity::{
+        error_kind, AbstractActivityHandler, ActivityContext, ActivityEventType, ActivityName,
+        ActivityRunId, CancellationToken, HandlerError,
+    },
     client::Client,
-    workflow::{AbstractWorkflowHandler, ActivityOptions, WorkflowContext, WorkflowName},
+    payload::SERIALIZATION_ERROR,
+    worker_events::{PollWorkflowCompletion, RunUpdate},
+    workflow::{
+        workflow_outcome, AbstractWorkflowHandler, ActivityOptions, QueryHandlers, WorkflowContext,
+        WorkflowEventType, WorkflowHandler, WorkflowName, WorkflowOptions, WorkflowRunId,
+    },
 };
 
+/// How often a worker tells the server it is still executing a workflow run. The server
+/// re-delivers runs whose worker has gone quiet for longer than its workflow task timeout.
+pub const WORKFLOW_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Polls wait on the server until there is work, so a failed poll is retried after a
+/// pause instead of immediately.
+const POLL_ERROR_BACKOFF: Duration = Duration::from_secs(1);
+
 #[derive(Clone)]
 pub struct Worker {
     pub workflow_handlers: Arc<RwLock<HashMap<WorkflowName, Box<dyn AbstractWorkflowHandler>>>>,
@@ -50,19 +69,38 @@ impl Worker {
 
     pub async fn poll_and_process_workflow(&self, name: WorkflowName) -> Result<String, String> {
         if let Some(poll_res) = self.client.poll_workflow(name.clone()).await? {
+            let query_handlers = QueryHandlers::default();
+            let release = Arc::new(Notify::new());
+            let (cancel_requested, cancel_requested_rx) = watch::channel(poll_res.cancel_requested);
             let context = WorkflowContext {
                 run_id: poll_res.workflow_run_id,
+                started_at: poll_res.started_at,
                 activity_handlers: self.activity_handlers.clone(),
                 client: self.client.clone(),
                 event_count_order: 0,
+                history: poll_res.history,
                 activity_options: ActivityOptions {
                     ..Default::default()
                 },
+                query_handlers: query_handlers.clone(),
+                release: release.clone(),
+                cancel_requested: cancel_requested_rx,
             };
 
             if let Some(workflow_handler) = self.workflow_handlers.read().await.get(&poll_res.name)
             {
-                let workflow_handler_result = workflow_handler.run(context, poll_res.input).await;
+                let workflow_handler_result = tokio::select! {
+                    result = workflow_handler.run(context, poll_res.input) => result,
+                    _ = release.notified() => {
+                        println!("Workflow is waiting on the server, releasing it. RunId = {}", poll_res.workflow_run_id);
+                        return Ok("released".to_string());
+                    }
+                    _ = self.keep_workflow_alive(poll_res.workflow_run_id, poll_res.started_at, cancel_requested) => {
+                        println!("Workflow is no longer running, dropping it. RunId = {}", poll_res.workflow_run_id);
+                        return Ok("dropped".to_string());
+                    }
+                    _ = self.answer_queries(poll_res.workflow_run_id, query_handlers) => unreachable!(),
+                };
 
                 match workflow_handler_result {
                     Ok(workflow_handler_result) => {
@@ -72,6 +110,7 @@ impl Worker {
                                 poll_res.workflow_id,
                                 poll_res.workflow_run_id,
                                 poll_res.rerun_of_workflow_run_id,
+                                poll_res.started_at,
                                 workflow_handler_result,
                                 "".to_string(),
                             )
@@ -84,6 +123,7 @@ impl Worker {
                                 poll_res.workflow_id,
                                 poll_res.workflow_run_id,
                                 poll_res.rerun_of_workflow_run_id,
+                                poll_res.started_at,
                                 "".to_string(),
                                 workflow_handler_result,
                             )
@@ -96,11 +136,132 @@ impl Worker {
         Ok("done".to_string())
     }
 
+    /// Heartbeats the run and passes on a cancellation request from the server. Returns
+    /// once the run went on without this worker, e.g. because it was terminated or handed
+    /// to another worker.
+    async fn keep_workflow_alive(
+        &self,
+        workflow_run_id: WorkflowRunId,
+        started_at: DateTime<Utc>,
+        cancel_requested: watch::Sender<bool>,
+    ) {
+        loop {
+            tokio::time::sleep(WORKFLOW_HEARTBEAT_INTERVAL).await;
+            match self
+                .client
+                .record_workflow_heartbeat(workflow_run_id, started_at)
+                .await
+            {
+                Ok(true) => {
+                    cancel_requested.send_replace(true);
+                }
+                Err(e) if error_kind(&e) == "NotRunning" => return,
+                _ => {}
+            }
+        }
+    }
+
+    async fn answer_queries(&self, workflow_run_id: WorkflowRunId, query_handlers: QueryHandlers) {
+        loop {
+            let queries = match self.client.poll_workflow_queries(workflow_run_id).await {
+                Ok(queries) => queries.unwrap_or_default(),
+                Err(e) => {
+                    println!("Polling queries failed: {e}");
+                    tokio::time::sleep(POLL_ERROR_BACKOFF).await;
+                    continue;
+                }
+            };
+
+            for query in queries {
+                let answer = match query_handlers
+                    .read()
+                    .expect("query handlers poisoned")
+                    .get(&query.query_name)
+                {
+                    Some(handler) => handler(query.args),
+                    None => Err(format!(
+                        "NotFound: the workflow has no query named {}",
+                        query.query_name
+                    )),
+                };
+                let (result, error) = match answer {
+                    Ok(result) => (result, "".to_string()),
+                    Err(error) => ("".to_string(), error),
+                };
+                let _ = self
+                    .client
+                    .answer_workflow_query(query.query_id, result, error)
+                    .await;
+            }
+        }
+    }
+
+    /// Fires the attempt's cancellation token once the server records anything else for
+    /// its run, e.g. that the attempt timed out or the workflow run was cancelled.
+    async fn watch_attempt(
+        &self,
+        activity_run_id: ActivityRunId,
+        attempt_number: i64,
+        cancellation: CancellationToken,
+    ) {
+        // Events recorded through another server sharing the store aren't streamed by
+        // ours, but the run finishing still shows up in the store.
+        tokio::select! {
+            _ = self.watch_attempt_events(activity_run_id, attempt_number) => {}
+            _ = self.wait_for_activity_run(activity_run_id) => {}
+        }
+        println!("Activity attempt is no longer running, cancelling it. RunId = {activity_run_id}");
+        cancellation.cancel();
+        std::future::pending().await
+    }
+
+    /// Returns once the server streams an event that ends the attempt.
+    async fn watch_attempt_events(&self, activity_run_id: ActivityRunId, attempt_number: i64) {
+        loop {
+            if let Ok(updates) = self.client.watch_runs(&[], &[activity_run_id]).await {
+                tokio::pin!(updates);
+                while let Some(Ok(update)) = updates.next().await {
+                    let RunUpdate::Activity(event) = update else {
+                        continue;
+                    };
+                    if event.event_type != ActivityEventType::Started
+                        || event.attempt_number != attempt_number
+                    {
+                        return;
+                    }
+                }
+            }
+            tokio::time::sleep(POLL_ERROR_BACKOFF).await;
+        }
+    }
+
+    /// Returns once the activity run has finished.
+    async fn wait_for_activity_run(&self, activity_run_id: ActivityRunId) {
+        loop {
+            match self.client.poll_activity_completion(activity_run_id).await {
+                Ok(Some(_)) => return,
+                Ok(None) => {}
+                Err(_) => tokio::time::sleep(POLL_ERROR_BACKOFF).await,
+            }
+        }
+    }
+
     pub async fn poll_and_process_activity(&self, name: ActivityName) -> Result<String, String> {
         if let Some(poll_res) = self.client.poll_activity(name.clone()).await? {
             if let Some(activity_handler) = self.activity_handlers.read().await.get(&poll_res.name)
             {
-                let activity_handler_result = activity_handler.run(poll_res.input).await;
+                let cancellation = CancellationToken::new();
+                let context = ActivityContext {
+                    activity_run_id: poll_res.activity_run_id,
+                    attempt_number: poll_res.attempt_number,
+                    heartbeat_details: poll_res.heartbeat_details,
+                    client: self.client.clone(),
+                    cancellation: cancellation.clone(),
+                };
+                let activity_handler_result = tokio::select! {
+                    result = activity_handler.run(context, poll_res.input) => result,
+                    _ = self.watch_attempt(poll_res.activity_run_id, poll_res.attempt_number, cancellation) => unreachable!(),
+                };
                 match activity_handler_result {
                     Ok(result) => {
                         let _ = self
@@ -111,7 +272,6 @@ impl Worker {
                                 poll_res.workflow_run_id,
                                 result,
                                 "".to_string(),
-                                poll_res.max_attempts,
                                 poll_res.attempt_number,
                             )
                             .await;
@@ -125,7 +285,6 @@ impl Worker {
                                 poll_res.workflow_run_id,
                                 "".to_string(),
                                 err,
-                                poll_res.max_attempts,
                                 poll_res.attempt_number,
                             )
                             .await;
@@ -144,7 +303,10 @@ impl Worker {
                 let worker = self.clone();
                 tokio::task::spawn(async move {
                     loop {
-                        let _ = worker.poll_and_process_workflow(wf_name.clone()).await;
+                        if let Err(e) = worker.poll_and_process_workflow(wf_name.clone()).await {
+                            println!("Polling workflow {wf_name} failed: {e}");
+                            tokio::time::sleep(POLL_ERROR_BACKOFF).await;
+                        }
                     }
                 });
             }
@@ -158,7 +320,10 @@ impl Worker {
                 let worker = self.clone();
                 tokio::task::spawn(async move {
                     loop {
-                        let _ = worker.poll_and_process_activity(act_name.clone()).await;
+                        if let Err(e) = worker.poll_and_process_activity(act_name.clone()).await {
+                            println!("Polling activity {act_name} failed: {e}");
+                            tokio::time::sleep(POLL_ERROR_BACKOFF).await;
+                        }
                     }
                 });
             }
@@ -181,6 +346,7 @@ impl Worker {
         &mut self,
         workflow: W,
         input: String,
+        options: WorkflowOptions,
     ) -> Result<String, String>
     where
         W: AbstractWorkflowHandler + 'static,
@@ -188,15 +354,48 @@ impl Worker {
         let name = WorkflowName::from(&workflow);
         println!("Executing Workflow: {name}");
 
-        let run_id = self.client.execute_workflow(name, input).await?;
+        let run_id = self.client.execute_workflow(name, input, options).await?;
+        let res = self.workflow_completion(run_id).await?;
+        match res.status {
+            WorkflowEventType::Succeeeded => Ok(res.result),
+            _ => Err(res.error),
+        }
+    }
+
+    /// Like `execute_workflow`, with the workflow's own input, output and error types.
+    pub async fn execute_typed_workflow<W>(
+        &mut self,
+        workflow: W,
+        input: W::Input,
+        options: WorkflowOptions,
+    ) -> Result<W::Output, HandlerError<W::Error>>
+    where
+        W: WorkflowHandler + 'static,
+    {
+        println!("Executing Workflow: {}", WorkflowName::from(&workflow));
+        let run_id = self
+            .client
+            .execute_typed_workflow(&workflow, &input, options)
+            .await
+            .map_err(|e| match error_kind(&e) {
+                SERIALIZATION_ERROR => HandlerError::Serialization(e),
+                _ => HandlerError::Other(e),
+            })?;
+        let completion = self
+            .workflow_completion(run_id)
+            .await
+            .map_err(HandlerError::Other)?;
+        workflow_outcome(&completion)
+    }
+
+    async fn workflow_completion(
+        &self,
+        run_id: WorkflowRunId,
+    ) -> Result<PollWorkflowCompletion, String> {
         loop {
             if let Some(res) = self.client.poll_workflow_completion(run_id).await? {
-                if !res.result.is_empty() {
-                    return Ok(res.result);
-                } else if !res.error.is_empty() {
-                    return Err(res.error);
-                }
-            };
+                return Ok(res);
+            }
         }
     }
 }
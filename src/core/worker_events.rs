@@ -1,8 +1,12 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
 use crate::core::{
-    activity::{ActivityId, ActivityName, ActivityRunId},
-    workflow::{WorkflowId, WorkflowName, WorkflowRunId},
+    activity::{ActivityEvent, ActivityId, ActivityName, ActivityRunId},
+    workflow::{
+        ActivityOptions, QueryId, WorkflowCommand, WorkflowEvent, WorkflowEventType, WorkflowId,
+        WorkflowName, WorkflowOptions, WorkflowRunId,
+    },
 };
 
 #[derive(Serialize, Deserialize)]
@@ -17,13 +21,17 @@ pub enum WorkerEvent {
         name: WorkflowName,
         input: String,
         workflow_run_id: WorkflowRunId,
+        options: WorkflowOptions,
     },
     EnqueuActivity {
         name: ActivityName,
         input: String,
         activity_run_id: ActivityRunId,
         workflow_run_id: WorkflowRunId,
-        max_attempts: i64,
+        /// The delivery issuing the command, see `PollWorkflowResponse::started_at`.
+        started_at: DateTime<Utc>,
+        sequence: i64,
+        options: ActivityOptions,
     },
     CompleteWorkflow {
         result: String,
@@ -31,6 +39,8 @@ pub enum WorkerEvent {
         workflow_id: WorkflowId,
         workflow_run_id: WorkflowRunId,
         rerun_of_workflow_run_id: Option<WorkflowRunId>,
+        /// The delivery being completed, see `PollWorkflowResponse::started_at`.
+        started_at: DateTime<Utc>,
     },
     PollWorkflow {
         name: WorkflowName,
@@ -44,7 +54,6 @@ pub enum WorkerEvent {
         activity_id: ActivityId,
         activity_run_id: ActivityRunId,
         workflow_run_id: WorkflowRunId,
-        max_attempts: i64,
         attempt_number: i64,
     },
     PollActivity {
@@ -53,6 +62,71 @@ pub enum WorkerEvent {
     PollActivityCompletion {
         activity_run_id: ActivityRunId,
     },
+    /// Waits until at least one of the runs has finished, then returns all that have.
+    GetActivityCompletions {
+        activity_run_ids: Vec<ActivityRunId>,
+    },
+    RecordWorkflowHeartbeat {
+        workflow_run_id: WorkflowRunId,
+        started_at: DateTime<Utc>,
+    },
+    RecordActivityHeartbeat {
+        activity_run_id: ActivityRunId,
+        attempt_number: i64,
+        details: String,
+    },
+    SignalWorkflow {
+        workflow_run_id: WorkflowRunId,
+        signal_name: String,
+        payload: String,
+    },
+    CancelWorkflow {
+        workflow_run_id: WorkflowRunId,
+    },
+    /// Takes the next signals named `signal_name` for the command at `sequence`. With
+    /// `wait`, exactly one, waiting for it to arrive; otherwise all that are there.
+    ReceiveSignals {
+        workflow_run_id: WorkflowRunId,
+        started_at: DateTime<Utc>,
+        sequence: i64,
+        signal_name: String,
+        wait: bool,
+    },
+    /// Started by the workflow run `workflow_run_id` at `sequence`.
+    StartChildWorkflow {
+        workflow_run_id: WorkflowRunId,
+        started_at: DateTime<Utc>,
+        sequence: i64,
+        name: WorkflowName,
+        input: String,
+        options: WorkflowOptions,
+    },
+    StartTimer {
+        workflow_run_id: WorkflowRunId,
+        started_at: DateTime<Utc>,
+        sequence: i64,
+        fire_at: DateTime<Utc>,
+    },
+    /// Sent by the worker executing `workflow_run_id` for the result of its child run.
+    /// Unless the child has finished, the server holds the run until it does.
+    WaitForChildWorkflow {
+        workflow_run_id: WorkflowRunId,
+        child_run_id: WorkflowRunId,
+    },
+    QueryWorkflow {
+        workflow_run_id: WorkflowRunId,
+        query_name: String,
+        args: String,
+    },
+    /// Sent by the worker executing the run, to pick up queries about it.
+    PollWorkflowQueries {
+        workflow_run_id: WorkflowRunId,
+    },
+    AnswerWorkflowQuery {
+        query_id: QueryId,
+        result: String,
+        error: String,
+    },
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -62,6 +136,12 @@ pub struct PollWorkflowResponse {
     pub workflow_id: WorkflowId,
     pub name: WorkflowName,
     pub input: String,
+    pub history: Vec<WorkflowCommand>,
+    #[serde(default)]
+    pub cancel_requested: bool,
+    /// When this delivery of the run started. Commands, completions and heartbeats carry
+    /// it, so the server can tell a worker whose run was since handed to another one.
+    pub started_at: DateTime<Utc>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -73,20 +153,31 @@ pub struct PollActivityResponse {
     pub input: String,
     pub max_attempts: i64,
     pub attempt_number: i64,
+    pub heartbeat_details: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct PollWorkflowCompletion {
     pub workflow_run_id: WorkflowRunId,
+    pub status: WorkflowEventType,
     pub result: String,
     pub error: String,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WorkflowQuery {
+    pub query_id: QueryId,
+    pub query_name: String,
+    pub args: String,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct PollActivityCompletion {
     pub activity_run_id: ActivityRunId,
     pub result: String,
     pub error: String,
+    /// When the run's final event was recorded.
+    pub completed_at: DateTime<Utc>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -95,6 +186,42 @@ pub enum ServerEvent {
     PollActivityResponse(PollActivityResponse),
     PollWorkflowCompletion(PollWorkflowCompletion),
     PollActivityCompletion(PollActivityCompletion),
-    GeneralSuccess { success: bool },
+    ActivityCompletions(Vec<PollActivityCompletion>),
+    GeneralSuccess {
+        success: bool,
+    },
     NotFound,
+    Signals {
+        payloads: Vec<String>,
+    },
+    WorkflowQueries(Vec<WorkflowQuery>),
+    ChildWorkflowStarted {
+        child_run_id: WorkflowRunId,
+    },
+    /// Without `sleeping`, the timer was already due and the workflow carries on.
+    TimerStarted {
+        sleeping: bool,
+    },
+    /// The run waits for its child on the server, so the worker lets go of it.
+    WaitingForChild,
+    QueryResult {
+        result: String,
+        error: String,
+    },
+    /// Nothing happened before the long poll's deadline; poll again.
+    Empty,
+    /// The request can't be carried out as asked, e.g. because of invalid options.
+    Rejected {
+        error: String,
+    },
+    /// The run (or the workflow run an activity belongs to) was asked to cancel, so
+    /// there is nothing left to wait for.
+    Cancelled,
+}
+
+/// An event recorded for a watched run, as streamed by the server's `/watch` endpoint.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum RunUpdate {
+    Workflow(WorkflowEvent),
+    Activity(ActivityEvent),
 }
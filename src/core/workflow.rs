@@ -1,13 +1,23 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, TimeDelta, Utc};
+use rand::Rng;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fmt;
+use std::str::FromStr;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::Duration;
+use tokio::sync::{watch, Notify, RwLock};
 use uuid::Uuid;
 
-use crate::core::activity::{AbstractActivityHandler, ActivityName};
+use crate::core::activity::{
+    error_kind, AbstractActivityHandler, ActivityHandler, ActivityName, ActivityRunId, HandlerError,
+};
 use crate::core::client::Client;
+use crate::core::payload::{
+    from_payload, to_error_payload, to_payload, ErrorKind, SERIALIZATION_ERROR,
+};
+use crate::core::worker_events::{PollActivityCompletion, PollWorkflowCompletion};
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
 #[serde(transparent)]
@@ -66,83 +76,843 @@ impl fmt::Display for WorkflowRunId {
         write!(f, "{}", self.0)
     }
 }
+impl FromStr for WorkflowRunId {
+    type Err = String;
 
-#[derive(Debug, Clone)]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Uuid::parse_str(s)
+            .map(WorkflowRunId)
+            .map_err(|e| format!("Invalid run id {s}: {e}"))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(transparent)]
+pub struct QueryId(Uuid);
+impl Default for QueryId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl QueryId {
+    pub fn new() -> Self {
+        QueryId(Uuid::new_v4())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Workflow {
     pub id: WorkflowId,
     pub name: WorkflowName,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum WorkflowEventType {
     Pending,
     Started,
     Succeeeded,
     Failed,
+    TimedOut,
+    /// The run is sleeping and no worker holds it until the timer fires.
+    TimerStarted,
+    /// Always followed by `Pending`, which hands the run to a worker again.
+    TimerFired,
+    /// The run is waiting for its child run `child_run_id` to finish, and no worker holds
+    /// it until then.
+    WaitingForChild,
+    /// Stopped by the server without asking the workflow, e.g. when its parent closed.
+    Terminated,
+    /// Someone asked the run to stop. Always followed by the event that (again) hands
+    /// the run to a worker, so the workflow can see the request and clean up.
+    CancelRequested,
+    /// The workflow failed after cancellation was requested.
+    Cancelled,
+}
+
+impl WorkflowEventType {
+    pub fn is_finished(&self) -> bool {
+        !matches!(
+            self,
+            WorkflowEventType::Pending
+                | WorkflowEventType::Started
+                | WorkflowEventType::TimerStarted
+                | WorkflowEventType::TimerFired
+                | WorkflowEventType::WaitingForChild
+                | WorkflowEventType::CancelRequested
+        )
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct WorkflowEvent {
     pub workflow_id: WorkflowId,
     pub run_id: WorkflowRunId,
     pub event_type: WorkflowEventType,
     pub payload: String,
     pub rerun_of: Option<WorkflowRunId>,
+    /// Sequence number from which a rerun stops reusing results of `rerun_of`.
+    pub rerun_checkpoint: Option<i64>,
+    pub created_at: DateTime<Utc>,
+    pub options: WorkflowOptions,
+    /// When the timer of a `TimerStarted` event fires.
+    #[serde(default)]
+    pub fire_at: Option<DateTime<Utc>>,
+    /// The run that started this one as a child workflow.
+    #[serde(default)]
+    pub parent_run_id: Option<WorkflowRunId>,
+    /// The child run a `WaitingForChild` event waits for.
+    #[serde(default)]
+    pub child_run_id: Option<WorkflowRunId>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct WorkflowOptions {
+    /// Maximum time from enqueueing the run until it completes, including time spent
+    /// waiting for a worker to pick it up.
+    pub execution_timeout: Option<Duration>,
+    /// Maximum time from a worker starting the run until it completes.
+    pub run_timeout: Option<Duration>,
+    /// What happens to a child workflow run when its parent finishes first.
+    #[serde(default)]
+    pub parent_close_policy: ParentClosePolicy,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub enum ParentClosePolicy {
+    #[default]
+    Terminate,
+    /// Let the child run to completion on its own.
+    Abandon,
+    /// Ask the child to cancel, leaving it time to clean up.
+    RequestCancel,
+}
+
+/// Something a workflow asked the server to do, keyed by its position in the run
+/// (`WorkflowContext::event_count_order`). Replaying a run walks these in order.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WorkflowCommand {
+    pub workflow_run_id: WorkflowRunId,
+    pub sequence: i64,
+    pub command_type: WorkflowCommandType,
     pub created_at: DateTime<Utc>,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum WorkflowCommandType {
+    ScheduleActivity {
+        name: ActivityName,
+        input: String,
+        activity_run_id: ActivityRunId,
+    },
+    /// The workflow took these signals, the next ones of that name in arrival order.
+    ReceiveSignals {
+        signal_name: String,
+        payloads: Vec<String>,
+        /// Copied from the run this one reruns rather than taken from its own signals.
+        #[serde(default)]
+        memoized: bool,
+    },
+    StartTimer {
+        fire_at: DateTime<Utc>,
+    },
+    StartChildWorkflow {
+        name: WorkflowName,
+        input: String,
+        child_run_id: WorkflowRunId,
+    },
+}
+
+impl WorkflowCommandType {
+    /// Whether two commands ask for the same thing, ignoring the ids assigned to them.
+    pub fn is_same_command(&self, other: &Self) -> bool {
+        match (self, other) {
+            (
+                WorkflowCommandType::ScheduleActivity { name, input, .. },
+                WorkflowCommandType::ScheduleActivity {
+                    name: other_name,
+                    input: other_input,
+                    ..
+                },
+            ) => name == other_name && input == other_input,
+            (
+                WorkflowCommandType::ReceiveSignals { signal_name, .. },
+                WorkflowCommandType::ReceiveSignals {
+                    signal_name: other_signal_name,
+                    ..
+                },
+            ) => signal_name == other_signal_name,
+            // Sleeps are usually relative to when they start, so only the order counts.
+            (WorkflowCommandType::StartTimer { .. }, WorkflowCommandType::StartTimer { .. }) => {
+                true
+            }
+            (
+                WorkflowCommandType::StartChildWorkflow { name, input, .. },
+                WorkflowCommandType::StartChildWorkflow {
+                    name: other_name,
+                    input: other_input,
+                    ..
+                },
+            ) => name == other_name && input == other_input,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for WorkflowCommandType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WorkflowCommandType::ScheduleActivity { name, input, .. } => {
+                write!(f, "{name}({input})")
+            }
+            WorkflowCommandType::ReceiveSignals { signal_name, .. } => {
+                write!(f, "signal {signal_name}")
+            }
+            WorkflowCommandType::StartTimer { fire_at } => write!(f, "timer until {fire_at}"),
+            WorkflowCommandType::StartChildWorkflow { name, input, .. } => {
+                write!(f, "child workflow {name}({input})")
+            }
+        }
+    }
+}
+
+/// A message sent to a workflow run from outside, e.g. an approval.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WorkflowSignal {
+    pub workflow_run_id: WorkflowRunId,
+    pub signal_name: String,
+    pub payload: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Answers a query about a running workflow from its arguments. Handlers read state the
+/// workflow shares with them (e.g. through an `Arc<Mutex<_>>`) and must not block.
+pub type QueryHandler = Box<dyn Fn(String) -> Result<String, String> + Send + Sync>;
+
+pub type QueryHandlers = Arc<std::sync::RwLock<HashMap<String, QueryHandler>>>;
+
+/// An activity scheduled by `WorkflowContext::start_typed_activity`, whose output is an
+/// `O` and whose handler fails with an `E`. Those scheduled by `start_activity` report
+/// their output and error as they were sent.
+pub struct ActivityHandle<O, E> {
+    pub activity_run_id: ActivityRunId,
+    client: Client,
+    decode: fn(&PollActivityCompletion) -> Result<O, HandlerError<E>>,
+}
+
+impl<O, E> Clone for ActivityHandle<O, E> {
+    fn clone(&self) -> Self {
+        ActivityHandle {
+            activity_run_id: self.activity_run_id,
+            client: self.client.clone(),
+            decode: self.decode,
+        }
+    }
+}
+
+impl<O, E> ActivityHandle<O, E> {
+    /// Waits for the activity to finish.
+    pub async fn result(&self) -> Result<O, HandlerError<E>> {
+        let completion = self.completion().await.map_err(HandlerError::Other)?;
+        (self.decode)(&completion)
+    }
+
+    async fn completion(&self) -> Result<PollActivityCompletion, String> {
+        loop {
+            if let Some(completion) = self
+                .client
+                .poll_activity_completion(self.activity_run_id)
+                .await?
+            {
+                return Ok(completion);
+            }
+        }
+    }
+
+    /// Waits for all of `handles`, returning their results in the same order.
+    pub async fn join_all(handles: &[ActivityHandle<O, E>]) -> Vec<Result<O, HandlerError<E>>> {
+        let mut results: Vec<Option<Result<O, HandlerError<E>>>> =
+            handles.iter().map(|_| None).collect();
+        loop {
+            let waiting: Vec<ActivityRunId> = handles
+                .iter()
+                .zip(&results)
+                .filter(|(_, result)| result.is_none())
+                .map(|(handle, _)| handle.activity_run_id)
+                .collect();
+            if waiting.is_empty() {
+                break;
+            }
+
+            match handles[0].client.get_activity_completions(waiting).await {
+                Ok(completions) => {
+                    for completion in completions {
+                        for (handle, result) in handles.iter().zip(&mut results) {
+                            if handle.activity_run_id == completion.activity_run_id {
+                                *result = Some((handle.decode)(&completion));
+                            }
+                        }
+                    }
+                }
+                Err(e) => {
+                    for result in results.iter_mut().filter(|result| result.is_none()) {
+                        *result = Some(Err(HandlerError::Other(e.clone())));
+                    }
+                }
+            }
+        }
+        results.into_iter().flatten().collect()
+    }
+
+    /// Waits for the first of `handles` to finish and returns its index and result.
+    /// "First" means first recorded by the server, not first noticed here, so a replay
+    /// that finds several of them already finished picks the same one.
+    pub async fn select(
+        handles: &[ActivityHandle<O, E>],
+    ) -> Result<(usize, Result<O, HandlerError<E>>), String> {
+        if handles.is_empty() {
+            return Err("InvalidInput: select needs at least one activity".to_string());
+        }
+        let client = &handles[0].client;
+        let activity_run_ids: Vec<ActivityRunId> = handles
+            .iter()
+            .map(|handle| handle.activity_run_id)
+            .collect();
+
+        loop {
+            let first = client
+                .get_activity_completions(activity_run_ids.clone())
+                .await?
+                .into_iter()
+                .min_by_key(|completion| completion.completed_at);
+            if let Some(first) = first {
+                let index = activity_run_ids
+                    .iter()
+                    .position(|&id| id == first.activity_run_id)
+                    .expect("completions are for the selected runs");
+                return Ok((index, (handles[index].decode)(&first)));
+            }
+        }
+    }
+}
+
+/// The output or error of a finished typed workflow run.
+pub(crate) fn workflow_outcome<O: DeserializeOwned, E: DeserializeOwned>(
+    completion: &PollWorkflowCompletion,
+) -> Result<O, HandlerError<E>> {
+    match completion.status {
+        WorkflowEventType::Succeeeded => from_payload(&completion.result).map_err(|e| {
+            HandlerError::Serialization(format!(
+                "{SERIALIZATION_ERROR}: invalid workflow output: {e}"
+            ))
+        }),
+        _ => Err(HandlerError::from_payload(completion.error.clone())),
+    }
+}
+
+/// The raw output or error of an activity, which doesn't have to be JSON.
+fn raw_outcome(completion: &PollActivityCompletion) -> Result<String, HandlerError<String>> {
+    if !completion.error.is_empty() {
+        return Err(HandlerError::Other(completion.error.clone()));
+    }
+    Ok(completion.result.clone())
+}
+
+fn outcome<O: DeserializeOwned, E: DeserializeOwned>(
+    completion: &PollActivityCompletion,
+) -> Result<O, HandlerError<E>> {
+    if !completion.error.is_empty() {
+        return Err(HandlerError::from_payload(completion.error.clone()));
+    }
+    from_payload(&completion.result).map_err(|e| {
+        HandlerError::Serialization(format!(
+            "{SERIALIZATION_ERROR}: invalid activity output: {e}"
+        ))
+    })
+}
+
 pub struct WorkflowContext {
     pub run_id: WorkflowRunId,
+    /// When this delivery of the run started; sent along with every command.
+    pub started_at: DateTime<Utc>,
     pub event_count_order: i64,
+    /// Commands already issued by earlier deliveries of this run.
+    pub history: Vec<WorkflowCommand>,
     pub activity_handlers: Arc<RwLock<HashMap<ActivityName, Box<dyn AbstractActivityHandler>>>>,
     pub client: Client,
     pub activity_options: ActivityOptions,
+    /// Answered by the worker while this run executes.
+    pub query_handlers: QueryHandlers,
+    /// Notified when the run starts waiting on a timer, so the worker drops it until
+    /// the timer fires.
+    pub release: Arc<Notify>,
+    pub cancel_requested: watch::Receiver<bool>,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct RetryOptions {
     pub max_attempts: i64,
+    /// Delay before the first retry.
+    pub initial_interval: Duration,
+    /// Multiplier applied to the delay after every failed attempt.
+    pub backoff_coefficient: f64,
+    /// Upper bound on the delay between attempts.
+    pub maximum_interval: Duration,
+    /// Fraction of the delay to randomly add or remove (0.0 - 1.0), so retries of
+    /// many failed activities don't all land on the downstream at once.
+    pub jitter: f64,
+    /// Error kinds (see [`error_kind`]) that fail the activity without retrying, on top
+    /// of `SerializationError`.
+    pub non_retryable_error_kinds: Vec<String>,
 }
 
 impl Default for RetryOptions {
     fn default() -> Self {
-        Self { max_attempts: 1 }
+        Self {
+            max_attempts: 1,
+            initial_interval: Duration::from_secs(1),
+            backoff_coefficient: 2.0,
+            maximum_interval: Duration::from_secs(100),
+            jitter: 0.0,
+            non_retryable_error_kinds: vec![],
+        }
     }
 }
 
-#[derive(Default)]
+impl RetryOptions {
+    pub fn is_retryable(&self, error: &str) -> bool {
+        let kind = error_kind(error);
+        kind != SERIALIZATION_ERROR && !self.non_retryable_error_kinds.iter().any(|k| k == kind)
+    }
+
+    /// Rejects options the backoff can't be computed from.
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, value) in [
+            ("backoff_coefficient", self.backoff_coefficient),
+            ("jitter", self.jitter),
+        ] {
+            if !value.is_finite() || value < 0.0 {
+                return Err(format!(
+                    "InvalidInput: retry option {name} must be finite and non-negative, got {value}"
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Delay to wait after `attempt_number` failed before scheduling the next attempt.
+    pub fn next_retry_delay(&self, attempt_number: i64) -> Duration {
+        let exponent = i32::try_from(attempt_number - 1).unwrap_or(i32::MAX).max(0);
+        let backoff = self.initial_interval.as_secs_f64() * self.backoff_coefficient.powi(exponent);
+        let capped = backoff.min(self.maximum_interval.as_secs_f64());
+
+        // Options recorded before they were validated may still hold a NaN.
+        let jitter = if self.jitter.is_nan() {
+            0.0
+        } else {
+            self.jitter.clamp(0.0, 1.0)
+        };
+        let jittered = capped * rand::rng().random_range(1.0 - jitter..=1.0 + jitter);
+
+        Duration::try_from_secs_f64(jittered.clamp(0.0, self.maximum_interval.as_secs_f64()))
+            .unwrap_or(self.maximum_interval)
+    }
+
+    /// When to schedule the next attempt after `attempt_number` failed at `failed_at`.
+    /// A delay too long to represent retries at the end of time rather than right away.
+    pub fn next_retry_at(&self, failed_at: DateTime<Utc>, attempt_number: i64) -> DateTime<Utc> {
+        TimeDelta::from_std(self.next_retry_delay(attempt_number))
+            .ok()
+            .and_then(|delay| failed_at.checked_add_signed(delay))
+            .unwrap_or(DateTime::<Utc>::MAX_UTC)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct ActivityOptions {
     pub retry_policy: RetryOptions,
+    /// Maximum time an attempt may wait in the queue before a worker picks it up.
+    /// Not retried, since another attempt would wait in the same queue.
+    pub schedule_to_start_timeout: Option<Duration>,
+    /// Maximum time a single attempt may run. A timed out attempt is retried according
+    /// to `retry_policy`, with the error kind `TimedOut`.
+    pub start_to_close_timeout: Option<Duration>,
+    /// Maximum time for the whole activity, including all retries.
+    pub schedule_to_close_timeout: Option<Duration>,
+    /// Maximum time between heartbeats of a running attempt. A missed heartbeat times
+    /// out the attempt, which is then retried according to `retry_policy`.
+    pub heartbeat_timeout: Option<Duration>,
 }
 
 impl WorkflowContext {
     pub fn with_activity_options(&mut self, activity_options: ActivityOptions) {
         self.activity_options = activity_options;
     }
+
+    /// Answers queries named `query_name` with `handler` from now on, replacing any
+    /// handler registered under that name before.
+    pub fn register_query<F>(&self, query_name: impl Into<String>, handler: F)
+    where
+        F: Fn(String) -> Result<String, String> + Send + Sync + 'static,
+    {
+        self.query_handlers
+            .write()
+            .expect("query handlers poisoned")
+            .insert(query_name.into(), Box::new(handler));
+    }
+
+    /// Whether someone asked this run to cancel, as of the worker's last heartbeat.
+    /// Waiting on activities, signals or timers fails with a `Cancelled` error once they
+    /// did, but the workflow may still schedule activities to clean up. Returning an
+    /// error then ends the run as `Cancelled`.
+    pub fn is_cancel_requested(&self) -> bool {
+        *self.cancel_requested.borrow()
+    }
+
+    /// Resolves once the run is asked to cancel, e.g. to race long-running work in a
+    /// `tokio::select!`.
+    pub async fn cancel_requested(&self) {
+        let mut cancel_requested = self.cancel_requested.clone();
+        if cancel_requested
+            .wait_for(|requested| *requested)
+            .await
+            .is_err()
+        {
+            std::future::pending::<()>().await;
+        }
+    }
+
+    /// Runs an activity and waits for it. The input, output and error are passed as they
+    /// are; typed activities take JSON ones, see `execute_typed_activity`.
     pub async fn execute_activity<H>(&mut self, handler: H, input: String) -> Result<String, String>
     where
         H: AbstractActivityHandler + 'static,
+    {
+        let handle = self.start_activity(handler, input).await?;
+        handle.result().await.map_err(String::from)
+    }
+
+    /// Schedules an activity without waiting for it, so several can run at once. The
+    /// sequence is taken when this is called, so start activities in an order that
+    /// doesn't depend on how earlier ones turned out, e.g. all of them before awaiting any.
+    pub async fn start_activity<H>(
+        &mut self,
+        handler: H,
+        input: String,
+    ) -> Result<ActivityHandle<String, String>, String>
+    where
+        H: AbstractActivityHandler + 'static,
+    {
+        let activity_run_id = self
+            .schedule_activity(ActivityName::from(&handler), input)
+            .await?;
+        Ok(ActivityHandle {
+            activity_run_id,
+            client: self.client.clone(),
+            decode: raw_outcome,
+        })
+    }
+
+    /// Like `execute_activity`, with the handler's own input, output and error types.
+    pub async fn execute_typed_activity<H>(
+        &mut self,
+        handler: H,
+        input: H::Input,
+    ) -> Result<H::Output, HandlerError<H::Error>>
+    where
+        H: ActivityHandler + 'static,
+    {
+        self.start_typed_activity(handler, input)
+            .await?
+            .result()
+            .await
+    }
+
+    /// Like `start_activity`, with the handler's own input, output and error types.
+    pub async fn start_typed_activity<H>(
+        &mut self,
+        handler: H,
+        input: H::Input,
+    ) -> Result<ActivityHandle<H::Output, H::Error>, HandlerError<H::Error>>
+    where
+        H: ActivityHandler + 'static,
     {
         let name = ActivityName::from(&handler);
-        println!("Executing Activity: {name}");
+        let input = to_payload(&input).map_err(|e| {
+            HandlerError::Serialization(format!(
+                "{SERIALIZATION_ERROR}: invalid input for activity {name}: {e}"
+            ))
+        })?;
+        let activity_run_id = self
+            .schedule_activity(name, input)
+            .await
+            .map_err(HandlerError::Other)?;
+        Ok(ActivityHandle {
+            activity_run_id,
+            client: self.client.clone(),
+            decode: outcome,
+        })
+    }
+
+    /// Takes the next sequence for the activity, and schedules it unless an earlier
+    /// delivery of the run already did.
+    async fn schedule_activity(
+        &mut self,
+        name: ActivityName,
+        input: String,
+    ) -> Result<ActivityRunId, String> {
         self.event_count_order += 1;
-        let run_id = self
+        let sequence = self.event_count_order;
+
+        let recorded = self
+            .history
+            .iter()
+            .find(|command| command.sequence == sequence)
+            .map(|command| &command.command_type);
+
+        match recorded {
+            Some(WorkflowCommandType::ScheduleActivity {
+                name: recorded_name,
+                input: recorded_input,
+                activity_run_id,
+            }) => {
+                if *recorded_name != name || *recorded_input != input {
+                    return Err(format!(
+                        "NonDeterminism: history has {recorded_name}({recorded_input}) at sequence {sequence}, but the workflow scheduled {name}({input})"
+                    ));
+                }
+                println!("Replaying Activity: {name}");
+                Ok(*activity_run_id)
+            }
+            Some(other) => Err(format!(
+                "NonDeterminism: history has {other} at sequence {sequence}, but the workflow scheduled {name}({input})"
+            )),
+            None => {
+                println!("Executing Activity: {name}");
+                self.client
+                    .execute_activity(
+                        self.run_id,
+                        self.started_at,
+                        sequence,
+                        name,
+                        input,
+                        self.activity_options.clone(),
+                    )
+                    .await
+            }
+        }
+    }
+
+    /// Runs another workflow as a child of this run and waits for its result. The child
+    /// is picked up by whichever worker polls for its workflow, so a worker that runs
+    /// one workflow at a time can't execute a child of its own workflow.
+    pub async fn execute_child_workflow<W>(
+        &mut self,
+        handler: W,
+        input: String,
+        options: WorkflowOptions,
+    ) -> Result<String, String>
+    where
+        W: AbstractWorkflowHandler + 'static,
+    {
+        let completion = self
+            .run_child_workflow(WorkflowName::from(&handler), input, options)
+            .await?;
+        match completion.status {
+            WorkflowEventType::Succeeeded => Ok(completion.result),
+            _ => Err(completion.error),
+        }
+    }
+
+    /// Like `execute_child_workflow`, with the child's own input, output and error types.
+    pub async fn execute_typed_child_workflow<W>(
+        &mut self,
+        handler: W,
+        input: W::Input,
+        options: WorkflowOptions,
+    ) -> Result<W::Output, HandlerError<W::Error>>
+    where
+        W: WorkflowHandler + 'static,
+    {
+        let name = WorkflowName::from(&handler);
+        let input = to_payload(&input).map_err(|e| {
+            HandlerError::Serialization(format!(
+                "{SERIALIZATION_ERROR}: invalid input for child workflow {name}: {e}"
+            ))
+        })?;
+        let completion = self
+            .run_child_workflow(name, input, options)
+            .await
+            .map_err(HandlerError::Other)?;
+        workflow_outcome(&completion)
+    }
+
+    async fn run_child_workflow(
+        &mut self,
+        name: WorkflowName,
+        input: String,
+        options: WorkflowOptions,
+    ) -> Result<PollWorkflowCompletion, String> {
+        self.event_count_order += 1;
+        let sequence = self.event_count_order;
+
+        let recorded = self
+            .history
+            .iter()
+            .find(|command| command.sequence == sequence)
+            .map(|command| &command.command_type);
+
+        let child_run_id = match recorded {
+            Some(WorkflowCommandType::StartChildWorkflow {
+                name: recorded_name,
+                input: recorded_input,
+                child_run_id,
+            }) => {
+                if *recorded_name != name || *recorded_input != input {
+                    return Err(format!(
+                        "NonDeterminism: history has child workflow {recorded_name}({recorded_input}) at sequence {sequence}, but the workflow started {name}({input})"
+                    ));
+                }
+                println!("Replaying Child Workflow: {name}");
+                *child_run_id
+            }
+            Some(other) => {
+                return Err(format!(
+                    "NonDeterminism: history has {other} at sequence {sequence}, but the workflow started child workflow {name}({input})"
+                ));
+            }
+            None => {
+                println!("Executing Child Workflow: {name}");
+                self.client
+                    .start_child_workflow(
+                        self.run_id,
+                        self.started_at,
+                        sequence,
+                        name,
+                        input,
+                        options,
+                    )
+                    .await?
+            }
+        };
+
+        // Like a timer, the worker lets go of the run while the child runs. That frees it
+        // up for the child, which may well need the same worker.
+        match self
+            .client
+            .wait_for_child_workflow(self.run_id, child_run_id)
+            .await?
+        {
+            Some(completion) => Ok(completion),
+            None => {
+                self.release.notify_one();
+                std::future::pending().await
+            }
+        }
+    }
+
+    /// Durably sleeps for `duration`; see [`WorkflowContext::sleep_until`].
+    pub async fn sleep(&mut self, duration: Duration) -> Result<(), String> {
+        let duration = TimeDelta::from_std(duration).map_err(|e| e.to_string())?;
+        self.sleep_until(Utc::now() + duration).await
+    }
+
+    /// Durably sleeps until `fire_at`. Unless the timer is already due, the worker lets
+    /// go of the run here and the server hands it out again once the timer fires; it is
+    /// then replayed up to this point and continues from there.
+    pub async fn sleep_until(&mut self, fire_at: DateTime<Utc>) -> Result<(), String> {
+        self.event_count_order += 1;
+        let sequence = self.event_count_order;
+
+        let recorded = self
+            .history
+            .iter()
+            .find(|command| command.sequence == sequence)
+            .map(|command| &command.command_type);
+
+        let fire_at = match recorded {
+            // Replays use the time recorded when the timer started.
+            Some(WorkflowCommandType::StartTimer { fire_at }) => *fire_at,
+            Some(other) => {
+                return Err(format!(
+                    "NonDeterminism: history has {other} at sequence {sequence}, but the workflow started a timer"
+                ));
+            }
+            None => fire_at,
+        };
+        if fire_at <= Utc::now() && recorded.is_some() {
+            return Ok(());
+        }
+
+        let sleeping = self
             .client
-            .execute_activity(
-                self.run_id,
-                name,
-                input,
-                self.activity_options.retry_policy.max_attempts,
-            )
+            .start_timer(self.run_id, self.started_at, sequence, fire_at)
             .await?;
+        if !sleeping {
+            return Ok(());
+        }
 
-        loop {
-            if let Some(res) = self.client.poll_activity_completion(run_id).await? {
-                if !res.result.is_empty() {
-                    return Ok(res.result);
-                } else if !res.error.is_empty() {
-                    return Err(res.error);
+        self.release.notify_one();
+        std::future::pending().await
+    }
+
+    /// Waits for the next signal named `signal_name` that this run hasn't taken yet.
+    pub async fn wait_for_signal(&mut self, signal_name: &str) -> Result<String, String> {
+        let mut payloads = self.receive_signals(signal_name, true).await?;
+        payloads
+            .pop()
+            .ok_or_else(|| format!("No {signal_name} signal was received"))
+    }
+
+    /// Takes every signal named `signal_name` that arrived since the run last took them,
+    /// oldest first. Doesn't wait, so this may be empty.
+    pub async fn drain_signals(&mut self, signal_name: &str) -> Result<Vec<String>, String> {
+        self.receive_signals(signal_name, false).await
+    }
+
+    /// Which signals were taken is recorded as a command, so a replay gets the same ones
+    /// no matter what arrived since.
+    async fn receive_signals(
+        &mut self,
+        signal_name: &str,
+        wait: bool,
+    ) -> Result<Vec<String>, String> {
+        self.event_count_order += 1;
+        let sequence = self.event_count_order;
+
+        let recorded = self
+            .history
+            .iter()
+            .find(|command| command.sequence == sequence)
+            .map(|command| &command.command_type);
+
+        match recorded {
+            Some(WorkflowCommandType::ReceiveSignals {
+                signal_name: recorded_name,
+                payloads,
+                ..
+            }) if recorded_name == signal_name => {
+                println!("Replaying Signal: {signal_name}");
+                Ok(payloads.clone())
+            }
+            Some(other) => Err(format!(
+                "NonDeterminism: history has {other} at sequence {sequence}, but the workflow received signal {signal_name}"
+            )),
+            None => loop {
+                if let Some(payloads) = self
+                    .client
+                    .receive_signals(
+                        self.run_id,
+                        self.started_at,
+                        sequence,
+                        signal_name.to_string(),
+                        wait,
+                    )
+                    .await?
+                {
+                    return Ok(payloads);
                 }
-            };
+            },
         }
     }
 }
@@ -151,3 +921,32 @@ impl WorkflowContext {
 pub trait AbstractWorkflowHandler: Send + Sync {
     async fn run(&self, context: WorkflowContext, input: String) -> Result<String, String>;
 }
+
+/// A workflow with typed input, output and error, encoded like those of an
+/// [`ActivityHandler`]. Every `WorkflowHandler` is an `AbstractWorkflowHandler` too.
+#[async_trait::async_trait]
+pub trait WorkflowHandler: Send + Sync {
+    type Input: Serialize + DeserializeOwned + Send;
+    type Output: Serialize + DeserializeOwned + Send;
+    type Error: Serialize + DeserializeOwned + ErrorKind + Send;
+
+    async fn run(
+        &self,
+        context: WorkflowContext,
+        input: Self::Input,
+    ) -> Result<Self::Output, Self::Error>;
+}
+
+#[async_trait::async_trait]
+impl<W: WorkflowHandler> AbstractWorkflowHandler for W {
+    async fn run(&self, context: WorkflowContext, input: String) -> Result<String, String> {
+        let input = from_payload(&input)
+            .map_err(|e| format!("{SERIALIZATION_ERROR}: invalid workflow input: {e}"))?;
+        match WorkflowHandler::run(self, context, input).await {
+            Ok(output) => to_payload(&output)
+                .map_err(|e| format!("{SERIALIZATION_ERROR}: invalid workflow output: {e}")),
+            Err(error) => Err(to_error_payload(&error)
+                .map_err(|e| format!("{SERIALIZATION_ERROR}: invalid workflow error: {e}"))?),
+        }
+    }
+}
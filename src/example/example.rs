@@ -1,23 +1,32 @@
 use std::time::{Duration, Instant};
 
 use crate::core;
+use crate::inmemory_db::Db;
 use crate::server::Server;
+use crate::store::{PostgresStore, SqliteStore};
 use rand::Rng;
 use tokio::signal;
 
 struct SumActivity;
 #[async_trait::async_trait]
-impl core::AbstractActivityHandler for SumActivity {
-    async fn run(&self, input: String) -> Result<String, String> {
-        println!("[running sum activity with input {input}]");
-        let number = input.parse::<i32>().map_err(|_e| "Invalid string")?;
-        Ok(format!("{}", number + 1))
+impl core::ActivityHandler for SumActivity {
+    type Input = i32;
+    type Output = i32;
+    type Error = String;
+
+    async fn run(&self, _context: core::ActivityContext, number: i32) -> Result<i32, String> {
+        println!("[running sum activity with input {number}]");
+        Ok(number + 1)
     }
 }
 struct FailActivity;
 #[async_trait::async_trait]
-impl core::AbstractActivityHandler for FailActivity {
-    async fn run(&self, input: String) -> Result<String, String> {
+impl core::ActivityHandler for FailActivity {
+    type Input = String;
+    type Output = i32;
+    type Error = String;
+
+    async fn run(&self, _context: core::ActivityContext, input: String) -> Result<i32, String> {
         println!("[running fail activity with input {input}]");
         Err("Sadge".to_string())
     }
@@ -25,30 +34,38 @@ impl core::AbstractActivityHandler for FailActivity {
 
 struct SumAndPrintWorkflow;
 #[async_trait::async_trait]
-impl core::AbstractWorkflowHandler for SumAndPrintWorkflow {
-    async fn run(
-        &self,
-        mut context: core::WorkflowContext,
-        input: String,
-    ) -> Result<String, String> {
+impl core::WorkflowHandler for SumAndPrintWorkflow {
+    type Input = i32;
+    type Output = String;
+    type Error = String;
+
+    async fn run(&self, mut context: core::WorkflowContext, input: i32) -> Result<String, String> {
         println!("\n\n[sumandprint workflow running with {input}] ");
         let options = core::ActivityOptions {
-            retry_policy: core::RetryOptions { max_attempts: 3 },
+            retry_policy: core::RetryOptions {
+                max_attempts: 3,
+                initial_interval: Duration::from_millis(200),
+                jitter: 0.2,
+                non_retryable_error_kinds: vec!["ValidationError".to_string()],
+                ..Default::default()
+            },
+            start_to_close_timeout: Some(Duration::from_secs(5)),
+            ..Default::default()
         };
 
         context.with_activity_options(options);
 
-        let res = context.execute_activity(SumActivity, input.clone()).await?;
+        let res = context.execute_typed_activity(SumActivity, input).await?;
 
         let might_fail_randomly = rand::rng().random_bool(0.6);
 
         let res_2 = if might_fail_randomly {
             context
-                .execute_activity(FailActivity, "Fail input".to_string())
+                .execute_typed_activity(FailActivity, "Fail input".to_string())
                 .await?
         } else {
             context
-                .execute_activity(SumActivity, input.clone())
+                .execute_typed_activity(SumActivity, input)
                 .await
                 .map_err(|e| {
                     println!("error: {e}");
@@ -63,8 +80,21 @@ impl core::AbstractWorkflowHandler for SumAndPrintWorkflow {
 pub async fn run() {
     println!("-------- Setting up -----");
 
-    // New worker
-    let server = Server::new();
+    // New worker. Set JAMESPORAL_POSTGRES=<url>, JAMESPORAL_SQLITE=<path> or
+    // JAMESPORAL_WAL=<dir> to keep runs across restarts.
+    let server = if let Ok(url) = std::env::var("JAMESPORAL_POSTGRES") {
+        Server::new(
+            PostgresStore::connect(&url)
+                .await
+                .expect("Could not connect to Postgres"),
+        )
+    } else if let Ok(path) = std::env::var("JAMESPORAL_SQLITE") {
+        Server::new(SqliteStore::open(path).expect("Could not open SQLite store"))
+    } else if let Ok(dir) = std::env::var("JAMESPORAL_WAL") {
+        Server::new(Db::open(dir).expect("Could not open write-ahead log"))
+    } else {
+        Server::new(Db::new())
+    };
     tokio::task::spawn(async move { server.run().await });
 
     let client = core::Client::new("http://localhost:8080");
@@ -90,7 +120,14 @@ pub async fn run() {
 
     let start = Instant::now();
     let res = worker
-        .execute_workflow(SumAndPrintWorkflow, "3".to_string())
+        .execute_workflow(
+            SumAndPrintWorkflow,
+            "3".to_string(),
+            core::WorkflowOptions {
+                execution_timeout: Some(Duration::from_secs(60)),
+                ..Default::default()
+            },
+        )
         .await;
     let execute_duration = start.elapsed();
 
@@ -102,6 +139,9 @@ pub async fn run() {
     println!(
         r#"if it was a failure you can rerun it with POST http://localhost:8080/rerun_workflow {{ "workflow_run_id": "<the id>" }}"#
     );
+    println!(
+        r#"add "checkpoint": {{ "sequence": <n> }} or {{ "activity_run_id": "<the id>" }} to re-execute from that activity onwards"#
+    );
 
     // tokio::time::sleep(Duration::from_secs(2)).await;
     signal::ctrl_c()
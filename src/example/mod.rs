@@ -1,2 +1,3 @@
+#[allow(clippy::module_inception)]
 pub mod example;
 pub use example::run;
@@ -0,0 +1,745 @@
+mod wal;
+
+use std::collections::{BTreeMap, VecDeque};
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::core::activity::{
+    Activity, ActivityEvent, ActivityEventType, ActivityHeartbeat, ActivityId, ActivityName,
+    ActivityRunId,
+};
+use crate::core::workflow::{
+    Workflow, WorkflowCommand, WorkflowEvent, WorkflowEventType, WorkflowId, WorkflowName,
+    WorkflowRunId, WorkflowSignal,
+};
+use crate::store::{is_same_event, started_activity_event, started_workflow_event, Store};
+use chrono::{DateTime, Utc};
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+use wal::{LogRecord, Wal, COMPACT_AFTER_RECORDS};
+
+/// Activity runs by the time they become due, earliest first.
+pub type ScheduledRuns = BTreeMap<DateTime<Utc>, Vec<ActivityRunId>>;
+
+#[derive(Clone)]
+pub struct Db {
+    pub workflows: Arc<DashMap<WorkflowName, Workflow>>,
+    pub workflow_runs: Arc<DashMap<WorkflowId, Vec<WorkflowRunId>>>,
+    pub workflow_events: Arc<DashMap<WorkflowRunId, Vec<WorkflowEvent>>>,
+    pub workflow_commands: Arc<DashMap<WorkflowRunId, Vec<WorkflowCommand>>>,
+    pub workflow_heartbeats: Arc<DashMap<WorkflowRunId, DateTime<Utc>>>,
+    pub workflow_signals: Arc<DashMap<WorkflowRunId, Vec<WorkflowSignal>>>,
+    /// Last event of every run, so reading a run's state doesn't walk its history.
+    pub workflow_states: Arc<DashMap<WorkflowRunId, WorkflowEvent>>,
+    /// Runs in the order they became `Pending`. Runs that have moved on since are
+    /// dropped when a poller gets to them.
+    pub pending_workflows: Arc<DashMap<WorkflowId, VecDeque<WorkflowRunId>>>,
+
+    pub activities: Arc<DashMap<ActivityName, Activity>>,
+    pub activity_runs: Arc<DashMap<ActivityId, Vec<ActivityRunId>>>,
+    pub activity_events: Arc<DashMap<ActivityRunId, Vec<ActivityEvent>>>,
+    pub activity_heartbeats: Arc<DashMap<ActivityRunId, ActivityHeartbeat>>,
+    pub activity_states: Arc<DashMap<ActivityRunId, ActivityEvent>>,
+    pub pending_activities: Arc<DashMap<ActivityId, VecDeque<ActivityRunId>>>,
+    /// Retries still waiting out their backoff, by when they become due. Claims move the
+    /// due ones over to `pending_activities`.
+    pub scheduled_activities: Arc<DashMap<ActivityId, ScheduledRuns>>,
+
+    wal: Option<Arc<Mutex<Wal>>>,
+}
+
+impl Db {
+    pub fn new() -> Self {
+        Self {
+            workflows: Arc::new(DashMap::new()),
+            workflow_runs: Arc::new(DashMap::new()),
+            workflow_events: Arc::new(DashMap::new()),
+            workflow_commands: Arc::new(DashMap::new()),
+            workflow_heartbeats: Arc::new(DashMap::new()),
+            workflow_signals: Arc::new(DashMap::new()),
+            workflow_states: Arc::new(DashMap::new()),
+            pending_workflows: Arc::new(DashMap::new()),
+
+            activities: Arc::new(DashMap::new()),
+            activity_runs: Arc::new(DashMap::new()),
+            activity_events: Arc::new(DashMap::new()),
+            activity_heartbeats: Arc::new(DashMap::new()),
+            activity_states: Arc::new(DashMap::new()),
+            pending_activities: Arc::new(DashMap::new()),
+            scheduled_activities: Arc::new(DashMap::new()),
+
+            wal: None,
+        }
+    }
+
+    /// A `Db` that logs every write to `dir` and starts from what was logged there
+    /// before. Workflow heartbeats are not logged; workers resume sending them on their
+    /// own.
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, String> {
+        Self::open_compacting_after(dir, COMPACT_AFTER_RECORDS)
+    }
+
+    /// Like `open`, writing a snapshot every `records` logged records. Snapshots are
+    /// written in the background, but the write that triggers one still copies the
+    /// whole state while holding up other writes.
+    pub fn open_compacting_after(dir: impl AsRef<Path>, records: usize) -> Result<Self, String> {
+        let (mut wal, records) = Wal::open(dir.as_ref(), records)?;
+        println!(
+            "Restoring {} records from {}",
+            records.len(),
+            dir.as_ref().display()
+        );
+
+        let db = Self::new();
+        for record in records {
+            db.apply(record);
+        }
+        if wal.has_uncompacted_records() {
+            wal.compact(db.records())?;
+        }
+
+        Ok(Self {
+            wal: Some(Arc::new(Mutex::new(wal))),
+            ..db
+        })
+    }
+
+    /// Holding the log lock while changing the maps keeps the log in the same order as
+    /// the maps, and keeps compaction from snapshotting a change that isn't logged yet.
+    fn lock_wal(&self) -> Option<MutexGuard<'_, Wal>> {
+        self.wal
+            .as_ref()
+            .map(|wal| wal.lock().expect("write-ahead log poisoned"))
+    }
+
+    fn write(&self, record: LogRecord) {
+        let Some(mut wal) = self.lock_wal() else {
+            self.apply(record);
+            return;
+        };
+
+        wal.append(&record)
+            .unwrap_or_else(|e| panic!("Could not append to write-ahead log: {e}"));
+        self.apply(record);
+        self.compact_if_due(&mut wal);
+    }
+
+    /// Rotates the log and writes the snapshot on another thread, so writers only wait
+    /// for the state to be copied, not for it to reach the disk.
+    fn compact_if_due(&self, wal: &mut Wal) {
+        if !wal.should_compact() {
+            return;
+        }
+        let snapshot = wal
+            .start_generation()
+            .unwrap_or_else(|e| panic!("Could not compact write-ahead log: {e}"));
+        let state = self.records();
+        std::thread::spawn(move || {
+            // The logs it would have replaced stay and are replayed on restart instead.
+            if let Err(e) = snapshot.write(state) {
+                println!("Could not compact write-ahead log: {e}");
+            }
+        });
+    }
+
+    fn append_workflow_events_if_last(
+        &self,
+        expected: &WorkflowEvent,
+        events: Vec<WorkflowEvent>,
+    ) -> bool {
+        let Some(last) = events.iter().max_by_key(|e| e.created_at).cloned() else {
+            return false;
+        };
+        let run_id = expected.run_id;
+        let mut wal = self.lock_wal();
+
+        // Appends to the run wait for each other on its state, which makes checking and
+        // updating it a compare-and-set. The pending queue is only touched once the
+        // state is released, since claims lock the queue before the state.
+        {
+            let Some(mut state) = self.workflow_states.get_mut(&run_id) else {
+                return false;
+            };
+            if !is_same_event(&*state, expected) {
+                return false;
+            }
+            if let Some(wal) = wal.as_mut() {
+                wal.append(&LogRecord::WorkflowEvents(events.clone()))
+                    .unwrap_or_else(|e| panic!("Could not append to write-ahead log: {e}"));
+            }
+            *state = last.clone();
+            self.workflow_events
+                .entry(run_id)
+                .or_default()
+                .extend(events);
+        }
+
+        if last.event_type == WorkflowEventType::Pending {
+            self.pending_workflows
+                .entry(last.workflow_id)
+                .or_default()
+                .push_back(run_id);
+        }
+        if let Some(wal) = wal.as_mut() {
+            self.compact_if_due(wal);
+        }
+        true
+    }
+
+    fn append_workflow_command(&self, command: WorkflowCommand) -> bool {
+        let mut wal = self.lock_wal();
+        // Holding the run's entry keeps others from adding a command in the meantime.
+        let mut commands = self
+            .workflow_commands
+            .entry(command.workflow_run_id)
+            .or_default();
+        if commands.iter().any(|c| c.sequence == command.sequence) {
+            return false;
+        }
+        if let Some(wal) = wal.as_mut() {
+            wal.append(&LogRecord::WorkflowCommand(command.clone()))
+                .unwrap_or_else(|e| panic!("Could not append to write-ahead log: {e}"));
+        }
+        commands.push(command);
+        drop(commands);
+
+        if let Some(wal) = wal.as_mut() {
+            self.compact_if_due(wal);
+        }
+        true
+    }
+
+    fn append_activity_events_if_last(
+        &self,
+        expected: &ActivityEvent,
+        events: Vec<ActivityEvent>,
+    ) -> bool {
+        let Some(last) = events.iter().max_by_key(|e| e.created_at).cloned() else {
+            return false;
+        };
+        let run_id = expected.activity_run_id;
+        let mut wal = self.lock_wal();
+
+        {
+            let Some(mut state) = self.activity_states.get_mut(&run_id) else {
+                return false;
+            };
+            if !is_same_event(&*state, expected) {
+                return false;
+            }
+            if let Some(wal) = wal.as_mut() {
+                wal.append(&LogRecord::ActivityEvents(events.clone()))
+                    .unwrap_or_else(|e| panic!("Could not append to write-ahead log: {e}"));
+            }
+            *state = last.clone();
+            self.activity_events
+                .entry(run_id)
+                .or_default()
+                .extend(events);
+        }
+
+        if last.event_type == ActivityEventType::Pending {
+            self.enqueue_activity(last.activity_id, run_id, last.scheduled_for);
+        }
+        if let Some(wal) = wal.as_mut() {
+            self.compact_if_due(wal);
+        }
+        true
+    }
+
+    fn claim_workflow(&self, workflow_id: WorkflowId) -> Option<WorkflowEvent> {
+        let mut wal = self.lock_wal();
+
+        // Polls for the same workflow wait for each other on its queue, which makes
+        // checking and updating the run's state below a compare-and-set.
+        let mut queue = self.pending_workflows.get_mut(&workflow_id)?;
+        while let Some(run_id) = queue.pop_front() {
+            let Some(mut state) = self.workflow_states.get_mut(&run_id) else {
+                continue;
+            };
+            if state.event_type != WorkflowEventType::Pending {
+                continue;
+            }
+
+            let pending = state.clone();
+            let started = started_workflow_event(&pending);
+            *state = started.clone();
+            self.workflow_events
+                .entry(run_id)
+                .or_default()
+                .push(started.clone());
+            drop(state);
+            drop(queue);
+
+            if let Some(wal) = wal.as_mut() {
+                wal.append(&LogRecord::WorkflowEvent(started))
+                    .unwrap_or_else(|e| panic!("Could not append to write-ahead log: {e}"));
+                self.compact_if_due(wal);
+            }
+            return Some(pending);
+        }
+        None
+    }
+
+    fn claim_activity(&self, activity_id: ActivityId) -> Option<ActivityEvent> {
+        let mut wal = self.lock_wal();
+        let now = Utc::now();
+
+        // Like `claim_workflow`, with attempts that became due since the last claim
+        // joining the back of the queue first.
+        let mut queue = self.pending_activities.entry(activity_id).or_default();
+        if let Some(mut scheduled) = self.scheduled_activities.get_mut(&activity_id) {
+            while let Some(due) = scheduled.first_entry() {
+                if *due.key() > now {
+                    break;
+                }
+                queue.extend(due.remove());
+            }
+        }
+
+        while let Some(run_id) = queue.pop_front() {
+            let Some(mut state) = self.activity_states.get_mut(&run_id) else {
+                continue;
+            };
+            // Also skips runs that were queued again for a later retry since.
+            if !is_due(&state, now) {
+                continue;
+            }
+
+            let pending = state.clone();
+            let started = started_activity_event(&pending);
+            *state = started.clone();
+            self.activity_events
+                .entry(run_id)
+                .or_default()
+                .push(started.clone());
+            drop(state);
+            drop(queue);
+
+            if let Some(wal) = wal.as_mut() {
+                wal.append(&LogRecord::ActivityEvents(vec![started]))
+                    .unwrap_or_else(|e| panic!("Could not append to write-ahead log: {e}"));
+                self.compact_if_due(wal);
+            }
+            return Some(pending);
+        }
+        None
+    }
+
+    /// Queues a pending attempt of the activity, or schedules it if it isn't due yet.
+    fn enqueue_activity(
+        &self,
+        activity_id: ActivityId,
+        run_id: ActivityRunId,
+        scheduled_for: Option<DateTime<Utc>>,
+    ) {
+        match scheduled_for.filter(|scheduled_for| *scheduled_for > Utc::now()) {
+            Some(scheduled_for) => self
+                .scheduled_activities
+                .entry(activity_id)
+                .or_default()
+                .entry(scheduled_for)
+                .or_default()
+                .push(run_id),
+            None => self
+                .pending_activities
+                .entry(activity_id)
+                .or_default()
+                .push_back(run_id),
+        }
+    }
+
+    /// Runs `f` against this db. With a log, writes do file I/O under a std mutex, so
+    /// they run on the blocking thread pool instead of stalling the async workers.
+    async fn blocking<T, F>(&self, f: F) -> T
+    where
+        F: FnOnce(&Db) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        if self.wal.is_none() {
+            return f(self);
+        }
+        let db = self.clone();
+        tokio::task::spawn_blocking(move || f(&db))
+            .await
+            .unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()))
+    }
+
+    fn apply(&self, record: LogRecord) {
+        match record {
+            LogRecord::Workflow(workflow) => {
+                self.workflows.insert(workflow.name.clone(), workflow);
+            }
+            LogRecord::Activity(activity) => {
+                self.activities.insert(activity.name.clone(), activity);
+            }
+            LogRecord::WorkflowEvent(event) => self.apply_workflow_event(event),
+            LogRecord::WorkflowEvents(events) => {
+                for event in events {
+                    self.apply_workflow_event(event);
+                }
+            }
+            LogRecord::ActivityEvents(events) => self.apply_activity_events(events),
+            LogRecord::WorkflowCommand(command) => {
+                self.workflow_commands
+                    .entry(command.workflow_run_id)
+                    .or_default()
+                    .push(command);
+            }
+            LogRecord::WorkflowSignal(signal) => {
+                self.workflow_signals
+                    .entry(signal.workflow_run_id)
+                    .or_default()
+                    .push(signal);
+            }
+            LogRecord::ActivityHeartbeat(heartbeat) => {
+                self.activity_heartbeats
+                    .insert(heartbeat.activity_run_id, heartbeat);
+            }
+        }
+    }
+
+    fn apply_workflow_event(&self, event: WorkflowEvent) {
+        let workflow_id = event.workflow_id;
+        let run_id = event.run_id;
+        let is_pending = event.event_type == WorkflowEventType::Pending;
+
+        self.workflow_events
+            .entry(run_id)
+            .or_default()
+            .push(event.clone());
+
+        let (is_new_run, is_latest) = match self.workflow_states.entry(run_id) {
+            Entry::Occupied(mut state) if state.get().created_at <= event.created_at => {
+                state.insert(event);
+                (false, true)
+            }
+            Entry::Occupied(_) => (false, false),
+            Entry::Vacant(state) => {
+                state.insert(event);
+                (true, true)
+            }
+        };
+
+        if is_new_run {
+            self.workflow_runs
+                .entry(workflow_id)
+                .or_default()
+                .push(run_id);
+        }
+        if is_latest && is_pending {
+            self.pending_workflows
+                .entry(workflow_id)
+                .or_default()
+                .push_back(run_id);
+        }
+    }
+
+    /// Applies the events of one activity run as a single state change, so a failed
+    /// attempt and its retry never show up as the run having failed.
+    fn apply_activity_events(&self, events: Vec<ActivityEvent>) {
+        let Some(last) = events.iter().max_by_key(|e| e.created_at).cloned() else {
+            return;
+        };
+        let activity_id = last.activity_id;
+        let run_id = last.activity_run_id;
+        let is_pending = last.event_type == ActivityEventType::Pending;
+        let scheduled_for = last.scheduled_for;
+
+        self.activity_events
+            .entry(run_id)
+            .or_default()
+            .extend(events);
+
+        let (is_new_run, is_latest) = match self.activity_states.entry(run_id) {
+            Entry::Occupied(mut state) if state.get().created_at <= last.created_at => {
+                state.insert(last);
+                (false, true)
+            }
+            Entry::Occupied(_) => (false, false),
+            Entry::Vacant(state) => {
+                state.insert(last);
+                (true, true)
+            }
+        };
+
+        if is_new_run {
+            self.activity_runs
+                .entry(activity_id)
+                .or_default()
+                .push(run_id);
+        }
+        if is_latest && is_pending {
+            self.enqueue_activity(activity_id, run_id, scheduled_for);
+        }
+    }
+
+    /// The current state as records that rebuild it when applied in order. Runs are
+    /// walked in the order they were recorded, so pending runs are picked up in the
+    /// same order after a restart.
+    fn records(&self) -> Vec<LogRecord> {
+        let mut records = vec![];
+        for workflow in self.workflows.iter() {
+            records.push(LogRecord::Workflow(workflow.clone()));
+        }
+        for activity in self.activities.iter() {
+            records.push(LogRecord::Activity(activity.clone()));
+        }
+        for runs in self.workflow_runs.iter() {
+            for run in runs.iter() {
+                if let Some(events) = self.workflow_events.get(run) {
+                    records.extend(events.iter().cloned().map(LogRecord::WorkflowEvent));
+                }
+            }
+        }
+        for commands in self.workflow_commands.iter() {
+            records.extend(commands.iter().cloned().map(LogRecord::WorkflowCommand));
+        }
+        for signals in self.workflow_signals.iter() {
+            records.extend(signals.iter().cloned().map(LogRecord::WorkflowSignal));
+        }
+        for runs in self.activity_runs.iter() {
+            for run in runs.iter() {
+                if let Some(events) = self.activity_events.get(run) {
+                    records.push(LogRecord::ActivityEvents(events.clone()));
+                }
+            }
+        }
+        for heartbeat in self.activity_heartbeats.iter() {
+            records.push(LogRecord::ActivityHeartbeat(heartbeat.clone()));
+        }
+        records
+    }
+}
+
+#[async_trait::async_trait]
+impl Store for Db {
+    async fn get_workflow_by_name(&self, name: &WorkflowName) -> Option<Workflow> {
+        let workflow = self.workflows.get(name)?;
+        Some(workflow.clone())
+    }
+
+    async fn get_activity_by_name(&self, name: &ActivityName) -> Option<Activity> {
+        let activity = self.activities.get(name)?;
+        Some(activity.clone())
+    }
+
+    async fn add_activity(&self, activity: Activity) {
+        self.blocking(|db| db.write(LogRecord::Activity(activity)))
+            .await;
+    }
+
+    async fn add_workflow(&self, workflow: Workflow) {
+        self.blocking(|db| db.write(LogRecord::Workflow(workflow)))
+            .await;
+    }
+
+    async fn add_activity_events(&self, events: Vec<ActivityEvent>) {
+        self.blocking(|db| db.write(LogRecord::ActivityEvents(events)))
+            .await;
+    }
+
+    async fn add_workflow_event(&self, event: WorkflowEvent) {
+        self.blocking(|db| db.write(LogRecord::WorkflowEvent(event)))
+            .await;
+    }
+
+    async fn add_workflow_events_if_last(
+        &self,
+        expected: &WorkflowEvent,
+        events: Vec<WorkflowEvent>,
+    ) -> bool {
+        let expected = expected.clone();
+        self.blocking(move |db| db.append_workflow_events_if_last(&expected, events))
+            .await
+    }
+
+    async fn add_activity_events_if_last(
+        &self,
+        expected: &ActivityEvent,
+        events: Vec<ActivityEvent>,
+    ) -> bool {
+        let expected = expected.clone();
+        self.blocking(move |db| db.append_activity_events_if_last(&expected, events))
+            .await
+    }
+
+    async fn get_workflow_runs(&self, workflow_id: WorkflowId) -> Vec<WorkflowRunId> {
+        self.workflow_runs
+            .get(&workflow_id)
+            .map(|runs| runs.clone())
+            .unwrap_or_default()
+    }
+
+    async fn get_activity_runs(&self, activity_id: ActivityId) -> Vec<ActivityRunId> {
+        self.activity_runs
+            .get(&activity_id)
+            .map(|runs| runs.clone())
+            .unwrap_or_default()
+    }
+
+    async fn add_workflow_command(&self, command: WorkflowCommand) -> bool {
+        self.blocking(|db| db.append_workflow_command(command))
+            .await
+    }
+
+    async fn get_workflow_commands(&self, workflow_run_id: WorkflowRunId) -> Vec<WorkflowCommand> {
+        let mut commands = self
+            .workflow_commands
+            .get(&workflow_run_id)
+            .map(|commands| commands.clone())
+            .unwrap_or_default();
+        commands.sort_by_key(|c| c.sequence);
+        commands
+    }
+
+    async fn add_workflow_signal(&self, signal: WorkflowSignal) {
+        self.blocking(|db| db.write(LogRecord::WorkflowSignal(signal)))
+            .await;
+    }
+
+    async fn get_workflow_signals(&self, workflow_run_id: WorkflowRunId) -> Vec<WorkflowSignal> {
+        self.workflow_signals
+            .get(&workflow_run_id)
+            .map(|signals| signals.clone())
+            .unwrap_or_default()
+    }
+
+    async fn record_workflow_heartbeat(&self, workflow_run_id: WorkflowRunId) {
+        self.workflow_heartbeats.insert(workflow_run_id, Utc::now());
+    }
+
+    async fn get_last_workflow_heartbeat(
+        &self,
+        workflow_run_id: WorkflowRunId,
+    ) -> Option<DateTime<Utc>> {
+        let heartbeat = self.workflow_heartbeats.get(&workflow_run_id)?;
+        Some(*heartbeat)
+    }
+
+    async fn record_activity_heartbeat(&self, heartbeat: ActivityHeartbeat) {
+        self.blocking(|db| db.write(LogRecord::ActivityHeartbeat(heartbeat)))
+            .await;
+    }
+
+    async fn get_last_activity_heartbeat(
+        &self,
+        activity_run_id: ActivityRunId,
+    ) -> Option<ActivityHeartbeat> {
+        let heartbeat = self.activity_heartbeats.get(&activity_run_id)?;
+        Some(heartbeat.clone())
+    }
+
+    async fn get_unfinished_activity_runs(&self) -> Vec<ActivityRunId> {
+        self.activity_states
+            .iter()
+            .filter(|state| {
+                matches!(
+                    state.event_type,
+                    ActivityEventType::Pending | ActivityEventType::Started
+                )
+            })
+            .map(|state| *state.key())
+            .collect()
+    }
+
+    async fn get_unfinished_workflow_runs(&self) -> Vec<WorkflowRunId> {
+        self.workflow_states
+            .iter()
+            .filter(|state| !state.event_type.is_finished())
+            .map(|state| *state.key())
+            .collect()
+    }
+
+    async fn get_activity_run_events(&self, activity_run_id: ActivityRunId) -> Vec<ActivityEvent> {
+        let mut activity_events = self
+            .activity_events
+            .get(&activity_run_id)
+            .map(|events| events.clone())
+            .unwrap_or_default();
+        activity_events.sort_by_key(|a| a.created_at);
+        activity_events
+    }
+
+    async fn get_workflow_run_events(&self, workflow_run_id: WorkflowRunId) -> Vec<WorkflowEvent> {
+        let mut workflow_events = self
+            .workflow_events
+            .get(&workflow_run_id)
+            .map(|events| events.clone())
+            .unwrap_or_default();
+        workflow_events.sort_by_key(|a| a.created_at);
+        workflow_events
+    }
+
+    async fn get_last_workflow_run_event(
+        &self,
+        workflow_run_id: WorkflowRunId,
+    ) -> Option<WorkflowEvent> {
+        let state = self.workflow_states.get(&workflow_run_id)?;
+        Some(state.clone())
+    }
+
+    async fn get_last_activity_run_event(
+        &self,
+        activity_run_id: ActivityRunId,
+    ) -> Option<ActivityEvent> {
+        let state = self.activity_states.get(&activity_run_id)?;
+        Some(state.clone())
+    }
+
+    async fn get_first_pending_workflow(&self, name: WorkflowName) -> Option<WorkflowEvent> {
+        let workflow = self.get_workflow_by_name(&name).await?;
+        let queue = self.pending_workflows.get(&workflow.id)?;
+
+        queue.iter().find_map(|run_id| {
+            let state = self.workflow_states.get(run_id)?;
+            (state.event_type == WorkflowEventType::Pending).then(|| state.clone())
+        })
+    }
+
+    async fn get_first_pending_activity(&self, name: ActivityName) -> Option<ActivityEvent> {
+        let activity = self.get_activity_by_name(&name).await?;
+        let now = Utc::now();
+        let first_due = |run_id: &ActivityRunId| {
+            let state = self.activity_states.get(run_id)?;
+            is_due(&state, now).then(|| state.clone())
+        };
+
+        let queued = self
+            .pending_activities
+            .get(&activity.id)
+            .and_then(|queue| queue.iter().find_map(first_due));
+        queued.or_else(|| {
+            let scheduled = self.scheduled_activities.get(&activity.id)?;
+            scheduled
+                .range(..=now)
+                .flat_map(|(_, runs)| runs)
+                .find_map(first_due)
+        })
+    }
+
+    async fn claim_pending_workflow(&self, name: WorkflowName) -> Option<WorkflowEvent> {
+        let workflow = self.get_workflow_by_name(&name).await?;
+        self.blocking(move |db| db.claim_workflow(workflow.id))
+            .await
+    }
+
+    async fn claim_pending_activity(&self, name: ActivityName) -> Option<ActivityEvent> {
+        let activity = self.get_activity_by_name(&name).await?;
+        self.blocking(move |db| db.claim_activity(activity.id))
+            .await
+    }
+}
+
+fn is_due(state: &ActivityEvent, now: DateTime<Utc>) -> bool {
+    state.event_type == ActivityEventType::Pending
+        && state
+            .scheduled_for
+            .is_none_or(|scheduled_for| scheduled_for <= now)
+}
+
+impl Default for Db {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,249 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::core::activity::{Activity, ActivityEvent, ActivityHeartbeat};
+use crate::core::workflow::{Workflow, WorkflowCommand, WorkflowEvent, WorkflowSignal};
+
+/// Number of records appended to the log after which the whole state is written to a
+/// fresh snapshot and the log starts over, so restarts replay little more than this.
+pub const COMPACT_AFTER_RECORDS: usize = 10_000;
+
+/// One write to the `Db`. Snapshots and logs are both JSON lines of these.
+#[derive(Serialize, Deserialize)]
+pub enum LogRecord {
+    Workflow(Workflow),
+    Activity(Activity),
+    WorkflowEvent(WorkflowEvent),
+    /// Events of one workflow run appended in one step.
+    WorkflowEvents(Vec<WorkflowEvent>),
+    ActivityEvents(Vec<ActivityEvent>),
+    WorkflowCommand(WorkflowCommand),
+    WorkflowSignal(WorkflowSignal),
+    /// Only the last heartbeat of a run is kept, since it carries the progress details
+    /// the next attempt resumes from.
+    ActivityHeartbeat(ActivityHeartbeat),
+}
+
+/// Files are numbered by generation: `snapshot-N.jsonl` holds the state at the start of
+/// generation N and `log-N.jsonl` everything appended since. A snapshot only appears once
+/// it is complete, and the logs of older generations are only removed after that, so a
+/// crash mid-compaction replays the last complete snapshot and every log after it.
+///
+/// Records are written to the OS without fsync, which survives the server process
+/// crashing but not the machine losing power.
+pub struct Wal {
+    dir: PathBuf,
+    generation: u64,
+    log: File,
+    records_since_snapshot: usize,
+    compact_after_records: usize,
+    /// Set while a snapshot is being written, so only one is written at a time.
+    compacting: Arc<AtomicBool>,
+}
+
+/// The snapshot that starts a generation whose log is already being appended to.
+pub struct PendingSnapshot {
+    dir: PathBuf,
+    generation: u64,
+    compacting: Arc<AtomicBool>,
+}
+
+impl Wal {
+    /// Opens the latest generation in `dir`, returning its records in the order they
+    /// have to be applied.
+    pub fn open(
+        dir: &Path,
+        compact_after_records: usize,
+    ) -> Result<(Self, Vec<LogRecord>), String> {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        let mut generation = latest_snapshot(dir)?;
+
+        let mut records = read_records(&snapshot_path(dir, generation))?;
+        let mut records_since_snapshot = 0;
+        loop {
+            let logged = read_records(&log_path(dir, generation))?;
+            records_since_snapshot += logged.len();
+            records.extend(logged);
+            // The snapshot of the next generation wasn't finished.
+            if !log_path(dir, generation + 1).exists() {
+                break;
+            }
+            generation += 1;
+        }
+
+        let wal = Self {
+            dir: dir.to_path_buf(),
+            generation,
+            log: open_log(&log_path(dir, generation))?,
+            records_since_snapshot,
+            compact_after_records,
+            compacting: Arc::new(AtomicBool::new(false)),
+        };
+        Ok((wal, records))
+    }
+
+    pub fn append(&mut self, record: &LogRecord) -> Result<(), String> {
+        let mut line = serde_json::to_string(record).map_err(|e| e.to_string())?;
+        line.push('\n');
+        self.log
+            .write_all(line.as_bytes())
+            .map_err(|e| e.to_string())?;
+        self.records_since_snapshot += 1;
+        Ok(())
+    }
+
+    pub fn should_compact(&self) -> bool {
+        self.records_since_snapshot >= self.compact_after_records
+            && !self.compacting.load(Ordering::SeqCst)
+    }
+
+    /// Whether anything has to be replayed on top of the snapshot, including a torn
+    /// record that new appends must not be written after.
+    pub fn has_uncompacted_records(&self) -> bool {
+        self.records_since_snapshot > 0
+            || self.log.metadata().is_ok_and(|metadata| metadata.len() > 0)
+    }
+
+    /// Starts the next generation with an empty log. Its snapshot has to be written with
+    /// the state as of this call, which can happen while appends go on.
+    pub fn start_generation(&mut self) -> Result<PendingSnapshot, String> {
+        let next = self.generation + 1;
+        self.log = open_log(&log_path(&self.dir, next))?;
+        self.compacting.store(true, Ordering::SeqCst);
+        self.generation = next;
+        self.records_since_snapshot = 0;
+        Ok(PendingSnapshot {
+            dir: self.dir.clone(),
+            generation: next,
+            compacting: self.compacting.clone(),
+        })
+    }
+
+    /// Starts the next generation with `state` as its snapshot and an empty log, then
+    /// removes the previous generations.
+    pub fn compact(&mut self, state: Vec<LogRecord>) -> Result<(), String> {
+        self.start_generation()?.write(state)
+    }
+}
+
+impl PendingSnapshot {
+    /// Writes `state` as the snapshot, then removes the generations it replaces.
+    pub fn write(self, state: Vec<LogRecord>) -> Result<(), String> {
+        let written = self.write_snapshot(&state);
+        self.compacting.store(false, Ordering::SeqCst);
+        written?;
+
+        remove_generations_before(&self.dir, self.generation)?;
+        println!(
+            "Compacted write-ahead log into snapshot {} ({} records)",
+            self.generation,
+            state.len()
+        );
+        Ok(())
+    }
+
+    fn write_snapshot(&self, state: &[LogRecord]) -> Result<(), String> {
+        let tmp_path = self
+            .dir
+            .join(format!("snapshot-{}.jsonl.tmp", self.generation));
+
+        let file = File::create(&tmp_path).map_err(|e| e.to_string())?;
+        let mut writer = BufWriter::new(file);
+        for record in state {
+            serde_json::to_writer(&mut writer, record).map_err(|e| e.to_string())?;
+            writer.write_all(b"\n").map_err(|e| e.to_string())?;
+        }
+        let file = writer.into_inner().map_err(|e| e.to_string())?;
+        file.sync_all().map_err(|e| e.to_string())?;
+        fs::rename(&tmp_path, snapshot_path(&self.dir, self.generation)).map_err(|e| e.to_string())
+    }
+}
+
+fn snapshot_path(dir: &Path, generation: u64) -> PathBuf {
+    dir.join(format!("snapshot-{generation}.jsonl"))
+}
+
+fn log_path(dir: &Path, generation: u64) -> PathBuf {
+    dir.join(format!("log-{generation}.jsonl"))
+}
+
+fn open_log(path: &Path) -> Result<File, String> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| e.to_string())
+}
+
+/// The generation a snapshot or log file belongs to.
+fn file_generation(name: &str) -> Option<u64> {
+    let generation = name
+        .strip_prefix("snapshot-")
+        .or_else(|| name.strip_prefix("log-"))?
+        .strip_suffix(".jsonl")?;
+    generation.parse().ok()
+}
+
+/// The newest generation with a complete snapshot, or 0 if nothing was compacted yet.
+fn latest_snapshot(dir: &Path) -> Result<u64, String> {
+    let mut latest = 0;
+    for entry in fs::read_dir(dir).map_err(|e| e.to_string())? {
+        let name = entry.map_err(|e| e.to_string())?.file_name();
+        let generation = name
+            .to_str()
+            .filter(|name| name.starts_with("snapshot-"))
+            .and_then(file_generation);
+
+        if let Some(generation) = generation {
+            latest = latest.max(generation);
+        }
+    }
+    Ok(latest)
+}
+
+fn remove_generations_before(dir: &Path, generation: u64) -> Result<(), String> {
+    for entry in fs::read_dir(dir).map_err(|e| e.to_string())? {
+        let entry = entry.map_err(|e| e.to_string())?;
+        let older = entry
+            .file_name()
+            .to_str()
+            .and_then(file_generation)
+            .is_some_and(|older| older < generation);
+
+        if older {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+    Ok(())
+}
+
+fn read_records(path: &Path) -> Result<Vec<LogRecord>, String> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e.to_string()),
+    };
+
+    let lines = BufReader::new(file)
+        .lines()
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    let mut records = vec![];
+    for (i, line) in lines.iter().enumerate() {
+        match serde_json::from_str(line) {
+            Ok(record) => records.push(record),
+            // The server died halfway through appending the last record.
+            Err(_) if i == lines.len() - 1 => {
+                println!("Ignoring torn record at the end of {}", path.display());
+            }
+            Err(e) => return Err(format!("{}:{}: {e}", path.display(), i + 1)),
+        }
+    }
+    Ok(records)
+}
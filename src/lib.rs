@@ -0,0 +1,5 @@
+pub mod core;
+pub mod example;
+pub mod inmemory_db;
+pub mod server;
+pub mod store;
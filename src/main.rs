@@ -1,7 +1,4 @@
-pub mod core;
-pub mod example;
-pub mod inmemory_db;
-pub mod server;
+use jamesporal::example;
 
 #[tokio::main]
 async fn main() {
@@ -0,0 +1,116 @@
+use chrono::Utc;
+
+use crate::core::activity::ActivityEventType;
+use crate::core::workflow::{
+    ParentClosePolicy, WorkflowCommandType, WorkflowEvent, WorkflowEventType, WorkflowRunId,
+};
+use crate::store::Store;
+
+use super::{terminate, ServerState};
+
+pub async fn is_cancel_requested(db: &dyn Store, workflow_run_id: WorkflowRunId) -> bool {
+    db.get_workflow_run_events(workflow_run_id)
+        .await
+        .iter()
+        .any(|event| event.event_type == WorkflowEventType::CancelRequested)
+}
+
+/// Asks an unfinished run, given its last event, to cancel, along with those of its
+/// children that aren't abandoned. The workflow keeps running so it can clean up: the
+/// activities it is waiting on fail with a `Cancelled` error, and a run that no worker
+/// holds (e.g. one that is sleeping) is handed to a worker again.
+pub async fn request_cancel(state: &ServerState, last_event: WorkflowEvent) {
+    let db = state.db.as_ref();
+    let mut cancelled = vec![last_event];
+    while let Some(last_event) = cancelled.pop() {
+        let workflow_run_id = last_event.run_id;
+        if !record_cancel_request(state, last_event).await {
+            continue;
+        }
+        println!("Cancelling workflow. RunId = {workflow_run_id}");
+
+        let error = format!("Cancelled: workflow run {workflow_run_id} was cancelled");
+        terminate::end_outstanding_activities(
+            state,
+            workflow_run_id,
+            ActivityEventType::Cancelled,
+            error,
+        )
+        .await;
+
+        for command in db.get_workflow_commands(workflow_run_id).await {
+            let WorkflowCommandType::StartChildWorkflow { child_run_id, .. } = command.command_type
+            else {
+                continue;
+            };
+            let Some(child) = db.get_last_workflow_run_event(child_run_id).await else {
+                continue;
+            };
+            if !child.event_type.is_finished()
+                && child.options.parent_close_policy != ParentClosePolicy::Abandon
+            {
+                cancelled.push(child);
+            }
+        }
+    }
+}
+
+/// Records the request and puts the run where a worker executes it: a started run stays
+/// with its worker, anything else is enqueued again. Returns whether it did, which it
+/// doesn't once the run has finished or was already asked to cancel.
+async fn record_cancel_request(state: &ServerState, mut last_event: WorkflowEvent) -> bool {
+    let db = state.db.as_ref();
+    let workflow_run_id = last_event.run_id;
+    loop {
+        if last_event.event_type.is_finished() || is_cancel_requested(db, workflow_run_id).await {
+            return false;
+        }
+
+        let requested = WorkflowEvent {
+            event_type: WorkflowEventType::CancelRequested,
+            payload: "".to_string(),
+            created_at: Utc::now(),
+            fire_at: None,
+            child_run_id: None,
+            ..last_event.clone()
+        };
+        let resumed = match last_event.event_type {
+            WorkflowEventType::Started => WorkflowEvent {
+                created_at: Utc::now(),
+                ..last_event.clone()
+            },
+            _ => {
+                let input = db
+                    .get_first_workflow_run_event(workflow_run_id)
+                    .await
+                    .map_or(last_event.payload.clone(), |enqueued| enqueued.payload);
+                WorkflowEvent {
+                    event_type: WorkflowEventType::Pending,
+                    payload: input,
+                    created_at: Utc::now(),
+                    fire_at: None,
+                    child_run_id: None,
+                    ..last_event.clone()
+                }
+            }
+        };
+
+        if db
+            .add_workflow_events_if_last(&last_event, vec![requested.clone(), resumed.clone()])
+            .await
+        {
+            state.wakeups.workflow_event_recorded(&requested);
+            state.wakeups.workflow_event_recorded(&resumed);
+            // Wakes a workflow that is waiting for a signal.
+            state.wakeups.workflow_signals.notify(workflow_run_id);
+            return true;
+        }
+
+        // The run moved on since it was read, e.g. a worker picked it up or another
+        // request got there first; try again from where it is now.
+        let Some(latest) = db.get_last_workflow_run_event(workflow_run_id).await else {
+            return false;
+        };
+        last_event = latest;
+    }
+}
@@ -0,0 +1,273 @@
+use chrono::Utc;
+
+use crate::core::workflow::{
+    ParentClosePolicy, Workflow, WorkflowCommand, WorkflowCommandType, WorkflowEvent,
+    WorkflowEventType, WorkflowName, WorkflowOptions, WorkflowRunId,
+};
+use crate::store::Store;
+
+use super::{cancel, follows_past_run, terminate, ServerState};
+
+/// Enqueues the child workflow a run started at `sequence` and returns the child's run
+/// id, or `None` if another delivery of the run issued a different command there. A
+/// rerun reuses the child its original run completed there instead.
+pub async fn start_child(
+    state: &ServerState,
+    parent: WorkflowEvent,
+    workflow: Workflow,
+    sequence: i64,
+    input: String,
+    options: WorkflowOptions,
+) -> Option<WorkflowRunId> {
+    let db = state.db.as_ref();
+
+    // A worker that crashed after starting the child starts it again when replaying.
+    if let Some(child_run_id) = recorded_child(db, parent.run_id, sequence).await {
+        return Some(child_run_id);
+    }
+
+    let before_checkpoint = parent
+        .rerun_checkpoint
+        .is_none_or(|checkpoint| sequence < checkpoint);
+    let memoized = match parent.rerun_of.filter(|_| before_checkpoint) {
+        Some(past_workflow_run_id) => {
+            memoized_child(
+                db,
+                past_workflow_run_id,
+                parent.run_id,
+                sequence,
+                &workflow.name,
+                &input,
+            )
+            .await
+        }
+        None => None,
+    };
+
+    let (child_run_id, enqueued) = match memoized {
+        Some(child_run_id) => (child_run_id, None),
+        None => {
+            let pending = WorkflowEvent {
+                workflow_id: workflow.id,
+                run_id: WorkflowRunId::new(),
+                event_type: WorkflowEventType::Pending,
+                rerun_of: None,
+                rerun_checkpoint: None,
+                payload: input.clone(),
+                created_at: Utc::now(),
+                options,
+                fire_at: None,
+                child_run_id: None,
+                parent_run_id: Some(parent.run_id),
+            };
+            db.add_workflow_event(pending.clone()).await;
+            state.wakeups.workflow_event_recorded(&pending);
+            (pending.run_id, Some(pending))
+        }
+    };
+
+    // Recorded after the child is enqueued, so the command never points at a run that
+    // doesn't exist.
+    let recorded = db
+        .add_workflow_command(WorkflowCommand {
+            workflow_run_id: parent.run_id,
+            sequence,
+            command_type: WorkflowCommandType::StartChildWorkflow {
+                name: workflow.name,
+                input,
+                child_run_id,
+            },
+            created_at: Utc::now(),
+        })
+        .await;
+    if recorded {
+        return Some(child_run_id);
+    }
+
+    // Another delivery got there first, so nothing waits for the child just enqueued.
+    if let Some(mut last_event) = enqueued {
+        let reason = format!(
+            "Terminated: its parent {} moved on without it",
+            parent.run_id
+        );
+        while !last_event.event_type.is_finished()
+            && !terminate::terminate(state, last_event.clone(), reason.clone()).await
+        {
+            let Some(latest) = db.get_last_workflow_run_event(child_run_id).await else {
+                break;
+            };
+            last_event = latest;
+        }
+    }
+    recorded_child(db, parent.run_id, sequence).await
+}
+
+/// The child the run started at `sequence`, if it started one there.
+async fn recorded_child(
+    db: &dyn Store,
+    parent_run_id: WorkflowRunId,
+    sequence: i64,
+) -> Option<WorkflowRunId> {
+    db.get_workflow_commands(parent_run_id)
+        .await
+        .into_iter()
+        .find_map(|command| match command.command_type {
+            WorkflowCommandType::StartChildWorkflow { child_run_id, .. }
+                if command.sequence == sequence =>
+            {
+                Some(child_run_id)
+            }
+            _ => None,
+        })
+}
+
+/// The child the original run started at `sequence`, if it succeeded and the rerun
+/// still follows the original run.
+async fn memoized_child(
+    db: &dyn Store,
+    past_workflow_run_id: WorkflowRunId,
+    workflow_run_id: WorkflowRunId,
+    sequence: i64,
+    name: &WorkflowName,
+    input: &str,
+) -> Option<WorkflowRunId> {
+    let command_type = WorkflowCommandType::StartChildWorkflow {
+        name: name.clone(),
+        input: input.to_string(),
+        child_run_id: WorkflowRunId::new(),
+    };
+    if !follows_past_run(
+        db,
+        past_workflow_run_id,
+        workflow_run_id,
+        sequence,
+        &command_type,
+    )
+    .await
+    {
+        return None;
+    }
+
+    let past_child_run_id = db
+        .get_workflow_commands(past_workflow_run_id)
+        .await
+        .into_iter()
+        .find_map(|past| match past.command_type {
+            WorkflowCommandType::StartChildWorkflow { child_run_id, .. }
+                if past.sequence == sequence =>
+            {
+                Some(child_run_id)
+            }
+            _ => None,
+        })?;
+    db.get_last_workflow_run_event(past_child_run_id)
+        .await
+        .filter(|child| child.event_type == WorkflowEventType::Succeeeded)
+        .map(|child| child.run_id)
+}
+
+/// Holds a run, given its `Started` event, until its child `child_run_id` finishes.
+/// Returns whether it does; otherwise the run has moved on since.
+pub async fn wait_for_child(
+    state: &ServerState,
+    started: WorkflowEvent,
+    child_run_id: WorkflowRunId,
+) -> bool {
+    let waiting = WorkflowEvent {
+        event_type: WorkflowEventType::WaitingForChild,
+        payload: "".to_string(),
+        created_at: Utc::now(),
+        fire_at: None,
+        child_run_id: Some(child_run_id),
+        ..started.clone()
+    };
+    if !state
+        .db
+        .add_workflow_events_if_last(&started, vec![waiting.clone()])
+        .await
+    {
+        return false;
+    }
+    println!(
+        "Workflow waiting for child {child_run_id}. RunId = {}",
+        started.run_id
+    );
+    state.wakeups.workflow_event_recorded(&waiting);
+    true
+}
+
+/// Hands a run waiting for its child back to workers once the child has finished. They
+/// replay it up to the child and carry on with the child's result.
+pub async fn resume_parent(
+    state: &ServerState,
+    enqueued: WorkflowEvent,
+    last_event: WorkflowEvent,
+) {
+    let db = state.db.as_ref();
+    let Some(child_run_id) = last_event.child_run_id else {
+        return;
+    };
+    if db.get_completed_workflow(child_run_id).await.is_none() {
+        return;
+    }
+
+    let pending = WorkflowEvent {
+        event_type: WorkflowEventType::Pending,
+        payload: enqueued.payload,
+        created_at: Utc::now(),
+        fire_at: None,
+        child_run_id: None,
+        ..last_event.clone()
+    };
+    if db
+        .add_workflow_events_if_last(&last_event, vec![pending.clone()])
+        .await
+    {
+        println!(
+            "Child finished, resuming workflow. RunId = {}",
+            last_event.run_id
+        );
+        state.wakeups.workflow_event_recorded(&pending);
+    }
+}
+
+/// Applies the parent-close policy of every unfinished child of a run that just
+/// finished. Children terminated here close their own children in turn.
+pub async fn close_children(state: &ServerState, workflow_run_id: WorkflowRunId) {
+    let db = state.db.as_ref();
+    let mut closed = vec![workflow_run_id];
+    while let Some(parent_run_id) = closed.pop() {
+        for command in db.get_workflow_commands(parent_run_id).await {
+            let WorkflowCommandType::StartChildWorkflow { child_run_id, .. } = command.command_type
+            else {
+                continue;
+            };
+            let Some(mut child) = db.get_last_workflow_run_event(child_run_id).await else {
+                continue;
+            };
+            if child.event_type.is_finished() {
+                continue;
+            }
+
+            match child.options.parent_close_policy {
+                ParentClosePolicy::Terminate => {
+                    let reason = format!("Terminated: parent workflow run {parent_run_id} closed");
+                    // The child may move on in the meantime, e.g. when a worker picks it
+                    // up. Only a child terminated here has its own children closed.
+                    loop {
+                        if terminate::terminate(state, child, reason.clone()).await {
+                            closed.push(child_run_id);
+                            break;
+                        }
+                        match db.get_last_workflow_run_event(child_run_id).await {
+                            Some(latest) if !latest.event_type.is_finished() => child = latest,
+                            _ => break,
+                        }
+                    }
+                }
+                ParentClosePolicy::RequestCancel => cancel::request_cancel(state, child).await,
+                ParentClosePolicy::Abandon => {}
+            }
+        }
+    }
+}
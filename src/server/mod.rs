@@ -0,0 +1,1039 @@
+mod cancel;
+mod children;
+mod queries;
+mod rerun;
+mod terminate;
+mod timeouts;
+mod timers;
+mod wakeups;
+mod watch;
+
+use std::future::IntoFuture;
+use std::sync::Arc;
+
+use crate::core::activity::{
+    Activity, ActivityEvent, ActivityEventType, ActivityHeartbeat, ActivityId,
+};
+use crate::core::worker_events::{
+    PollActivityCompletion, PollActivityResponse, PollWorkflowCompletion, PollWorkflowResponse,
+    ServerEvent, WorkerEvent, WorkflowQuery,
+};
+use crate::core::workflow::{
+    QueryId, Workflow, WorkflowCommand, WorkflowCommandType, WorkflowEvent, WorkflowEventType,
+    WorkflowId, WorkflowRunId, WorkflowSignal,
+};
+use crate::inmemory_db::Db;
+use crate::store::Store;
+use axum::extract::State;
+use axum::response::IntoResponse;
+use axum::{Json, Router};
+use chrono::{DateTime, Utc};
+use queries::Queries;
+use wakeups::Wakeups;
+
+pub struct Server {
+    state: ServerState,
+}
+
+#[derive(Clone)]
+pub struct ServerState {
+    db: Arc<dyn Store>,
+    wakeups: Arc<Wakeups>,
+    queries: Arc<Queries>,
+}
+
+/// Records a failed (or timed out) attempt and, if the retry policy allows it, schedules
+/// the next attempt after the backoff delay. Nothing is recorded unless `attempt`, the
+/// event it failed from, is still the run's last event; returns whether it was.
+async fn record_failed_attempt(
+    state: &ServerState,
+    scheduled: ActivityEvent,
+    attempt: &ActivityEvent,
+    failed: ActivityEvent,
+) -> bool {
+    let db = state.db.as_ref();
+    let retry_policy = &scheduled.options.retry_policy;
+    let attempt_number = failed.attempt_number;
+
+    let now = Utc::now();
+    let retry_at = retry_policy.next_retry_at(now, attempt_number);
+    let within_schedule_to_close = timeouts::deadline(
+        scheduled.created_at,
+        scheduled.options.schedule_to_close_timeout,
+    )
+    .is_none_or(|deadline| retry_at < deadline);
+
+    if retry_policy.is_retryable(&failed.payload)
+        && attempt_number < retry_policy.max_attempts
+        && within_schedule_to_close
+    {
+        println!(
+            "Activity attempt {attempt_number}/{} failed, retrying in {}ms. RunId = {}",
+            retry_policy.max_attempts,
+            (retry_at - now).num_milliseconds(),
+            failed.activity_run_id
+        );
+        let retry = ActivityEvent {
+            event_type: ActivityEventType::Pending,
+            payload: scheduled.payload.clone(),
+            created_at: now,
+            attempt_number: attempt_number + 1,
+            scheduled_for: Some(retry_at),
+            ..failed.clone()
+        };
+        let events = vec![failed, retry];
+        if !db
+            .add_activity_events_if_last(attempt, events.clone())
+            .await
+        {
+            return false;
+        }
+        state.wakeups.activity_events_recorded(&events);
+    } else {
+        if !db
+            .add_activity_events_if_last(attempt, vec![failed.clone()])
+            .await
+        {
+            return false;
+        }
+        state.wakeups.activity_events_recorded(&[failed]);
+    }
+    true
+}
+
+/// When the current delivery of a run started: the first `Started` since the run was last
+/// enqueued. Later `Started` events (e.g. after a cancellation request) belong to the same
+/// delivery.
+async fn delivery_started_at(
+    db: &dyn Store,
+    workflow_run_id: WorkflowRunId,
+) -> Option<DateTime<Utc>> {
+    let events = db.get_workflow_run_events(workflow_run_id).await;
+    let enqueued = events
+        .iter()
+        .rposition(|e| e.event_type == WorkflowEventType::Pending)?;
+    events[enqueued..]
+        .iter()
+        .find(|e| e.event_type == WorkflowEventType::Started)
+        .map(|e| e.created_at)
+}
+
+/// Whether the worker that got the delivery started at `started_at` still executes the
+/// run, rather than one it was handed to since.
+async fn is_current_delivery(
+    db: &dyn Store,
+    workflow_run_id: WorkflowRunId,
+    started_at: DateTime<Utc>,
+) -> bool {
+    delivery_started_at(db, workflow_run_id).await == Some(started_at)
+}
+
+/// Whether the rerun has issued exactly the same commands as the original run up to
+/// `sequence`, where it is now issuing `command_type`. Once the two histories diverge,
+/// everything after is re-executed.
+async fn follows_past_run(
+    db: &dyn Store,
+    past_workflow_run_id: WorkflowRunId,
+    workflow_run_id: WorkflowRunId,
+    sequence: i64,
+    command_type: &WorkflowCommandType,
+) -> bool {
+    let past_commands = db.get_workflow_commands(past_workflow_run_id).await;
+    let commands = db.get_workflow_commands(workflow_run_id).await;
+
+    let issued = commands
+        .iter()
+        .filter(|c| c.sequence < sequence)
+        .map(|c| (c.sequence, &c.command_type))
+        .chain([(sequence, command_type)]);
+
+    for (command_sequence, command_type) in issued {
+        let same_as_past = past_commands
+            .iter()
+            .find(|past| past.sequence == command_sequence)
+            .is_some_and(|past| past.command_type.is_same_command(command_type));
+
+        if !same_as_past {
+            if command_sequence == sequence {
+                println!(
+                    "Rerun diverged from RunId = {past_workflow_run_id} at sequence {sequence}, re-executing from here"
+                );
+            }
+            return false;
+        }
+    }
+    true
+}
+
+/// Finds the result of the activity the rerun workflow scheduled at `sequence`, if the
+/// rerun still follows the original run.
+async fn memoized_activity_result(
+    db: &dyn Store,
+    past_workflow_run_id: WorkflowRunId,
+    workflow_run_id: WorkflowRunId,
+    activity_id: ActivityId,
+    sequence: i64,
+    command_type: &WorkflowCommandType,
+) -> Option<ActivityEvent> {
+    if !follows_past_run(
+        db,
+        past_workflow_run_id,
+        workflow_run_id,
+        sequence,
+        command_type,
+    )
+    .await
+    {
+        return None;
+    }
+
+    db.get_success_activity_event_for_sequence(past_workflow_run_id, activity_id, sequence)
+        .await
+}
+
+fn workflow_completion(completed: WorkflowEvent) -> PollWorkflowCompletion {
+    match completed.event_type {
+        WorkflowEventType::Succeeeded => PollWorkflowCompletion {
+            workflow_run_id: completed.run_id,
+            status: completed.event_type,
+            result: completed.payload,
+            error: "".to_string(),
+        },
+        _ => PollWorkflowCompletion {
+            workflow_run_id: completed.run_id,
+            status: completed.event_type,
+            error: completed.payload,
+            result: "".to_string(),
+        },
+    }
+}
+
+/// Signals the original run took at `sequence`, so a rerun doesn't wait for approvals
+/// and the like that were already given.
+async fn memoized_signals(
+    db: &dyn Store,
+    past_workflow_run_id: WorkflowRunId,
+    workflow_run_id: WorkflowRunId,
+    sequence: i64,
+    signal_name: &str,
+    wait: bool,
+) -> Option<Vec<String>> {
+    let command_type = WorkflowCommandType::ReceiveSignals {
+        signal_name: signal_name.to_string(),
+        payloads: vec![],
+        memoized: true,
+    };
+    if !follows_past_run(
+        db,
+        past_workflow_run_id,
+        workflow_run_id,
+        sequence,
+        &command_type,
+    )
+    .await
+    {
+        return None;
+    }
+
+    let past_command = db
+        .get_workflow_commands(past_workflow_run_id)
+        .await
+        .into_iter()
+        .find(|past| past.sequence == sequence)?;
+    match past_command.command_type {
+        // Waiting takes exactly one signal.
+        WorkflowCommandType::ReceiveSignals { payloads, .. } if !wait || payloads.len() == 1 => {
+            Some(payloads)
+        }
+        _ => None,
+    }
+}
+
+/// The next signals named `signal_name` after the `taken` ones the run already received:
+/// the first one when waiting, or all of them. `None` while waiting for one to arrive.
+async fn next_signals(
+    db: &dyn Store,
+    workflow_run_id: WorkflowRunId,
+    signal_name: &str,
+    taken: usize,
+    wait: bool,
+) -> Option<Vec<String>> {
+    let mut payloads: Vec<String> = db
+        .get_workflow_signals(workflow_run_id)
+        .await
+        .into_iter()
+        .filter(|signal| signal.signal_name == signal_name)
+        .skip(taken)
+        .map(|signal| signal.payload)
+        .collect();
+
+    if !wait {
+        return Some(payloads);
+    }
+    payloads.truncate(1);
+    (!payloads.is_empty()).then_some(payloads)
+}
+
+fn activity_completion(completed: ActivityEvent) -> PollActivityCompletion {
+    let (result, error) = match completed.event_type {
+        ActivityEventType::Succeeeded => (completed.payload, "".to_string()),
+        _ => ("".to_string(), completed.payload),
+    };
+    PollActivityCompletion {
+        activity_run_id: completed.activity_run_id,
+        result,
+        error,
+        completed_at: completed.created_at,
+    }
+}
+
+async fn handle_worker_event(
+    State(state): State<ServerState>,
+    Json(event): Json<WorkerEvent>,
+) -> impl IntoResponse {
+    let db = state.db.clone();
+    match event {
+        WorkerEvent::RegisterWorkflow { name } => {
+            let exists = db.workflow_exists(&name).await;
+            if !exists {
+                db.add_workflow(Workflow {
+                    name,
+                    id: WorkflowId::new(),
+                })
+                .await;
+            }
+        }
+        WorkerEvent::EnqueuWorkflow {
+            name,
+            input,
+            workflow_run_id,
+            options,
+        } => {
+            if let Some(workflow) = db.get_workflow_by_name(&name).await {
+                let pending = WorkflowEvent {
+                    workflow_id: workflow.id,
+                    run_id: workflow_run_id,
+                    event_type: WorkflowEventType::Pending,
+                    rerun_of: None,
+                    rerun_checkpoint: None,
+                    payload: input,
+                    created_at: Utc::now(),
+                    options,
+                    fire_at: None,
+                    child_run_id: None,
+                    parent_run_id: None,
+                };
+                db.add_workflow_event(pending.clone()).await;
+                state.wakeups.workflow_event_recorded(&pending);
+            }
+        }
+        WorkerEvent::RegisterActivity { name } => {
+            let existing = db.activity_exists(&name).await;
+            if !existing {
+                db.add_activity(Activity {
+                    name,
+                    id: ActivityId::new(),
+                })
+                .await
+            }
+            return Json(ServerEvent::GeneralSuccess { success: true });
+        }
+        WorkerEvent::EnqueuActivity {
+            name,
+            input,
+            activity_run_id,
+            workflow_run_id,
+            started_at,
+            sequence,
+            options,
+        } => {
+            if let Err(error) = options.retry_policy.validate() {
+                return Json(ServerEvent::Rejected { error });
+            }
+            if let Some(workflow) = db.get_last_workflow_run_event(workflow_run_id).await {
+                if workflow.event_type.is_finished()
+                    || !is_current_delivery(db.as_ref(), workflow_run_id, started_at).await
+                {
+                    // E.g. terminated while its worker was still executing it, or handed
+                    // to another worker that issues its own commands.
+                    return Json(ServerEvent::GeneralSuccess { success: false });
+                }
+                if let Some(activity) = db.get_activity_by_name(&name).await {
+                    let command_type = WorkflowCommandType::ScheduleActivity {
+                        name,
+                        input: input.clone(),
+                        activity_run_id,
+                    };
+                    if !db
+                        .add_workflow_command(WorkflowCommand {
+                            workflow_run_id,
+                            sequence,
+                            command_type: command_type.clone(),
+                            created_at: Utc::now(),
+                        })
+                        .await
+                    {
+                        // Another delivery already issued a command at this sequence.
+                        return Json(ServerEvent::GeneralSuccess { success: false });
+                    }
+
+                    let before_checkpoint = workflow
+                        .rerun_checkpoint
+                        .is_none_or(|checkpoint| sequence < checkpoint);
+
+                    if let Some(past_workflow_run_id) =
+                        workflow.rerun_of.filter(|_| before_checkpoint)
+                    {
+                        if let Some(past_success_of_activity) = memoized_activity_result(
+                            db.as_ref(),
+                            past_workflow_run_id,
+                            workflow_run_id,
+                            activity.id,
+                            sequence,
+                            &command_type,
+                        )
+                        .await
+                        {
+                            let memoized = ActivityEvent {
+                                activity_id: activity.id,
+                                activity_run_id,
+                                workflow_run_id,
+                                event_type: ActivityEventType::Succeeeded,
+                                payload: past_success_of_activity.payload,
+                                created_at: Utc::now(),
+                                attempt_number: 1,
+                                sequence,
+                                options,
+                                scheduled_for: None,
+                            };
+                            db.add_activity_event(memoized.clone()).await;
+                            state.wakeups.activity_events_recorded(&[memoized]);
+
+                            return Json(ServerEvent::GeneralSuccess { success: true });
+                        }
+                    }
+                    let pending = ActivityEvent {
+                        activity_id: activity.id,
+                        activity_run_id,
+                        workflow_run_id,
+                        event_type: ActivityEventType::Pending,
+                        payload: input,
+                        created_at: Utc::now(),
+                        attempt_number: 1,
+                        sequence,
+                        options,
+                        scheduled_for: None,
+                    };
+                    db.add_activity_event(pending.clone()).await;
+                    state.wakeups.activity_events_recorded(&[pending]);
+                }
+            }
+        }
+        WorkerEvent::PollWorkflow { name } => {
+            let Some(workflow) = db.get_workflow_by_name(&name).await else {
+                return Json(ServerEvent::NotFound);
+            };
+            let claimed = state
+                .wakeups
+                .workflow_tasks
+                .long_poll(workflow.id, || db.claim_pending_workflow(name.clone()))
+                .await;
+            let Some(pending) = claimed else {
+                return Json(ServerEvent::Empty);
+            };
+            let Some(started) = db.get_last_workflow_run_event(pending.run_id).await else {
+                return Json(ServerEvent::Empty);
+            };
+            state.wakeups.workflow_event_recorded(&started);
+
+            return Json(ServerEvent::PollWorkflowResponse(PollWorkflowResponse {
+                workflow_run_id: pending.run_id,
+                rerun_of_workflow_run_id: pending.rerun_of,
+                workflow_id: pending.workflow_id,
+                name,
+                input: pending.payload.clone(),
+                history: db.get_workflow_commands(pending.run_id).await,
+                cancel_requested: cancel::is_cancel_requested(db.as_ref(), pending.run_id).await,
+                started_at: started.created_at,
+            }));
+        }
+        WorkerEvent::CompleteWorkflow {
+            result,
+            error,
+            workflow_id,
+            workflow_run_id,
+            rerun_of_workflow_run_id,
+            started_at,
+        } => {
+            let Some(last_event) = db.get_last_workflow_run_event(workflow_run_id).await else {
+                return Json(ServerEvent::NotFound);
+            };
+            if last_event.event_type != WorkflowEventType::Started
+                || delivery_started_at(db.as_ref(), workflow_run_id).await != Some(started_at)
+            {
+                // The run already timed out while the worker was still executing it, or
+                // was handed to another worker after this one went quiet.
+                return Json(ServerEvent::GeneralSuccess { success: false });
+            }
+
+            println!("Completed Workflow, RunId = {}\n", workflow_run_id);
+            let completed = if error.is_empty() {
+                WorkflowEvent {
+                    workflow_id,
+                    run_id: workflow_run_id,
+                    event_type: WorkflowEventType::Succeeeded,
+                    rerun_of: rerun_of_workflow_run_id,
+                    rerun_checkpoint: last_event.rerun_checkpoint,
+                    payload: result,
+                    created_at: Utc::now(),
+                    options: last_event.options.clone(),
+                    fire_at: None,
+                    child_run_id: None,
+                    parent_run_id: last_event.parent_run_id,
+                }
+            } else {
+                let event_type = if cancel::is_cancel_requested(db.as_ref(), workflow_run_id).await
+                {
+                    WorkflowEventType::Cancelled
+                } else {
+                    WorkflowEventType::Failed
+                };
+                WorkflowEvent {
+                    workflow_id,
+                    run_id: workflow_run_id,
+                    event_type,
+                    rerun_of: rerun_of_workflow_run_id,
+                    rerun_checkpoint: last_event.rerun_checkpoint,
+                    payload: error,
+                    created_at: Utc::now(),
+                    options: last_event.options.clone(),
+                    fire_at: None,
+                    child_run_id: None,
+                    parent_run_id: last_event.parent_run_id,
+                }
+            };
+            if !db
+                .add_workflow_events_if_last(&last_event, vec![completed.clone()])
+                .await
+            {
+                return Json(ServerEvent::GeneralSuccess { success: false });
+            }
+            state.wakeups.workflow_event_recorded(&completed);
+            children::close_children(&state, workflow_run_id).await;
+        }
+        WorkerEvent::PollWorkflowCompletion { workflow_run_id } => {
+            let completed = state
+                .wakeups
+                .workflow_completions
+                .long_poll(workflow_run_id, || {
+                    db.get_completed_workflow(workflow_run_id)
+                })
+                .await;
+            let Some(completed) = completed else {
+                return Json(ServerEvent::Empty);
+            };
+
+            return Json(ServerEvent::PollWorkflowCompletion(workflow_completion(
+                completed,
+            )));
+        }
+        WorkerEvent::PollActivity { name } => {
+            let Some(activity) = db.get_activity_by_name(&name).await else {
+                return Json(ServerEvent::NotFound);
+            };
+            let claimed = state
+                .wakeups
+                .activity_tasks
+                .long_poll(activity.id, || db.claim_pending_activity(name.clone()))
+                .await;
+            let Some(pending) = claimed else {
+                return Json(ServerEvent::Empty);
+            };
+            if let Some(started) = db
+                .get_last_activity_run_event(pending.activity_run_id)
+                .await
+            {
+                state.wakeups.activity_events_recorded(&[started]);
+            }
+
+            return Json(ServerEvent::PollActivityResponse(PollActivityResponse {
+                activity_run_id: pending.activity_run_id,
+                activity_id: pending.activity_id,
+                workflow_run_id: pending.workflow_run_id,
+                name,
+                input: pending.payload.clone(),
+                max_attempts: pending.options.retry_policy.max_attempts,
+                attempt_number: pending.attempt_number,
+                heartbeat_details: db
+                    .get_last_activity_heartbeat(pending.activity_run_id)
+                    .await
+                    .map(|heartbeat| heartbeat.details),
+            }));
+        }
+        WorkerEvent::CompleteActivity {
+            result,
+            error,
+            activity_id,
+            activity_run_id,
+            workflow_run_id,
+            attempt_number,
+        } => {
+            let (Some(scheduled), Some(last_event)) = (
+                db.get_first_activity_run_event(activity_run_id).await,
+                db.get_last_activity_run_event(activity_run_id).await,
+            ) else {
+                return Json(ServerEvent::NotFound);
+            };
+
+            if last_event.event_type != ActivityEventType::Started
+                || last_event.attempt_number != attempt_number
+            {
+                // This attempt already timed out (and may have been retried since).
+                return Json(ServerEvent::GeneralSuccess { success: false });
+            }
+
+            if error.is_empty() {
+                let succeeded = ActivityEvent {
+                    activity_id,
+                    activity_run_id,
+                    workflow_run_id,
+                    event_type: ActivityEventType::Succeeeded,
+                    payload: result,
+                    created_at: Utc::now(),
+                    attempt_number,
+                    sequence: last_event.sequence,
+                    options: scheduled.options,
+                    scheduled_for: None,
+                };
+                if !db
+                    .add_activity_events_if_last(&last_event, vec![succeeded.clone()])
+                    .await
+                {
+                    // It timed out while the server was recording its completion.
+                    return Json(ServerEvent::GeneralSuccess { success: false });
+                }
+                state.wakeups.activity_events_recorded(&[succeeded]);
+            } else {
+                let failed = ActivityEvent {
+                    activity_id,
+                    activity_run_id,
+                    workflow_run_id,
+                    event_type: ActivityEventType::Failed,
+                    payload: error,
+                    created_at: Utc::now(),
+                    attempt_number,
+                    sequence: last_event.sequence,
+                    options: scheduled.options.clone(),
+                    scheduled_for: None,
+                };
+                if !record_failed_attempt(&state, scheduled, &last_event, failed).await {
+                    return Json(ServerEvent::GeneralSuccess { success: false });
+                }
+            }
+        }
+        WorkerEvent::SignalWorkflow {
+            workflow_run_id,
+            signal_name,
+            payload,
+        } => {
+            let Some(last_event) = db.get_last_workflow_run_event(workflow_run_id).await else {
+                return Json(ServerEvent::NotFound);
+            };
+            if last_event.event_type.is_finished() {
+                return Json(ServerEvent::GeneralSuccess { success: false });
+            }
+
+            db.add_workflow_signal(WorkflowSignal {
+                workflow_run_id,
+                signal_name,
+                payload,
+                created_at: Utc::now(),
+            })
+            .await;
+            state.wakeups.workflow_signals.notify(workflow_run_id);
+        }
+        WorkerEvent::CancelWorkflow { workflow_run_id } => {
+            let Some(last_event) = db.get_last_workflow_run_event(workflow_run_id).await else {
+                return Json(ServerEvent::NotFound);
+            };
+            if last_event.event_type.is_finished() {
+                return Json(ServerEvent::GeneralSuccess { success: false });
+            }
+
+            cancel::request_cancel(&state, last_event).await;
+        }
+        WorkerEvent::ReceiveSignals {
+            workflow_run_id,
+            started_at,
+            sequence,
+            signal_name,
+            wait,
+        } => {
+            let Some(workflow) = db.get_last_workflow_run_event(workflow_run_id).await else {
+                return Json(ServerEvent::NotFound);
+            };
+            if workflow.event_type.is_finished()
+                || !is_current_delivery(db.as_ref(), workflow_run_id, started_at).await
+            {
+                return Json(ServerEvent::GeneralSuccess { success: false });
+            }
+
+            let before_checkpoint = workflow
+                .rerun_checkpoint
+                .is_none_or(|checkpoint| sequence < checkpoint);
+            let memoized = match workflow.rerun_of.filter(|_| before_checkpoint) {
+                Some(past_workflow_run_id) => {
+                    memoized_signals(
+                        db.as_ref(),
+                        past_workflow_run_id,
+                        workflow_run_id,
+                        sequence,
+                        &signal_name,
+                        wait,
+                    )
+                    .await
+                }
+                None => None,
+            };
+
+            let is_memoized = memoized.is_some();
+            let payloads = match memoized {
+                Some(payloads) => payloads,
+                None => {
+                    let taken = db
+                        .get_workflow_commands(workflow_run_id)
+                        .await
+                        .into_iter()
+                        .filter(|c| c.sequence < sequence)
+                        .map(|c| match c.command_type {
+                            // Memoized signals came from the original run, not this one's.
+                            WorkflowCommandType::ReceiveSignals {
+                                signal_name: name,
+                                payloads,
+                                memoized: false,
+                            } if name == signal_name => payloads.len(),
+                            _ => 0,
+                        })
+                        .sum();
+
+                    let found = if wait {
+                        // A cancelled run stops waiting, as the signal may never come.
+                        state
+                            .wakeups
+                            .workflow_signals
+                            .long_poll(workflow_run_id, || async {
+                                match next_signals(
+                                    db.as_ref(),
+                                    workflow_run_id,
+                                    &signal_name,
+                                    taken,
+                                    wait,
+                                )
+                                .await
+                                {
+                                    Some(payloads) => Some(Some(payloads)),
+                                    None => {
+                                        cancel::is_cancel_requested(db.as_ref(), workflow_run_id)
+                                            .await
+                                            .then_some(None)
+                                    }
+                                }
+                            })
+                            .await
+                    } else {
+                        Some(
+                            next_signals(db.as_ref(), workflow_run_id, &signal_name, taken, wait)
+                                .await,
+                        )
+                    };
+                    match found {
+                        Some(Some(payloads)) => payloads,
+                        Some(None) => return Json(ServerEvent::Cancelled),
+                        None => return Json(ServerEvent::Empty),
+                    }
+                }
+            };
+
+            let recorded = db
+                .add_workflow_command(WorkflowCommand {
+                    workflow_run_id,
+                    sequence,
+                    command_type: WorkflowCommandType::ReceiveSignals {
+                        signal_name,
+                        payloads: payloads.clone(),
+                        memoized: is_memoized,
+                    },
+                    created_at: Utc::now(),
+                })
+                .await;
+            if !recorded {
+                return Json(ServerEvent::GeneralSuccess { success: false });
+            }
+            return Json(ServerEvent::Signals { payloads });
+        }
+        WorkerEvent::StartChildWorkflow {
+            workflow_run_id,
+            started_at,
+            sequence,
+            name,
+            input,
+            options,
+        } => {
+            let Some(parent) = db.get_last_workflow_run_event(workflow_run_id).await else {
+                return Json(ServerEvent::NotFound);
+            };
+            if parent.event_type != WorkflowEventType::Started
+                || !is_current_delivery(db.as_ref(), workflow_run_id, started_at).await
+            {
+                return Json(ServerEvent::GeneralSuccess { success: false });
+            }
+            let Some(workflow) = db.get_workflow_by_name(&name).await else {
+                return Json(ServerEvent::NotFound);
+            };
+
+            let Some(child_run_id) =
+                children::start_child(&state, parent, workflow, sequence, input, options).await
+            else {
+                return Json(ServerEvent::GeneralSuccess { success: false });
+            };
+            return Json(ServerEvent::ChildWorkflowStarted { child_run_id });
+        }
+        WorkerEvent::StartTimer {
+            workflow_run_id,
+            started_at,
+            sequence,
+            fire_at,
+        } => {
+            let Some(last_event) = db.get_last_workflow_run_event(workflow_run_id).await else {
+                return Json(ServerEvent::NotFound);
+            };
+            if last_event.event_type != WorkflowEventType::Started
+                || !is_current_delivery(db.as_ref(), workflow_run_id, started_at).await
+            {
+                return Json(ServerEvent::GeneralSuccess { success: false });
+            }
+            if fire_at > Utc::now()
+                && cancel::is_cancel_requested(db.as_ref(), workflow_run_id).await
+            {
+                return Json(ServerEvent::Cancelled);
+            }
+
+            let Some(sleeping) = timers::start_timer(&state, last_event, sequence, fire_at).await
+            else {
+                return Json(ServerEvent::GeneralSuccess { success: false });
+            };
+            return Json(ServerEvent::TimerStarted { sleeping });
+        }
+        WorkerEvent::WaitForChildWorkflow {
+            workflow_run_id,
+            child_run_id,
+        } => {
+            let Some(parent) = db.get_last_workflow_run_event(workflow_run_id).await else {
+                return Json(ServerEvent::NotFound);
+            };
+            if parent.event_type != WorkflowEventType::Started {
+                return Json(ServerEvent::GeneralSuccess { success: false });
+            }
+            if let Some(completed) = db.get_completed_workflow(child_run_id).await {
+                return Json(ServerEvent::PollWorkflowCompletion(workflow_completion(
+                    completed,
+                )));
+            }
+            if cancel::is_cancel_requested(db.as_ref(), workflow_run_id).await {
+                return Json(ServerEvent::Cancelled);
+            }
+
+            if !children::wait_for_child(&state, parent, child_run_id).await {
+                return Json(ServerEvent::GeneralSuccess { success: false });
+            }
+            return Json(ServerEvent::WaitingForChild);
+        }
+        WorkerEvent::QueryWorkflow {
+            workflow_run_id,
+            query_name,
+            args,
+        } => {
+            let Some(last_event) = db.get_last_workflow_run_event(workflow_run_id).await else {
+                return Json(ServerEvent::NotFound);
+            };
+            let answer = if last_event.event_type.is_finished() {
+                Err(format!(
+                    "NotRunning: workflow run {workflow_run_id} has already finished"
+                ))
+            } else if last_event.event_type != WorkflowEventType::Started {
+                // No worker is executing it to answer, e.g. it is still queued.
+                Err(format!(
+                    "NotRunning: workflow run {workflow_run_id} is not being executed"
+                ))
+            } else {
+                let query = WorkflowQuery {
+                    query_id: QueryId::new(),
+                    query_name,
+                    args,
+                };
+                state.queries.ask(workflow_run_id, query).await
+            };
+
+            return match answer {
+                Ok(result) => Json(ServerEvent::QueryResult {
+                    result,
+                    error: "".to_string(),
+                }),
+                Err(error) => Json(ServerEvent::QueryResult {
+                    result: "".to_string(),
+                    error,
+                }),
+            };
+        }
+        WorkerEvent::PollWorkflowQueries { workflow_run_id } => {
+            return match state.queries.poll(workflow_run_id).await {
+                Some(queries) => Json(ServerEvent::WorkflowQueries(queries)),
+                None => Json(ServerEvent::Empty),
+            };
+        }
+        WorkerEvent::AnswerWorkflowQuery {
+            query_id,
+            result,
+            error,
+        } => {
+            let answer = if error.is_empty() {
+                Ok(result)
+            } else {
+                Err(error)
+            };
+            let success = state.queries.answer(query_id, answer);
+            return Json(ServerEvent::GeneralSuccess { success });
+        }
+        WorkerEvent::RecordWorkflowHeartbeat {
+            workflow_run_id,
+            started_at,
+        } => {
+            let Some(last_event) = db.get_last_workflow_run_event(workflow_run_id).await else {
+                return Json(ServerEvent::NotFound);
+            };
+            if last_event.event_type.is_finished()
+                || delivery_started_at(db.as_ref(), workflow_run_id).await != Some(started_at)
+            {
+                // E.g. terminated, or handed to another worker, so this one can stop
+                // executing it.
+                return Json(ServerEvent::GeneralSuccess { success: false });
+            }
+            db.record_workflow_heartbeat(workflow_run_id).await;
+            if cancel::is_cancel_requested(db.as_ref(), workflow_run_id).await {
+                return Json(ServerEvent::Cancelled);
+            }
+        }
+        WorkerEvent::RecordActivityHeartbeat {
+            activity_run_id,
+            attempt_number,
+            details,
+        } => {
+            let Some(last_event) = db.get_last_activity_run_event(activity_run_id).await else {
+                return Json(ServerEvent::NotFound);
+            };
+            if last_event.event_type != ActivityEventType::Started
+                || last_event.attempt_number != attempt_number
+            {
+                if last_event.event_type == ActivityEventType::Cancelled {
+                    return Json(ServerEvent::Cancelled);
+                }
+                return Json(ServerEvent::GeneralSuccess { success: false });
+            }
+
+            db.record_activity_heartbeat(ActivityHeartbeat {
+                activity_run_id,
+                attempt_number,
+                details,
+                created_at: Utc::now(),
+            })
+            .await;
+        }
+        WorkerEvent::PollActivityCompletion { activity_run_id } => {
+            let completed = state
+                .wakeups
+                .activity_completions
+                .long_poll(activity_run_id, || {
+                    db.get_completed_activity(activity_run_id)
+                })
+                .await;
+            let Some(completed) = completed else {
+                return Json(ServerEvent::Empty);
+            };
+
+            return Json(ServerEvent::PollActivityCompletion(activity_completion(
+                completed,
+            )));
+        }
+        WorkerEvent::GetActivityCompletions { activity_run_ids } => {
+            let completions = state
+                .wakeups
+                .activity_completions
+                .long_poll_any(&activity_run_ids, || async {
+                    let mut completions = vec![];
+                    for &activity_run_id in &activity_run_ids {
+                        if let Some(completed) = db.get_completed_activity(activity_run_id).await {
+                            completions.push(activity_completion(completed));
+                        }
+                    }
+                    (!completions.is_empty() || activity_run_ids.is_empty()).then_some(completions)
+                })
+                .await;
+            return match completions {
+                Some(completions) => Json(ServerEvent::ActivityCompletions(completions)),
+                None => Json(ServerEvent::Empty),
+            };
+        }
+    };
+
+    Json(ServerEvent::GeneralSuccess { success: true })
+}
+
+impl Server {
+    pub fn new(store: impl Store + 'static) -> Self {
+        Self {
+            state: ServerState {
+                db: Arc::new(store),
+                wakeups: Arc::new(Wakeups::default()),
+                queries: Arc::new(Queries::default()),
+            },
+        }
+    }
+
+    pub async fn run(self) {
+        let listener = tokio::net::TcpListener::bind("localhost:8080")
+            .await
+            .expect("Could not start server");
+
+        println!("Starting service on 0.0.0.0:8080");
+        self.serve(listener).await
+    }
+
+    /// Serves on `listener`, e.g. one bound to an ephemeral port.
+    pub async fn serve(self, listener: tokio::net::TcpListener) {
+        let app = Router::new()
+            .route("/worker_event", axum::routing::post(handle_worker_event))
+            .route(
+                "/rerun_workflow",
+                axum::routing::post(rerun::handle_rerun_workflow),
+            )
+            .route(
+                "/terminate_workflow",
+                axum::routing::post(terminate::handle_terminate_workflow),
+            )
+            .route("/watch", axum::routing::get(watch::handle_watch))
+            .with_state(self.state.clone());
+
+        // Enforced in this task rather than a spawned one, so that aborting it stops the
+        // whole server.
+        tokio::select! {
+            res = axum::serve(listener, app).into_future() => res.expect("Server crashed"),
+            _ = timeouts::enforce_timeouts(self.state.clone()) => {}
+        }
+    }
+}
+
+impl Default for Server {
+    fn default() -> Self {
+        Self::new(Db::new())
+    }
+}
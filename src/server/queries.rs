@@ -0,0 +1,75 @@
+use std::time::Duration;
+
+use dashmap::DashMap;
+use tokio::sync::oneshot;
+
+use crate::core::worker_events::WorkflowQuery;
+use crate::core::workflow::{QueryId, WorkflowRunId};
+
+use super::wakeups::Channels;
+
+/// How long the client asking a query waits for the worker executing the run to answer.
+pub const QUERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Queries are relayed between the client and the worker executing the run and never
+/// stored; a query nobody answers in time fails.
+#[derive(Default)]
+pub struct Queries {
+    /// Queries the run's worker hasn't picked up yet.
+    pending: DashMap<WorkflowRunId, Vec<WorkflowQuery>>,
+    /// Where to send the answer to each query that is still waiting for one.
+    waiting: DashMap<QueryId, oneshot::Sender<Result<String, String>>>,
+    asked: Channels<WorkflowRunId>,
+}
+
+impl Queries {
+    /// Queues `query` for the worker executing `workflow_run_id` and waits for its answer.
+    pub async fn ask(
+        &self,
+        workflow_run_id: WorkflowRunId,
+        query: WorkflowQuery,
+    ) -> Result<String, String> {
+        let query_id = query.query_id;
+        let (tx, rx) = oneshot::channel();
+        self.waiting.insert(query_id, tx);
+        self.pending.entry(workflow_run_id).or_default().push(query);
+        self.asked.notify(workflow_run_id);
+
+        let answer = tokio::time::timeout(QUERY_TIMEOUT, rx).await;
+        self.waiting.remove(&query_id);
+        match answer {
+            Ok(Ok(answer)) => answer,
+            _ => {
+                if let Some(mut pending) = self.pending.get_mut(&workflow_run_id) {
+                    pending.retain(|query| query.query_id != query_id);
+                }
+                self.pending
+                    .remove_if(&workflow_run_id, |_, pending| pending.is_empty());
+                Err(format!(
+                    "TimedOut: no worker answered the query within {}s",
+                    QUERY_TIMEOUT.as_secs()
+                ))
+            }
+        }
+    }
+
+    /// Long polls for queries about `workflow_run_id`, for the worker executing it.
+    pub async fn poll(&self, workflow_run_id: WorkflowRunId) -> Option<Vec<WorkflowQuery>> {
+        self.asked
+            .long_poll(workflow_run_id, || {
+                let pending = self
+                    .pending
+                    .remove(&workflow_run_id)
+                    .map(|(_, pending)| pending);
+                std::future::ready(pending)
+            })
+            .await
+    }
+
+    /// Returns whether anyone was still waiting for the answer.
+    pub fn answer(&self, query_id: QueryId, answer: Result<String, String>) -> bool {
+        self.waiting
+            .remove(&query_id)
+            .is_some_and(|(_, tx)| tx.send(answer).is_ok())
+    }
+}
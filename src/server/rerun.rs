@@ -0,0 +1,171 @@
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use chrono::Utc;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::core::activity::ActivityRunId;
+use crate::core::workflow::{WorkflowCommandType, WorkflowEvent, WorkflowEventType, WorkflowRunId};
+
+use super::ServerState;
+
+#[derive(Deserialize)]
+pub struct RerunWorkflowPayload {
+    workflow_run_id: WorkflowRunId,
+    #[serde(default)]
+    mode: RerunMode,
+    checkpoint: Option<RerunCheckpoint>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RerunMode {
+    /// Re-run a failed, timed out, terminated or cancelled run, reusing the results of
+    /// activities that succeeded before the checkpoint.
+    #[default]
+    ResetFromFailure,
+    /// Re-execute every activity of a finished run.
+    ForceFullReexecution,
+    /// Re-execute a finished run from scratch with a different workflow input.
+    NewInput { input: String },
+}
+
+/// The first activity to re-execute in a rerun; results of activities before it are
+/// reused from the original run.
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RerunCheckpoint {
+    Sequence(i64),
+    ActivityRunId(ActivityRunId),
+}
+
+pub enum RerunWorkflowError {
+    RunNotFound,
+    RunInProgress,
+    ModeNotAllowed(String),
+    CheckpointNotFound,
+}
+
+impl IntoResponse for RerunWorkflowError {
+    fn into_response(self) -> Response {
+        let (status, error, message) = match self {
+            RerunWorkflowError::RunNotFound => (
+                StatusCode::NOT_FOUND,
+                "run_not_found",
+                "workflow run not found".to_string(),
+            ),
+            RerunWorkflowError::RunInProgress => (
+                StatusCode::CONFLICT,
+                "run_in_progress",
+                "workflow run has not finished yet".to_string(),
+            ),
+            RerunWorkflowError::ModeNotAllowed(message) => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "mode_not_allowed",
+                message,
+            ),
+            RerunWorkflowError::CheckpointNotFound => (
+                StatusCode::NOT_FOUND,
+                "checkpoint_not_found",
+                "checkpoint is not an activity of this workflow run".to_string(),
+            ),
+        };
+
+        (status, Json(json!({ "error": error, "message": message }))).into_response()
+    }
+}
+
+pub async fn handle_rerun_workflow(
+    State(state): State<ServerState>,
+    Json(payload): Json<RerunWorkflowPayload>,
+) -> Result<Json<Value>, RerunWorkflowError> {
+    let db = state.db.clone();
+
+    let (Some(first_event), Some(last_event)) = (
+        db.get_first_workflow_run_event(payload.workflow_run_id)
+            .await,
+        db.get_last_workflow_run_event(payload.workflow_run_id)
+            .await,
+    ) else {
+        return Err(RerunWorkflowError::RunNotFound);
+    };
+
+    let failed = match last_event.event_type {
+        WorkflowEventType::Pending
+        | WorkflowEventType::Started
+        | WorkflowEventType::TimerStarted
+        | WorkflowEventType::TimerFired
+        | WorkflowEventType::WaitingForChild
+        | WorkflowEventType::CancelRequested => return Err(RerunWorkflowError::RunInProgress),
+        WorkflowEventType::Succeeeded => false,
+        WorkflowEventType::Failed
+        | WorkflowEventType::TimedOut
+        | WorkflowEventType::Terminated
+        | WorkflowEventType::Cancelled => true,
+    };
+
+    let (input, rerun_checkpoint) = match payload.mode {
+        RerunMode::ResetFromFailure if !failed => {
+            return Err(RerunWorkflowError::ModeNotAllowed(format!(
+                "reset_from_failure needs a failed run, but the run is {:?}",
+                last_event.event_type
+            )))
+        }
+        RerunMode::ResetFromFailure => {
+            let checkpoint = match payload.checkpoint {
+                None => None,
+                Some(RerunCheckpoint::Sequence(sequence)) => {
+                    let commands = db.get_workflow_commands(payload.workflow_run_id).await;
+                    if !commands.iter().any(|command| {
+                        command.sequence == sequence
+                            && matches!(
+                                command.command_type,
+                                WorkflowCommandType::ScheduleActivity { .. }
+                            )
+                    }) {
+                        return Err(RerunWorkflowError::CheckpointNotFound);
+                    }
+                    Some(sequence)
+                }
+                Some(RerunCheckpoint::ActivityRunId(activity_run_id)) => {
+                    match db.get_first_activity_run_event(activity_run_id).await {
+                        Some(activity) if activity.workflow_run_id == payload.workflow_run_id => {
+                            Some(activity.sequence)
+                        }
+                        _ => return Err(RerunWorkflowError::CheckpointNotFound),
+                    }
+                }
+            };
+            (first_event.payload, checkpoint)
+        }
+        _ if payload.checkpoint.is_some() => {
+            return Err(RerunWorkflowError::ModeNotAllowed(
+                "a checkpoint can only be used with reset_from_failure".to_string(),
+            ))
+        }
+        // Sequences start at 1, so a checkpoint of 1 re-executes every activity.
+        RerunMode::ForceFullReexecution => (first_event.payload, Some(1)),
+        RerunMode::NewInput { input } => (input, Some(1)),
+    };
+
+    let new_workflow_run_id = WorkflowRunId::new();
+    let pending = WorkflowEvent {
+        workflow_id: last_event.workflow_id,
+        run_id: new_workflow_run_id,
+        event_type: WorkflowEventType::Pending,
+        rerun_of: Some(last_event.run_id),
+        rerun_checkpoint,
+        payload: input,
+        created_at: Utc::now(),
+        options: first_event.options,
+        fire_at: None,
+        child_run_id: None,
+        parent_run_id: None,
+    };
+    db.add_workflow_event(pending.clone()).await;
+    state.wakeups.workflow_event_recorded(&pending);
+
+    Ok(Json(json!({ "new_workflow_run_id": new_workflow_run_id })))
+}
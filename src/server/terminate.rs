@@ -0,0 +1,141 @@
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use chrono::Utc;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::core::activity::{ActivityEvent, ActivityEventType};
+use crate::core::workflow::{WorkflowCommandType, WorkflowEvent, WorkflowEventType, WorkflowRunId};
+
+use super::{children, ServerState};
+
+#[derive(Deserialize)]
+pub struct TerminateWorkflowPayload {
+    workflow_run_id: WorkflowRunId,
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+pub enum TerminateWorkflowError {
+    RunNotFound,
+    RunFinished,
+}
+
+impl IntoResponse for TerminateWorkflowError {
+    fn into_response(self) -> Response {
+        let (status, error, message) = match self {
+            TerminateWorkflowError::RunNotFound => (
+                StatusCode::NOT_FOUND,
+                "run_not_found",
+                "workflow run not found",
+            ),
+            TerminateWorkflowError::RunFinished => (
+                StatusCode::CONFLICT,
+                "run_finished",
+                "workflow run has already finished",
+            ),
+        };
+
+        (status, Json(json!({ "error": error, "message": message }))).into_response()
+    }
+}
+
+/// Stops a run on the spot, without giving the workflow a chance to clean up. Unlike
+/// cancelling, this works even when no worker executes the run anymore.
+pub async fn handle_terminate_workflow(
+    State(state): State<ServerState>,
+    Json(payload): Json<TerminateWorkflowPayload>,
+) -> Result<Json<Value>, TerminateWorkflowError> {
+    let reason = match payload.reason {
+        Some(reason) => format!("Terminated: {reason}"),
+        None => "Terminated: workflow run was terminated by an operator".to_string(),
+    };
+    // Retried until it sticks, as a worker may move the run on in the meantime.
+    loop {
+        let Some(last_event) = state
+            .db
+            .get_last_workflow_run_event(payload.workflow_run_id)
+            .await
+        else {
+            return Err(TerminateWorkflowError::RunNotFound);
+        };
+        if last_event.event_type.is_finished() {
+            return Err(TerminateWorkflowError::RunFinished);
+        }
+        if terminate(&state, last_event, reason.clone()).await {
+            break;
+        }
+    }
+    children::close_children(&state, payload.workflow_run_id).await;
+
+    Ok(Json(json!({ "terminated": payload.workflow_run_id })))
+}
+
+/// Ends the run as `Terminated`, along with the activity runs it is still waiting on.
+/// Returns whether it did; it doesn't if `last_event` is no longer the run's last event.
+pub async fn terminate(state: &ServerState, last_event: WorkflowEvent, reason: String) -> bool {
+    let workflow_run_id = last_event.run_id;
+    let terminated = WorkflowEvent {
+        event_type: WorkflowEventType::Terminated,
+        payload: reason,
+        created_at: Utc::now(),
+        fire_at: None,
+        child_run_id: None,
+        ..last_event.clone()
+    };
+    if !state
+        .db
+        .add_workflow_events_if_last(&last_event, vec![terminated.clone()])
+        .await
+    {
+        return false;
+    }
+    println!("Terminated workflow. RunId = {workflow_run_id}");
+    state.wakeups.workflow_event_recorded(&terminated);
+
+    let error = format!("Terminated: workflow run {workflow_run_id} was terminated");
+    end_outstanding_activities(state, workflow_run_id, ActivityEventType::Failed, error).await;
+    true
+}
+
+/// Ends every activity run the workflow run scheduled that hasn't finished yet with
+/// `event_type`, so workers stop picking them up and the workflow stops waiting for them.
+pub async fn end_outstanding_activities(
+    state: &ServerState,
+    workflow_run_id: WorkflowRunId,
+    event_type: ActivityEventType,
+    error: String,
+) {
+    let db = state.db.as_ref();
+    for command in db.get_workflow_commands(workflow_run_id).await {
+        let WorkflowCommandType::ScheduleActivity {
+            activity_run_id, ..
+        } = command.command_type
+        else {
+            continue;
+        };
+        // Retried until the run has finished, as a worker may complete the attempt or
+        // the server retry it in the meantime.
+        while let Some(activity) = db.get_last_activity_run_event(activity_run_id).await {
+            if activity.event_type.is_finished() {
+                break;
+            }
+            let ended = ActivityEvent {
+                event_type: event_type.clone(),
+                payload: error.clone(),
+                created_at: Utc::now(),
+                scheduled_for: None,
+                ..activity.clone()
+            };
+            if db
+                .add_activity_events_if_last(&activity, vec![ended.clone()])
+                .await
+            {
+                state.wakeups.activity_events_recorded(&[ended]);
+                break;
+            }
+        }
+    }
+}
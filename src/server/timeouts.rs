@@ -0,0 +1,222 @@
+use std::time::Duration;
+
+use chrono::{DateTime, TimeDelta, Utc};
+
+use crate::core::activity::{ActivityEvent, ActivityEventType};
+use crate::core::workflow::{WorkflowEvent, WorkflowEventType};
+
+use super::{children, record_failed_attempt, terminate, timers, ServerState};
+
+/// How long a started workflow run may go without a heartbeat from its worker before it
+/// is handed to another worker, which replays it from its recorded commands.
+const WORKFLOW_TASK_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub fn deadline(from: DateTime<Utc>, timeout: Option<Duration>) -> Option<DateTime<Utc>> {
+    from.checked_add_signed(TimeDelta::from_std(timeout?).ok()?)
+}
+
+/// Periodically times out runs that have outlived their deadlines, since a worker that
+/// crashed mid-run will never report back on its own.
+pub async fn enforce_timeouts(state: ServerState) {
+    loop {
+        // Stores panic on errors they can't recover from. Each round runs as its own task
+        // so that a panic only costs that round instead of stopping enforcement for good.
+        let round = tokio::spawn({
+            let state = state.clone();
+            async move {
+                enforce_workflow_timeouts(&state).await;
+                enforce_activity_timeouts(&state).await;
+            }
+        });
+        if let Err(e) = round.await {
+            println!("Enforcing timeouts failed, retrying: {e}");
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
+
+async fn enforce_workflow_timeouts(state: &ServerState) {
+    let db = state.db.as_ref();
+    for workflow_run_id in db.get_unfinished_workflow_runs().await {
+        let (Some(enqueued), Some(last_event)) = (
+            db.get_first_workflow_run_event(workflow_run_id).await,
+            db.get_last_workflow_run_event(workflow_run_id).await,
+        ) else {
+            continue;
+        };
+        let now = Utc::now();
+        let options = &enqueued.options;
+
+        let started_at = db
+            .get_workflow_run_events(workflow_run_id)
+            .await
+            .into_iter()
+            .find(|e| e.event_type == WorkflowEventType::Started)
+            .map(|e| e.created_at);
+
+        let reason = if deadline(enqueued.created_at, options.execution_timeout)
+            .is_some_and(|deadline| deadline <= now)
+        {
+            "execution"
+        } else if started_at
+            .and_then(|started_at| deadline(started_at, options.run_timeout))
+            .is_some_and(|deadline| deadline <= now)
+        {
+            "run"
+        } else {
+            match last_event.event_type {
+                WorkflowEventType::Started => {
+                    redeliver_abandoned_workflow(state, enqueued, last_event).await
+                }
+                WorkflowEventType::TimerStarted => {
+                    timers::fire_due_timer(state, enqueued, last_event).await
+                }
+                WorkflowEventType::WaitingForChild => {
+                    children::resume_parent(state, enqueued, last_event).await
+                }
+                _ => {}
+            }
+            continue;
+        };
+
+        println!("Workflow timed out ({reason}). RunId = {workflow_run_id}");
+        let timed_out = WorkflowEvent {
+            event_type: WorkflowEventType::TimedOut,
+            payload: format!("TimedOut: workflow exceeded its {reason} timeout"),
+            created_at: now,
+            fire_at: None,
+            child_run_id: None,
+            ..last_event.clone()
+        };
+        if !db
+            .add_workflow_events_if_last(&last_event, vec![timed_out.clone()])
+            .await
+        {
+            // It finished (or moved on) since we looked; check again next round.
+            continue;
+        }
+        state.wakeups.workflow_event_recorded(&timed_out);
+        let error = format!("Cancelled: workflow run {workflow_run_id} timed out");
+        terminate::end_outstanding_activities(
+            state,
+            workflow_run_id,
+            ActivityEventType::Cancelled,
+            error,
+        )
+        .await;
+        children::close_children(state, workflow_run_id).await;
+    }
+}
+
+async fn redeliver_abandoned_workflow(
+    state: &ServerState,
+    enqueued: WorkflowEvent,
+    last_event: WorkflowEvent,
+) {
+    let db = state.db.as_ref();
+    if last_event.event_type != WorkflowEventType::Started {
+        return;
+    }
+    let last_seen_at = db
+        .get_last_workflow_heartbeat(last_event.run_id)
+        .await
+        .map_or(last_event.created_at, |heartbeat| {
+            heartbeat.max(last_event.created_at)
+        });
+    if deadline(last_seen_at, Some(WORKFLOW_TASK_TIMEOUT))
+        .is_none_or(|deadline| deadline > Utc::now())
+    {
+        return;
+    }
+
+    println!(
+        "Workflow worker went quiet, re-delivering. RunId = {}",
+        last_event.run_id
+    );
+    let pending = WorkflowEvent {
+        event_type: WorkflowEventType::Pending,
+        payload: enqueued.payload,
+        created_at: Utc::now(),
+        ..last_event.clone()
+    };
+    if db
+        .add_workflow_events_if_last(&last_event, vec![pending.clone()])
+        .await
+    {
+        state.wakeups.workflow_event_recorded(&pending);
+    }
+}
+
+async fn enforce_activity_timeouts(state: &ServerState) {
+    let db = state.db.as_ref();
+    for activity_run_id in db.get_unfinished_activity_runs().await {
+        let (Some(scheduled), Some(last_event)) = (
+            db.get_first_activity_run_event(activity_run_id).await,
+            db.get_last_activity_run_event(activity_run_id).await,
+        ) else {
+            continue;
+        };
+        let now = Utc::now();
+        let options = &scheduled.options;
+
+        let timed_out = |since: DateTime<Utc>, timeout: Option<Duration>| {
+            deadline(since, timeout).is_some_and(|deadline| deadline <= now)
+        };
+        let timed_out_event = |reason: &str| ActivityEvent {
+            event_type: ActivityEventType::TimedOut,
+            payload: format!("TimedOut: activity exceeded its {reason} timeout"),
+            created_at: now,
+            scheduled_for: None,
+            ..last_event.clone()
+        };
+
+        if timed_out(scheduled.created_at, options.schedule_to_close_timeout) {
+            println!("Activity timed out (schedule-to-close). RunId = {activity_run_id}");
+            let timed_out = timed_out_event("schedule-to-close");
+            if db
+                .add_activity_events_if_last(&last_event, vec![timed_out.clone()])
+                .await
+            {
+                state.wakeups.activity_events_recorded(&[timed_out]);
+            }
+            continue;
+        }
+
+        let queued_since = last_event.scheduled_for.unwrap_or(last_event.created_at);
+        match last_event.event_type {
+            ActivityEventType::Pending
+                if timed_out(queued_since, options.schedule_to_start_timeout) =>
+            {
+                println!("Activity timed out (schedule-to-start). RunId = {activity_run_id}");
+                let timed_out = timed_out_event("schedule-to-start");
+                if db
+                    .add_activity_events_if_last(&last_event, vec![timed_out.clone()])
+                    .await
+                {
+                    state.wakeups.activity_events_recorded(&[timed_out]);
+                }
+            }
+            ActivityEventType::Started
+                if timed_out(last_event.created_at, options.start_to_close_timeout) =>
+            {
+                println!("Activity timed out (start-to-close). RunId = {activity_run_id}");
+                let timed_out_attempt = timed_out_event("start-to-close");
+                record_failed_attempt(state, scheduled, &last_event, timed_out_attempt).await;
+            }
+            ActivityEventType::Started if options.heartbeat_timeout.is_some() => {
+                let last_heartbeat_at = db
+                    .get_last_activity_heartbeat(activity_run_id)
+                    .await
+                    .filter(|heartbeat| heartbeat.attempt_number == last_event.attempt_number)
+                    .map_or(last_event.created_at, |heartbeat| heartbeat.created_at);
+
+                if timed_out(last_heartbeat_at, options.heartbeat_timeout) {
+                    println!("Activity missed its heartbeat. RunId = {activity_run_id}");
+                    let timed_out_attempt = timed_out_event("heartbeat");
+                    record_failed_attempt(state, scheduled, &last_event, timed_out_attempt).await;
+                }
+            }
+            _ => {}
+        }
+    }
+}
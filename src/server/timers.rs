@@ -0,0 +1,149 @@
+use chrono::{DateTime, Utc};
+
+use crate::core::workflow::{
+    WorkflowCommand, WorkflowCommandType, WorkflowEvent, WorkflowEventType, WorkflowRunId,
+};
+use crate::store::Store;
+
+use super::{follows_past_run, ServerState};
+
+/// Records the timer a run started at `sequence`. Unless it is already due, the run
+/// sleeps until it fires; returns whether it does, or `None` if another delivery of the
+/// run issued a different command there or the run moved on since `started`. A rerun doesn't wait again for timers its
+/// original run already waited for.
+pub async fn start_timer(
+    state: &ServerState,
+    started: WorkflowEvent,
+    sequence: i64,
+    fire_at: DateTime<Utc>,
+) -> Option<bool> {
+    let db = state.db.as_ref();
+    let workflow_run_id = started.run_id;
+
+    // A worker that crashed after recording the timer starts it again when replaying.
+    let recorded = recorded_timer(db, workflow_run_id, sequence).await;
+
+    let fire_at = match recorded {
+        Some(fire_at) => fire_at,
+        None => {
+            let command_type = WorkflowCommandType::StartTimer { fire_at };
+            let before_checkpoint = started
+                .rerun_checkpoint
+                .is_none_or(|checkpoint| sequence < checkpoint);
+            let already_waited = match started.rerun_of.filter(|_| before_checkpoint) {
+                Some(past_workflow_run_id) => {
+                    follows_past_run(
+                        db,
+                        past_workflow_run_id,
+                        workflow_run_id,
+                        sequence,
+                        &command_type,
+                    )
+                    .await
+                }
+                None => false,
+            };
+            let fire_at = if already_waited { Utc::now() } else { fire_at };
+
+            let recorded = db
+                .add_workflow_command(WorkflowCommand {
+                    workflow_run_id,
+                    sequence,
+                    command_type: WorkflowCommandType::StartTimer { fire_at },
+                    created_at: Utc::now(),
+                })
+                .await;
+            if recorded {
+                fire_at
+            } else {
+                recorded_timer(db, workflow_run_id, sequence).await?
+            }
+        }
+    };
+
+    let now = Utc::now();
+    if fire_at <= now {
+        return Some(false);
+    }
+
+    println!("Workflow sleeping until {fire_at}. RunId = {workflow_run_id}");
+    let timer_started = WorkflowEvent {
+        event_type: WorkflowEventType::TimerStarted,
+        payload: "".to_string(),
+        created_at: now,
+        fire_at: Some(fire_at),
+        ..started.clone()
+    };
+    // E.g. terminated or cancelled while the command was being recorded.
+    if !db
+        .add_workflow_events_if_last(&started, vec![timer_started.clone()])
+        .await
+    {
+        return None;
+    }
+    state.wakeups.workflow_event_recorded(&timer_started);
+    Some(true)
+}
+
+/// When the timer the run started at `sequence` fires, if it started one there.
+async fn recorded_timer(
+    db: &dyn Store,
+    workflow_run_id: WorkflowRunId,
+    sequence: i64,
+) -> Option<DateTime<Utc>> {
+    db.get_workflow_commands(workflow_run_id)
+        .await
+        .into_iter()
+        .find_map(|command| match command.command_type {
+            WorkflowCommandType::StartTimer { fire_at } if command.sequence == sequence => {
+                Some(fire_at)
+            }
+            _ => None,
+        })
+}
+
+/// Fires the timer of a sleeping run once it is due and hands the run back to workers,
+/// which replay it up to the timer and carry on from there.
+pub async fn fire_due_timer(
+    state: &ServerState,
+    enqueued: WorkflowEvent,
+    last_event: WorkflowEvent,
+) {
+    let db = state.db.as_ref();
+    if last_event.event_type != WorkflowEventType::TimerStarted
+        || last_event
+            .fire_at
+            .is_some_and(|fire_at| fire_at > Utc::now())
+    {
+        return;
+    }
+    let events = vec![
+        WorkflowEvent {
+            event_type: WorkflowEventType::TimerFired,
+            created_at: Utc::now(),
+            fire_at: None,
+            child_run_id: None,
+            ..last_event.clone()
+        },
+        WorkflowEvent {
+            event_type: WorkflowEventType::Pending,
+            payload: enqueued.payload,
+            created_at: Utc::now(),
+            fire_at: None,
+            child_run_id: None,
+            ..last_event.clone()
+        },
+    ];
+
+    // Another server sharing the store may fire it first.
+    if !db
+        .add_workflow_events_if_last(&last_event, events.clone())
+        .await
+    {
+        return;
+    }
+    println!("Timer fired. RunId = {}", last_event.run_id);
+    for event in &events {
+        state.wakeups.workflow_event_recorded(event);
+    }
+}
@@ -0,0 +1,171 @@
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::Utc;
+use dashmap::DashMap;
+use tokio::sync::{broadcast, Notify};
+use tokio::time::Instant;
+
+use crate::core::activity::{ActivityEvent, ActivityEventType, ActivityId, ActivityRunId};
+use crate::core::worker_events::RunUpdate;
+use crate::core::workflow::{WorkflowEvent, WorkflowEventType, WorkflowId, WorkflowRunId};
+
+/// How long a poll waits for something to happen before the server answers with
+/// `ServerEvent::Empty` and the worker polls again.
+pub const LONG_POLL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Changes made by another server sharing the store don't wake our polls, so waiting
+/// polls also check again this often.
+const RECHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Updates buffered for each watcher; one that falls further behind is told it lagged.
+const UPDATES_CAPACITY: usize = 1024;
+
+/// Wakes the long polls waiting on a key.
+pub struct Channels<K: Eq + Hash>(DashMap<K, Arc<Notify>>);
+
+impl<K: Eq + Hash + Copy> Channels<K> {
+    fn subscribe(&self, key: K) -> Arc<Notify> {
+        self.0.entry(key).or_default().clone()
+    }
+
+    /// Wakes everyone currently waiting on `key`. Call it after the change is in the
+    /// store, since woken polls check the store again.
+    pub fn notify(&self, key: K) {
+        // Waiters subscribe again after every wakeup, so the entry can go; otherwise
+        // completion channels would pile up for every run ever polled.
+        if let Some((_, notify)) = self.0.remove(&key) {
+            notify.notify_waiters();
+        }
+    }
+
+    /// Runs `check` until it finds something, waking up whenever `key` is notified.
+    /// Returns `None` once `LONG_POLL_TIMEOUT` passes.
+    pub async fn long_poll<T, F, Fut>(&self, key: K, check: F) -> Option<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Option<T>>,
+    {
+        self.long_poll_any(&[key], check).await
+    }
+
+    /// Like `long_poll`, waking up whenever any of `keys` is notified.
+    pub async fn long_poll_any<T, F, Fut>(&self, keys: &[K], mut check: F) -> Option<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Option<T>>,
+    {
+        let deadline = Instant::now() + LONG_POLL_TIMEOUT;
+        loop {
+            // Subscribe before checking, so a change between the check and the wait
+            // still wakes us.
+            let notifies: Vec<_> = keys.iter().map(|&key| self.subscribe(key)).collect();
+            let mut notified: Vec<_> = notifies
+                .iter()
+                .map(|notify| Box::pin(notify.notified()))
+                .collect();
+            for notified in &mut notified {
+                notified.as_mut().enable();
+            }
+
+            if let Some(found) = check().await {
+                return Some(found);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return None;
+            }
+
+            let any_notified = async {
+                if notified.is_empty() {
+                    std::future::pending().await
+                } else {
+                    futures::future::select_all(notified).await;
+                }
+            };
+            tokio::select! {
+                _ = any_notified => {}
+                _ = tokio::time::sleep_until((now + RECHECK_INTERVAL).min(deadline)) => {}
+            }
+        }
+    }
+}
+
+impl<K: Eq + Hash> Default for Channels<K> {
+    fn default() -> Self {
+        Self(DashMap::new())
+    }
+}
+
+pub struct Wakeups {
+    /// A run of the workflow may have become pending.
+    pub workflow_tasks: Channels<WorkflowId>,
+    /// An attempt of the activity may have become pending or due.
+    pub activity_tasks: Channels<ActivityId>,
+    pub workflow_completions: Channels<WorkflowRunId>,
+    pub activity_completions: Channels<ActivityRunId>,
+    /// A signal may have arrived for the run.
+    pub workflow_signals: Channels<WorkflowRunId>,
+    /// Every recorded event, for watchers. Events recorded together are sent together.
+    pub updates: broadcast::Sender<Vec<RunUpdate>>,
+}
+
+impl Wakeups {
+    /// Wakes whoever waits on a workflow event that was just added to the store.
+    pub fn workflow_event_recorded(&self, event: &WorkflowEvent) {
+        match &event.event_type {
+            WorkflowEventType::Pending => self.workflow_tasks.notify(event.workflow_id),
+            event_type if event_type.is_finished() => {
+                self.workflow_completions.notify(event.run_id)
+            }
+            _ => {}
+        }
+        // Sending only fails when nobody is watching.
+        let _ = self.updates.send(vec![RunUpdate::Workflow(event.clone())]);
+    }
+
+    /// Like `workflow_event_recorded`, for activity events added in one batch. A retry
+    /// wakes the activity's pollers once its backoff is over.
+    pub fn activity_events_recorded(self: &Arc<Self>, events: &[ActivityEvent]) {
+        let Some(last_event) = events.last() else {
+            return;
+        };
+        match last_event.event_type {
+            ActivityEventType::Pending => {
+                let activity_id = last_event.activity_id;
+                let delay = last_event
+                    .scheduled_for
+                    .and_then(|scheduled_for| (scheduled_for - Utc::now()).to_std().ok());
+                match delay {
+                    Some(delay) => {
+                        let wakeups = self.clone();
+                        tokio::spawn(async move {
+                            tokio::time::sleep(delay).await;
+                            wakeups.activity_tasks.notify(activity_id);
+                        });
+                    }
+                    None => self.activity_tasks.notify(activity_id),
+                }
+            }
+            ActivityEventType::Started => {}
+            _ => self.activity_completions.notify(last_event.activity_run_id),
+        }
+        let updates = events.iter().cloned().map(RunUpdate::Activity).collect();
+        let _ = self.updates.send(updates);
+    }
+}
+
+impl Default for Wakeups {
+    fn default() -> Self {
+        Self {
+            workflow_tasks: Channels::default(),
+            activity_tasks: Channels::default(),
+            workflow_completions: Channels::default(),
+            activity_completions: Channels::default(),
+            workflow_signals: Channels::default(),
+            updates: broadcast::channel(UPDATES_CAPACITY).0,
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::hash::Hash;
+use std::str::FromStr;
+
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use serde::Deserialize;
+use tokio::sync::{broadcast, mpsc};
+
+use crate::core::activity::ActivityRunId;
+use crate::core::worker_events::RunUpdate;
+use crate::core::workflow::WorkflowRunId;
+
+use super::ServerState;
+
+/// Comma separated run ids to watch, e.g.
+/// `/watch?workflow_run_ids=<id>,<id>&activity_run_ids=<id>`.
+#[derive(Deserialize)]
+pub struct WatchQuery {
+    #[serde(default)]
+    workflow_run_ids: String,
+    #[serde(default)]
+    activity_run_ids: String,
+}
+
+fn parse_ids<T: FromStr<Err = String> + Eq + Hash>(ids: &str) -> Result<HashSet<T>, String> {
+    ids.split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(T::from_str)
+        .collect()
+}
+
+/// Streams the events of the given runs as server-sent events: first the current state
+/// of each run, then every event recorded for them. The stream ends once all of them
+/// have finished.
+pub async fn handle_watch(
+    State(state): State<ServerState>,
+    Query(query): Query<WatchQuery>,
+) -> Response {
+    let watched = parse_ids(&query.workflow_run_ids)
+        .and_then(|workflows| Ok((workflows, parse_ids(&query.activity_run_ids)?)));
+    let (workflow_run_ids, activity_run_ids) = match watched {
+        Ok(watched) => watched,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+
+    // Subscribe before reading the current states, so nothing recorded in between is
+    // missed. It may be sent twice instead.
+    let updates = state.wakeups.updates.subscribe();
+    let (tx, mut rx) = mpsc::channel(64);
+    tokio::spawn(forward_updates(
+        state,
+        Watched {
+            workflow_run_ids,
+            activity_run_ids,
+        },
+        updates,
+        tx,
+    ));
+
+    let stream = futures::stream::poll_fn(move |cx| rx.poll_recv(cx));
+    Sse::new(stream)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+/// Runs that haven't finished yet.
+struct Watched {
+    workflow_run_ids: HashSet<WorkflowRunId>,
+    activity_run_ids: HashSet<ActivityRunId>,
+}
+
+impl Watched {
+    fn contains(&self, update: &RunUpdate) -> bool {
+        match update {
+            RunUpdate::Workflow(event) => self.workflow_run_ids.contains(&event.run_id),
+            RunUpdate::Activity(event) => self.activity_run_ids.contains(&event.activity_run_id),
+        }
+    }
+
+    /// Stops watching the run once `update`, the last event recorded for it, finishes it.
+    fn remove_if_finished(&mut self, update: &RunUpdate) {
+        match update {
+            RunUpdate::Workflow(event) if event.event_type.is_finished() => {
+                self.workflow_run_ids.remove(&event.run_id);
+            }
+            RunUpdate::Activity(event) if event.event_type.is_finished() => {
+                self.activity_run_ids.remove(&event.activity_run_id);
+            }
+            _ => {}
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.workflow_run_ids.is_empty() && self.activity_run_ids.is_empty()
+    }
+}
+
+type EventSender = mpsc::Sender<Result<Event, Infallible>>;
+
+async fn send(tx: &EventSender, update: &RunUpdate) -> Result<(), ()> {
+    let event = Event::default()
+        .json_data(update)
+        .expect("Run updates serialize to JSON");
+    // Fails once the client has gone away.
+    tx.send(Ok(event)).await.map_err(|_| ())
+}
+
+async fn send_current_states(
+    state: &ServerState,
+    watched: &mut Watched,
+    tx: &EventSender,
+) -> Result<(), ()> {
+    let mut current = vec![];
+    for &workflow_run_id in &watched.workflow_run_ids {
+        if let Some(event) = state.db.get_last_workflow_run_event(workflow_run_id).await {
+            current.push(RunUpdate::Workflow(event));
+        }
+    }
+    for &activity_run_id in &watched.activity_run_ids {
+        if let Some(event) = state.db.get_last_activity_run_event(activity_run_id).await {
+            current.push(RunUpdate::Activity(event));
+        }
+    }
+
+    for update in current {
+        send(tx, &update).await?;
+        watched.remove_if_finished(&update);
+    }
+    Ok(())
+}
+
+async fn forward_updates(
+    state: ServerState,
+    mut watched: Watched,
+    mut updates: broadcast::Receiver<Vec<RunUpdate>>,
+    tx: EventSender,
+) -> Result<(), ()> {
+    send_current_states(&state, &mut watched, &tx).await?;
+
+    while !watched.is_empty() {
+        let received = tokio::select! {
+            // Without this, a watcher whose runs never see another event would outlive
+            // its client until the server stops.
+            _ = tx.closed() => return Err(()),
+            received = updates.recv() => received,
+        };
+        match received {
+            Ok(batch) => {
+                let Some(last) = batch.last().filter(|update| watched.contains(update)) else {
+                    continue;
+                };
+                // A batch only ever holds the events of one run.
+                for update in &batch {
+                    send(&tx, update).await?;
+                }
+                watched.remove_if_finished(last);
+            }
+            // Some updates were dropped, so catch up from the store instead.
+            Err(broadcast::error::RecvError::Lagged(_)) => {
+                send_current_states(&state, &mut watched, &tx).await?;
+            }
+            Err(broadcast::error::RecvError::Closed) => break,
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,278 @@
+pub mod postgres;
+pub mod sqlite;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::core::activity::{
+    Activity, ActivityEvent, ActivityEventType, ActivityHeartbeat, ActivityId, ActivityName,
+    ActivityRunId,
+};
+use crate::core::workflow::{
+    Workflow, WorkflowCommand, WorkflowCommandType, WorkflowEvent, WorkflowEventType, WorkflowId,
+    WorkflowName, WorkflowRunId, WorkflowSignal,
+};
+
+pub use postgres::PostgresStore;
+pub use sqlite::SqliteStore;
+
+/// The event that marks `pending` as picked up by a worker.
+pub fn started_workflow_event(pending: &WorkflowEvent) -> WorkflowEvent {
+    WorkflowEvent {
+        workflow_id: pending.workflow_id,
+        run_id: pending.run_id,
+        event_type: WorkflowEventType::Started,
+        rerun_of: pending.rerun_of,
+        rerun_checkpoint: pending.rerun_checkpoint,
+        payload: "".to_string(),
+        created_at: Utc::now(),
+        options: pending.options.clone(),
+        fire_at: None,
+        child_run_id: None,
+        parent_run_id: pending.parent_run_id,
+    }
+}
+
+/// The event that marks the attempt `pending` as picked up by a worker.
+pub fn started_activity_event(pending: &ActivityEvent) -> ActivityEvent {
+    ActivityEvent {
+        workflow_run_id: pending.workflow_run_id,
+        payload: "".to_string(),
+        activity_id: pending.activity_id,
+        activity_run_id: pending.activity_run_id,
+        event_type: ActivityEventType::Started,
+        sequence: pending.sequence,
+        options: pending.options.clone(),
+        scheduled_for: None,
+        attempt_number: pending.attempt_number,
+        created_at: Utc::now(),
+    }
+}
+
+/// Ids, names and event types are stored as their plain serialized string.
+fn key<T: Serialize>(value: &T) -> String {
+    match serde_json::to_value(value).expect("keys are serializable") {
+        Value::String(key) => key,
+        other => other.to_string(),
+    }
+}
+
+fn to_json<T: Serialize>(value: &T) -> String {
+    serde_json::to_string(value).expect("records are serializable")
+}
+
+/// Whether two events are the same record. Events carry no id of their own, so this
+/// compares everything they hold.
+pub(crate) fn is_same_event<T: Serialize>(a: &T, b: &T) -> bool {
+    to_json(a) == to_json(b)
+}
+
+/// Everything the server persists. Implementations only provide the storage primitives;
+/// queries such as "first pending run" are built on top of them here.
+#[async_trait::async_trait]
+pub trait Store: Send + Sync {
+    async fn add_workflow(&self, workflow: Workflow);
+    async fn get_workflow_by_name(&self, name: &WorkflowName) -> Option<Workflow>;
+    async fn add_activity(&self, activity: Activity);
+    async fn get_activity_by_name(&self, name: &ActivityName) -> Option<Activity>;
+
+    async fn add_workflow_event(&self, event: WorkflowEvent);
+    /// Appends several events for the same activity run in one step, so pollers never
+    /// observe a half-applied transition (e.g. a failed attempt without its retry).
+    async fn add_activity_events(&self, events: Vec<ActivityEvent>);
+    /// Appends `events` to the workflow run only if its last event is still `expected`,
+    /// i.e. nobody moved the run on since the caller read it. Returns whether they were
+    /// appended.
+    async fn add_workflow_events_if_last(
+        &self,
+        expected: &WorkflowEvent,
+        events: Vec<WorkflowEvent>,
+    ) -> bool;
+    /// Like `add_workflow_events_if_last`, for an activity run.
+    async fn add_activity_events_if_last(
+        &self,
+        expected: &ActivityEvent,
+        events: Vec<ActivityEvent>,
+    ) -> bool;
+
+    /// Runs of a workflow, in the order they were first recorded.
+    async fn get_workflow_runs(&self, workflow_id: WorkflowId) -> Vec<WorkflowRunId>;
+    /// Runs of an activity, in the order they were first recorded.
+    async fn get_activity_runs(&self, activity_id: ActivityId) -> Vec<ActivityRunId>;
+    /// Events of a workflow run, oldest first.
+    async fn get_workflow_run_events(&self, workflow_run_id: WorkflowRunId) -> Vec<WorkflowEvent>;
+    /// Events of an activity run, oldest first.
+    async fn get_activity_run_events(&self, activity_run_id: ActivityRunId) -> Vec<ActivityEvent>;
+    /// Workflow runs that haven't finished, i.e. whose last event is `Pending`, `Started`,
+    /// `TimerStarted`, `TimerFired`, `WaitingForChild` or `CancelRequested`.
+    async fn get_unfinished_workflow_runs(&self) -> Vec<WorkflowRunId>;
+    /// Activity runs whose last event is `Pending` or `Started`.
+    async fn get_unfinished_activity_runs(&self) -> Vec<ActivityRunId>;
+
+    /// Records `command` unless the run already has one at its sequence, which the
+    /// replay of a run relies on. Returns whether it did.
+    async fn add_workflow_command(&self, command: WorkflowCommand) -> bool;
+    /// Commands of a workflow run, ordered by sequence.
+    async fn get_workflow_commands(&self, workflow_run_id: WorkflowRunId) -> Vec<WorkflowCommand>;
+
+    async fn add_workflow_signal(&self, signal: WorkflowSignal);
+    /// Signals sent to a workflow run, in the order they arrived.
+    async fn get_workflow_signals(&self, workflow_run_id: WorkflowRunId) -> Vec<WorkflowSignal>;
+
+    async fn record_workflow_heartbeat(&self, workflow_run_id: WorkflowRunId);
+    async fn get_last_workflow_heartbeat(
+        &self,
+        workflow_run_id: WorkflowRunId,
+    ) -> Option<DateTime<Utc>>;
+    async fn record_activity_heartbeat(&self, heartbeat: ActivityHeartbeat);
+    async fn get_last_activity_heartbeat(
+        &self,
+        activity_run_id: ActivityRunId,
+    ) -> Option<ActivityHeartbeat>;
+
+    async fn workflow_exists(&self, name: &WorkflowName) -> bool {
+        self.get_workflow_by_name(name).await.is_some()
+    }
+
+    async fn activity_exists(&self, name: &ActivityName) -> bool {
+        self.get_activity_by_name(name).await.is_some()
+    }
+
+    async fn add_activity_event(&self, event: ActivityEvent) {
+        self.add_activity_events(vec![event]).await
+    }
+
+    async fn get_first_workflow_run_event(
+        &self,
+        workflow_run_id: WorkflowRunId,
+    ) -> Option<WorkflowEvent> {
+        self.get_workflow_run_events(workflow_run_id)
+            .await
+            .into_iter()
+            .next()
+    }
+
+    async fn get_last_workflow_run_event(
+        &self,
+        workflow_run_id: WorkflowRunId,
+    ) -> Option<WorkflowEvent> {
+        self.get_workflow_run_events(workflow_run_id).await.pop()
+    }
+
+    async fn get_first_activity_run_event(
+        &self,
+        activity_run_id: ActivityRunId,
+    ) -> Option<ActivityEvent> {
+        self.get_activity_run_events(activity_run_id)
+            .await
+            .into_iter()
+            .next()
+    }
+
+    async fn get_last_activity_run_event(
+        &self,
+        activity_run_id: ActivityRunId,
+    ) -> Option<ActivityEvent> {
+        self.get_activity_run_events(activity_run_id).await.pop()
+    }
+
+    async fn get_first_pending_workflow(&self, name: WorkflowName) -> Option<WorkflowEvent> {
+        let workflow = self.get_workflow_by_name(&name).await?;
+
+        for run in self.get_workflow_runs(workflow.id).await {
+            let Some(last_event) = self.get_last_workflow_run_event(run).await else {
+                continue;
+            };
+
+            if last_event.event_type == WorkflowEventType::Pending {
+                return Some(last_event);
+            }
+        }
+        None
+    }
+
+    async fn get_first_pending_activity(&self, name: ActivityName) -> Option<ActivityEvent> {
+        let activity = self.get_activity_by_name(&name).await?;
+
+        for run in self.get_activity_runs(activity.id).await {
+            let Some(last_event) = self.get_last_activity_run_event(run).await else {
+                continue;
+            };
+
+            if last_event.event_type == ActivityEventType::Pending
+                && last_event
+                    .scheduled_for
+                    .is_none_or(|scheduled_for| scheduled_for <= Utc::now())
+            {
+                return Some(last_event);
+            }
+        }
+        None
+    }
+
+    /// Moves the first pending run of `name` to `Started` and returns its pending event.
+    /// This default reads and then writes, so two concurrent pollers can both claim the
+    /// same run; stores shared by several pollers override it with an atomic claim.
+    async fn claim_pending_workflow(&self, name: WorkflowName) -> Option<WorkflowEvent> {
+        let pending = self.get_first_pending_workflow(name).await?;
+        self.add_workflow_event(started_workflow_event(&pending))
+            .await;
+        Some(pending)
+    }
+
+    /// Moves the first due pending attempt of `name` to `Started` and returns its pending
+    /// event. Like `claim_pending_workflow`, the default is not atomic.
+    async fn claim_pending_activity(&self, name: ActivityName) -> Option<ActivityEvent> {
+        let pending = self.get_first_pending_activity(name).await?;
+        self.add_activity_event(started_activity_event(&pending))
+            .await;
+        Some(pending)
+    }
+
+    /// The successful activity run that `past_workflow_run_id` scheduled at `sequence`,
+    /// found through the run's own commands rather than every run of the activity.
+    async fn get_success_activity_event_for_sequence(
+        &self,
+        past_workflow_run_id: WorkflowRunId,
+        activity_id: ActivityId,
+        sequence: i64,
+    ) -> Option<ActivityEvent> {
+        let command = self
+            .get_workflow_commands(past_workflow_run_id)
+            .await
+            .into_iter()
+            .find(|command| command.sequence == sequence)?;
+        let WorkflowCommandType::ScheduleActivity {
+            activity_run_id, ..
+        } = command.command_type
+        else {
+            return None;
+        };
+        let last_event = self.get_last_activity_run_event(activity_run_id).await?;
+
+        (last_event.activity_id == activity_id
+            && last_event.event_type == ActivityEventType::Succeeeded)
+            .then_some(last_event)
+    }
+
+    async fn get_completed_activity(
+        &self,
+        activity_run_id: ActivityRunId,
+    ) -> Option<ActivityEvent> {
+        // Failed attempts that are being retried are followed by a new Pending event,
+        // so only the last event tells us whether the run is actually finished.
+        let last_event = self.get_last_activity_run_event(activity_run_id).await?;
+
+        last_event.event_type.is_finished().then_some(last_event)
+    }
+
+    async fn get_completed_workflow(
+        &self,
+        workflow_run_id: WorkflowRunId,
+    ) -> Option<WorkflowEvent> {
+        let last_event = self.get_last_workflow_run_event(workflow_run_id).await?;
+
+        last_event.event_type.is_finished().then_some(last_event)
+    }
+}
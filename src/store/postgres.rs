@@ -0,0 +1,592 @@
+use std::fmt::Display;
+
+use chrono::{DateTime, Utc};
+use deadpool_postgres::{Config, Object, Pool, Runtime};
+use serde::de::DeserializeOwned;
+use tokio_postgres::{NoTls, Transaction};
+
+use crate::core::activity::{
+    Activity, ActivityEvent, ActivityHeartbeat, ActivityId, ActivityName, ActivityRunId,
+};
+use crate::core::workflow::{
+    Workflow, WorkflowCommand, WorkflowEvent, WorkflowId, WorkflowName, WorkflowRunId,
+    WorkflowSignal,
+};
+use crate::store::{
+    is_same_event, key, started_activity_event, started_workflow_event, to_json, Store,
+};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS workflows (
+        name TEXT PRIMARY KEY,
+        workflow TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS workflow_runs (
+        run_id TEXT PRIMARY KEY,
+        workflow_id TEXT NOT NULL,
+        status TEXT NOT NULL,
+        position BIGSERIAL
+    );
+    CREATE INDEX IF NOT EXISTS workflow_runs_workflow_id ON workflow_runs (workflow_id, position);
+    CREATE TABLE IF NOT EXISTS workflow_events (
+        position BIGSERIAL PRIMARY KEY,
+        run_id TEXT NOT NULL,
+        event TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS workflow_events_run_id ON workflow_events (run_id, position);
+    CREATE TABLE IF NOT EXISTS workflow_commands (
+        run_id TEXT NOT NULL,
+        sequence BIGINT NOT NULL,
+        command TEXT NOT NULL
+    );
+    CREATE UNIQUE INDEX IF NOT EXISTS workflow_commands_run_id_sequence
+        ON workflow_commands (run_id, sequence);
+    CREATE TABLE IF NOT EXISTS workflow_signals (
+        position BIGSERIAL PRIMARY KEY,
+        run_id TEXT NOT NULL,
+        signal TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS workflow_signals_run_id ON workflow_signals (run_id, position);
+    CREATE TABLE IF NOT EXISTS workflow_heartbeats (
+        run_id TEXT PRIMARY KEY,
+        created_at TIMESTAMPTZ NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS activities (
+        name TEXT PRIMARY KEY,
+        activity TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS activity_runs (
+        run_id TEXT PRIMARY KEY,
+        activity_id TEXT NOT NULL,
+        status TEXT NOT NULL,
+        scheduled_for TIMESTAMPTZ,
+        position BIGSERIAL
+    );
+    CREATE INDEX IF NOT EXISTS activity_runs_activity_id ON activity_runs (activity_id, position);
+    CREATE TABLE IF NOT EXISTS activity_events (
+        position BIGSERIAL PRIMARY KEY,
+        run_id TEXT NOT NULL,
+        event TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS activity_events_run_id ON activity_events (run_id, position);
+    CREATE TABLE IF NOT EXISTS activity_heartbeats (
+        run_id TEXT PRIMARY KEY,
+        heartbeat TEXT NOT NULL
+    );
+";
+
+/// Store in a Postgres database that several servers can share. Next to the event log,
+/// `workflow_runs` and `activity_runs` keep each run's current status; claiming a run
+/// updates that row under `FOR UPDATE SKIP LOCKED`, so every run is handed out once.
+#[derive(Clone)]
+pub struct PostgresStore {
+    pool: Pool,
+}
+
+impl PostgresStore {
+    pub async fn connect(url: &str) -> Result<Self, String> {
+        let config = Config {
+            url: Some(url.to_string()),
+            ..Default::default()
+        };
+        let pool = config
+            .create_pool(Some(Runtime::Tokio1), NoTls)
+            .map_err(|e| e.to_string())?;
+
+        let client = pool.get().await.map_err(|e| e.to_string())?;
+        client
+            .batch_execute(SCHEMA)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        Ok(Self { pool })
+    }
+
+    async fn client(&self) -> Object {
+        expect_db(self.pool.get().await)
+    }
+}
+
+/// The server can't make progress without its store, so storage errors panic instead of
+/// being dropped.
+fn expect_db<T, E: Display>(result: Result<T, E>) -> T {
+    result.unwrap_or_else(|e| panic!("postgres store: {e}"))
+}
+
+fn from_key<T: DeserializeOwned>(key: String) -> T {
+    expect_db(serde_json::from_value(serde_json::Value::String(key)))
+}
+
+fn from_json<T: DeserializeOwned>(json: String) -> T {
+    expect_db(serde_json::from_str(&json))
+}
+
+async fn insert_workflow_event(tx: &Transaction<'_>, event: &WorkflowEvent) {
+    let run_id = key(&event.run_id);
+    expect_db(
+        tx.execute(
+            "INSERT INTO workflow_runs (run_id, workflow_id, status) VALUES ($1, $2, $3)
+             ON CONFLICT (run_id) DO UPDATE SET status = EXCLUDED.status",
+            &[&run_id, &key(&event.workflow_id), &key(&event.event_type)],
+        )
+        .await,
+    );
+    expect_db(
+        tx.execute(
+            "INSERT INTO workflow_events (run_id, event) VALUES ($1, $2)",
+            &[&run_id, &to_json(event)],
+        )
+        .await,
+    );
+}
+
+async fn insert_activity_event(tx: &Transaction<'_>, event: &ActivityEvent) {
+    let run_id = key(&event.activity_run_id);
+    expect_db(
+        tx.execute(
+            "INSERT INTO activity_runs (run_id, activity_id, status, scheduled_for)
+             VALUES ($1, $2, $3, $4)
+             ON CONFLICT (run_id) DO UPDATE
+             SET status = EXCLUDED.status, scheduled_for = EXCLUDED.scheduled_for",
+            &[
+                &run_id,
+                &key(&event.activity_id),
+                &key(&event.event_type),
+                &event.scheduled_for,
+            ],
+        )
+        .await,
+    );
+    expect_db(
+        tx.execute(
+            "INSERT INTO activity_events (run_id, event) VALUES ($1, $2)",
+            &[&run_id, &to_json(event)],
+        )
+        .await,
+    );
+}
+
+/// Locks the status row of a run and returns the run's last event. Every append to the
+/// run updates that row, so nothing can be appended until the transaction ends.
+async fn lock_last_event<T: DeserializeOwned>(
+    tx: &Transaction<'_>,
+    runs_table: &str,
+    events_table: &str,
+    run_id: &str,
+) -> Option<T> {
+    expect_db(
+        tx.execute(
+            &format!("SELECT 1 FROM {runs_table} WHERE run_id = $1 FOR UPDATE"),
+            &[&run_id],
+        )
+        .await,
+    );
+    let row = expect_db(
+        tx.query_opt(
+            &format!(
+                "SELECT event FROM {events_table} WHERE run_id = $1
+                 ORDER BY position DESC LIMIT 1"
+            ),
+            &[&run_id],
+        )
+        .await,
+    )?;
+    Some(from_json(row.get(0)))
+}
+
+#[async_trait::async_trait]
+impl Store for PostgresStore {
+    async fn add_workflow(&self, workflow: Workflow) {
+        expect_db(
+            self.client()
+                .await
+                .execute(
+                    "INSERT INTO workflows (name, workflow) VALUES ($1, $2)
+                     ON CONFLICT (name) DO UPDATE SET workflow = EXCLUDED.workflow",
+                    &[&key(&workflow.name), &to_json(&workflow)],
+                )
+                .await,
+        );
+    }
+
+    async fn get_workflow_by_name(&self, name: &WorkflowName) -> Option<Workflow> {
+        let row = expect_db(
+            self.client()
+                .await
+                .query_opt(
+                    "SELECT workflow FROM workflows WHERE name = $1",
+                    &[&key(name)],
+                )
+                .await,
+        )?;
+        Some(from_json(row.get(0)))
+    }
+
+    async fn add_activity(&self, activity: Activity) {
+        expect_db(
+            self.client()
+                .await
+                .execute(
+                    "INSERT INTO activities (name, activity) VALUES ($1, $2)
+                     ON CONFLICT (name) DO UPDATE SET activity = EXCLUDED.activity",
+                    &[&key(&activity.name), &to_json(&activity)],
+                )
+                .await,
+        );
+    }
+
+    async fn get_activity_by_name(&self, name: &ActivityName) -> Option<Activity> {
+        let row = expect_db(
+            self.client()
+                .await
+                .query_opt(
+                    "SELECT activity FROM activities WHERE name = $1",
+                    &[&key(name)],
+                )
+                .await,
+        )?;
+        Some(from_json(row.get(0)))
+    }
+
+    async fn add_workflow_event(&self, event: WorkflowEvent) {
+        let mut client = self.client().await;
+        let tx = expect_db(client.transaction().await);
+        insert_workflow_event(&tx, &event).await;
+        expect_db(tx.commit().await);
+    }
+
+    async fn add_activity_events(&self, events: Vec<ActivityEvent>) {
+        let mut client = self.client().await;
+        let tx = expect_db(client.transaction().await);
+        for event in &events {
+            insert_activity_event(&tx, event).await;
+        }
+        expect_db(tx.commit().await);
+    }
+
+    async fn add_workflow_events_if_last(
+        &self,
+        expected: &WorkflowEvent,
+        events: Vec<WorkflowEvent>,
+    ) -> bool {
+        let mut client = self.client().await;
+        let tx = expect_db(client.transaction().await);
+        let last: Option<WorkflowEvent> = lock_last_event(
+            &tx,
+            "workflow_runs",
+            "workflow_events",
+            &key(&expected.run_id),
+        )
+        .await;
+        if !last.is_some_and(|last| is_same_event(&last, expected)) {
+            return false;
+        }
+        for event in &events {
+            insert_workflow_event(&tx, event).await;
+        }
+        expect_db(tx.commit().await);
+        true
+    }
+
+    async fn add_activity_events_if_last(
+        &self,
+        expected: &ActivityEvent,
+        events: Vec<ActivityEvent>,
+    ) -> bool {
+        let mut client = self.client().await;
+        let tx = expect_db(client.transaction().await);
+        let last: Option<ActivityEvent> = lock_last_event(
+            &tx,
+            "activity_runs",
+            "activity_events",
+            &key(&expected.activity_run_id),
+        )
+        .await;
+        if !last.is_some_and(|last| is_same_event(&last, expected)) {
+            return false;
+        }
+        for event in &events {
+            insert_activity_event(&tx, event).await;
+        }
+        expect_db(tx.commit().await);
+        true
+    }
+
+    async fn get_workflow_runs(&self, workflow_id: WorkflowId) -> Vec<WorkflowRunId> {
+        let rows = expect_db(
+            self.client()
+                .await
+                .query(
+                    "SELECT run_id FROM workflow_runs WHERE workflow_id = $1 ORDER BY position",
+                    &[&key(&workflow_id)],
+                )
+                .await,
+        );
+        rows.into_iter().map(|row| from_key(row.get(0))).collect()
+    }
+
+    async fn get_activity_runs(&self, activity_id: ActivityId) -> Vec<ActivityRunId> {
+        let rows = expect_db(
+            self.client()
+                .await
+                .query(
+                    "SELECT run_id FROM activity_runs WHERE activity_id = $1 ORDER BY position",
+                    &[&key(&activity_id)],
+                )
+                .await,
+        );
+        rows.into_iter().map(|row| from_key(row.get(0))).collect()
+    }
+
+    async fn get_workflow_run_events(&self, workflow_run_id: WorkflowRunId) -> Vec<WorkflowEvent> {
+        let rows = expect_db(
+            self.client()
+                .await
+                .query(
+                    "SELECT event FROM workflow_events WHERE run_id = $1 ORDER BY position",
+                    &[&key(&workflow_run_id)],
+                )
+                .await,
+        );
+        rows.into_iter().map(|row| from_json(row.get(0))).collect()
+    }
+
+    async fn get_activity_run_events(&self, activity_run_id: ActivityRunId) -> Vec<ActivityEvent> {
+        let rows = expect_db(
+            self.client()
+                .await
+                .query(
+                    "SELECT event FROM activity_events WHERE run_id = $1 ORDER BY position",
+                    &[&key(&activity_run_id)],
+                )
+                .await,
+        );
+        rows.into_iter().map(|row| from_json(row.get(0))).collect()
+    }
+
+    async fn get_unfinished_workflow_runs(&self) -> Vec<WorkflowRunId> {
+        let rows = expect_db(
+            self.client()
+                .await
+                .query(
+                    "SELECT run_id FROM workflow_runs
+                     WHERE status IN ('Pending', 'Started', 'TimerStarted', 'TimerFired',
+                     'WaitingForChild', 'CancelRequested')",
+                    &[],
+                )
+                .await,
+        );
+        rows.into_iter().map(|row| from_key(row.get(0))).collect()
+    }
+
+    async fn get_unfinished_activity_runs(&self) -> Vec<ActivityRunId> {
+        let rows = expect_db(
+            self.client()
+                .await
+                .query(
+                    "SELECT run_id FROM activity_runs WHERE status IN ('Pending', 'Started')",
+                    &[],
+                )
+                .await,
+        );
+        rows.into_iter().map(|row| from_key(row.get(0))).collect()
+    }
+
+    async fn add_workflow_command(&self, command: WorkflowCommand) -> bool {
+        let inserted = expect_db(
+            self.client()
+                .await
+                .execute(
+                    "INSERT INTO workflow_commands (run_id, sequence, command) VALUES ($1, $2, $3)
+                     ON CONFLICT (run_id, sequence) DO NOTHING",
+                    &[
+                        &key(&command.workflow_run_id),
+                        &command.sequence,
+                        &to_json(&command),
+                    ],
+                )
+                .await,
+        );
+        inserted == 1
+    }
+
+    async fn get_workflow_commands(&self, workflow_run_id: WorkflowRunId) -> Vec<WorkflowCommand> {
+        let rows = expect_db(
+            self.client()
+                .await
+                .query(
+                    "SELECT command FROM workflow_commands WHERE run_id = $1 ORDER BY sequence",
+                    &[&key(&workflow_run_id)],
+                )
+                .await,
+        );
+        rows.into_iter().map(|row| from_json(row.get(0))).collect()
+    }
+
+    async fn add_workflow_signal(&self, signal: WorkflowSignal) {
+        expect_db(
+            self.client()
+                .await
+                .execute(
+                    "INSERT INTO workflow_signals (run_id, signal) VALUES ($1, $2)",
+                    &[&key(&signal.workflow_run_id), &to_json(&signal)],
+                )
+                .await,
+        );
+    }
+
+    async fn get_workflow_signals(&self, workflow_run_id: WorkflowRunId) -> Vec<WorkflowSignal> {
+        let rows = expect_db(
+            self.client()
+                .await
+                .query(
+                    "SELECT signal FROM workflow_signals WHERE run_id = $1 ORDER BY position",
+                    &[&key(&workflow_run_id)],
+                )
+                .await,
+        );
+        rows.into_iter().map(|row| from_json(row.get(0))).collect()
+    }
+
+    async fn record_workflow_heartbeat(&self, workflow_run_id: WorkflowRunId) {
+        expect_db(
+            self.client()
+                .await
+                .execute(
+                    "INSERT INTO workflow_heartbeats (run_id, created_at) VALUES ($1, $2)
+                     ON CONFLICT (run_id) DO UPDATE SET created_at = EXCLUDED.created_at",
+                    &[&key(&workflow_run_id), &Utc::now()],
+                )
+                .await,
+        );
+    }
+
+    async fn get_last_workflow_heartbeat(
+        &self,
+        workflow_run_id: WorkflowRunId,
+    ) -> Option<DateTime<Utc>> {
+        let row = expect_db(
+            self.client()
+                .await
+                .query_opt(
+                    "SELECT created_at FROM workflow_heartbeats WHERE run_id = $1",
+                    &[&key(&workflow_run_id)],
+                )
+                .await,
+        )?;
+        Some(row.get(0))
+    }
+
+    async fn record_activity_heartbeat(&self, heartbeat: ActivityHeartbeat) {
+        expect_db(
+            self.client()
+                .await
+                .execute(
+                    "INSERT INTO activity_heartbeats (run_id, heartbeat) VALUES ($1, $2)
+                     ON CONFLICT (run_id) DO UPDATE SET heartbeat = EXCLUDED.heartbeat",
+                    &[&key(&heartbeat.activity_run_id), &to_json(&heartbeat)],
+                )
+                .await,
+        );
+    }
+
+    async fn get_last_activity_heartbeat(
+        &self,
+        activity_run_id: ActivityRunId,
+    ) -> Option<ActivityHeartbeat> {
+        let row = expect_db(
+            self.client()
+                .await
+                .query_opt(
+                    "SELECT heartbeat FROM activity_heartbeats WHERE run_id = $1",
+                    &[&key(&activity_run_id)],
+                )
+                .await,
+        )?;
+        Some(from_json(row.get(0)))
+    }
+
+    async fn claim_pending_workflow(&self, name: WorkflowName) -> Option<WorkflowEvent> {
+        let workflow = self.get_workflow_by_name(&name).await?;
+
+        let mut client = self.client().await;
+        let tx = expect_db(client.transaction().await);
+        // Another server may have claimed the row after our snapshot was taken; updating
+        // it makes Postgres re-check `status` against the committed row.
+        let claimed = expect_db(
+            tx.query_opt(
+                "UPDATE workflow_runs SET status = 'Started'
+                 WHERE run_id = (
+                     SELECT run_id FROM workflow_runs
+                     WHERE workflow_id = $1 AND status = 'Pending'
+                     ORDER BY position
+                     LIMIT 1
+                     FOR UPDATE SKIP LOCKED
+                 )
+                 AND status = 'Pending'
+                 RETURNING run_id",
+                &[&key(&workflow.id)],
+            )
+            .await,
+        )?;
+        let run_id: String = claimed.get(0);
+
+        let pending: WorkflowEvent = from_json(
+            expect_db(
+                tx.query_one(
+                    "SELECT event FROM workflow_events WHERE run_id = $1
+                     ORDER BY position DESC LIMIT 1",
+                    &[&run_id],
+                )
+                .await,
+            )
+            .get(0),
+        );
+        insert_workflow_event(&tx, &started_workflow_event(&pending)).await;
+        expect_db(tx.commit().await);
+
+        Some(pending)
+    }
+
+    async fn claim_pending_activity(&self, name: ActivityName) -> Option<ActivityEvent> {
+        let activity = self.get_activity_by_name(&name).await?;
+
+        let mut client = self.client().await;
+        let tx = expect_db(client.transaction().await);
+        let claimed = expect_db(
+            tx.query_opt(
+                "UPDATE activity_runs SET status = 'Started', scheduled_for = NULL
+                 WHERE run_id = (
+                     SELECT run_id FROM activity_runs
+                     WHERE activity_id = $1 AND status = 'Pending'
+                       AND (scheduled_for IS NULL OR scheduled_for <= now())
+                     ORDER BY position
+                     LIMIT 1
+                     FOR UPDATE SKIP LOCKED
+                 )
+                 AND status = 'Pending'
+                 RETURNING run_id",
+                &[&key(&activity.id)],
+            )
+            .await,
+        )?;
+        let run_id: String = claimed.get(0);
+
+        let pending: ActivityEvent = from_json(
+            expect_db(
+                tx.query_one(
+                    "SELECT event FROM activity_events WHERE run_id = $1
+                     ORDER BY position DESC LIMIT 1",
+                    &[&run_id],
+                )
+                .await,
+            )
+            .get(0),
+        );
+        insert_activity_event(&tx, &started_activity_event(&pending)).await;
+        expect_db(tx.commit().await);
+
+        Some(pending)
+    }
+}
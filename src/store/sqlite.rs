@@ -0,0 +1,562 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use rusqlite::types::Type;
+use rusqlite::{
+    params, params_from_iter, Connection, OptionalExtension, Transaction, TransactionBehavior,
+};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::core::activity::{
+    Activity, ActivityEvent, ActivityHeartbeat, ActivityId, ActivityName, ActivityRunId,
+};
+use crate::core::workflow::{
+    Workflow, WorkflowCommand, WorkflowEvent, WorkflowId, WorkflowName, WorkflowRunId,
+    WorkflowSignal,
+};
+use crate::store::{
+    is_same_event, key, started_activity_event, started_workflow_event, to_json, Store,
+};
+
+const SCHEMA: &str = "
+    PRAGMA journal_mode = WAL;
+
+    CREATE TABLE IF NOT EXISTS workflows (
+        name TEXT PRIMARY KEY,
+        workflow TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS workflow_events (
+        position INTEGER PRIMARY KEY AUTOINCREMENT,
+        workflow_id TEXT NOT NULL,
+        run_id TEXT NOT NULL,
+        event_type TEXT NOT NULL,
+        event TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS workflow_events_run_id ON workflow_events (run_id);
+    CREATE INDEX IF NOT EXISTS workflow_events_workflow_id ON workflow_events (workflow_id);
+    CREATE TABLE IF NOT EXISTS workflow_commands (
+        run_id TEXT NOT NULL,
+        sequence INTEGER NOT NULL,
+        command TEXT NOT NULL
+    );
+    CREATE UNIQUE INDEX IF NOT EXISTS workflow_commands_run_id_sequence
+        ON workflow_commands (run_id, sequence);
+    CREATE TABLE IF NOT EXISTS workflow_signals (
+        position INTEGER PRIMARY KEY AUTOINCREMENT,
+        run_id TEXT NOT NULL,
+        signal TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS workflow_signals_run_id ON workflow_signals (run_id);
+    CREATE TABLE IF NOT EXISTS workflow_heartbeats (
+        run_id TEXT PRIMARY KEY,
+        created_at TEXT NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS activities (
+        name TEXT PRIMARY KEY,
+        activity TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS activity_events (
+        position INTEGER PRIMARY KEY AUTOINCREMENT,
+        activity_id TEXT NOT NULL,
+        run_id TEXT NOT NULL,
+        event_type TEXT NOT NULL,
+        event TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS activity_events_run_id ON activity_events (run_id);
+    CREATE INDEX IF NOT EXISTS activity_events_activity_id ON activity_events (activity_id);
+    CREATE TABLE IF NOT EXISTS activity_heartbeats (
+        run_id TEXT PRIMARY KEY,
+        heartbeat TEXT NOT NULL
+    );
+";
+
+/// Durable store in a single SQLite file. Rows keep the queryable columns (ids, event
+/// type) alongside the full record as JSON.
+#[derive(Clone)]
+pub struct SqliteStore {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl SqliteStore {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, String> {
+        let conn = Connection::open(path).map_err(|e| e.to_string())?;
+        conn.execute_batch(SCHEMA).map_err(|e| e.to_string())?;
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Runs `f` on the blocking thread pool. The server can't make progress without its
+    /// store, so storage errors panic instead of being dropped.
+    async fn with_conn<T, F>(&self, f: F) -> T
+    where
+        F: FnOnce(&mut Connection) -> rusqlite::Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = conn.lock().expect("sqlite connection poisoned");
+            f(&mut conn)
+        })
+        .await
+        .expect("sqlite task panicked")
+        .unwrap_or_else(|e| panic!("sqlite store: {e}"))
+    }
+}
+
+fn from_key<T: DeserializeOwned>(key: String) -> rusqlite::Result<T> {
+    serde_json::from_value(Value::String(key))
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(0, Type::Text, Box::new(e)))
+}
+
+fn from_json<T: DeserializeOwned>(json: String) -> rusqlite::Result<T> {
+    serde_json::from_str(&json)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(0, Type::Text, Box::new(e)))
+}
+
+fn query_json<T: DeserializeOwned>(
+    conn: &Connection,
+    sql: &str,
+    param: String,
+) -> rusqlite::Result<Vec<T>> {
+    let mut statement = conn.prepare_cached(sql)?;
+    let rows = statement.query_map([param], |row| row.get::<_, String>(0))?;
+    rows.map(|json| from_json(json?)).collect()
+}
+
+fn query_keys<T: DeserializeOwned>(
+    conn: &Connection,
+    sql: &str,
+    param: Option<String>,
+) -> rusqlite::Result<Vec<T>> {
+    let mut statement = conn.prepare_cached(sql)?;
+    let rows = statement.query_map(params_from_iter(param), |row| row.get::<_, String>(0))?;
+    rows.map(|key| from_key(key?)).collect()
+}
+
+fn insert_workflow_event(conn: &Connection, event: &WorkflowEvent) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO workflow_events (workflow_id, run_id, event_type, event)
+         VALUES (?1, ?2, ?3, ?4)",
+        params![
+            key(&event.workflow_id),
+            key(&event.run_id),
+            key(&event.event_type),
+            to_json(event)
+        ],
+    )
+    .map(|_| ())
+}
+
+fn insert_activity_event(conn: &Connection, event: &ActivityEvent) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO activity_events (activity_id, run_id, event_type, event)
+         VALUES (?1, ?2, ?3, ?4)",
+        params![
+            key(&event.activity_id),
+            key(&event.activity_run_id),
+            key(&event.event_type),
+            to_json(event)
+        ],
+    )
+    .map(|_| ())
+}
+
+/// Takes the write lock up front, so two processes sharing the file can't both read the
+/// same last event and then both append after it.
+fn write_transaction(conn: &mut Connection) -> rusqlite::Result<Transaction<'_>> {
+    conn.transaction_with_behavior(TransactionBehavior::Immediate)
+}
+
+/// The single record `sql` selects for `param`, if any.
+fn query_one_json<T: DeserializeOwned>(
+    conn: &Connection,
+    sql: &str,
+    param: String,
+) -> rusqlite::Result<Option<T>> {
+    conn.query_row(sql, [param], |row| row.get::<_, String>(0))
+        .optional()?
+        .map(from_json)
+        .transpose()
+}
+
+/// Runs of `id` in `table` whose last event is `Pending`, oldest run first.
+fn pending_last_events<T: DeserializeOwned>(
+    conn: &Connection,
+    table: &str,
+    id_column: &str,
+    id: String,
+) -> rusqlite::Result<Vec<T>> {
+    query_json(
+        conn,
+        &format!(
+            "SELECT e.event FROM {table} e
+             JOIN (SELECT MIN(position) AS first, MAX(position) AS last FROM {table}
+                   WHERE {id_column} = ?1 GROUP BY run_id) runs
+               ON e.position = runs.last
+             WHERE e.event_type = 'Pending'
+             ORDER BY runs.first"
+        ),
+        id,
+    )
+}
+
+/// The last event recorded in `table` for `run_id`, if any.
+fn last_event<T: DeserializeOwned>(
+    conn: &Connection,
+    table: &str,
+    run_id: String,
+) -> rusqlite::Result<Option<T>> {
+    conn.query_row(
+        &format!("SELECT event FROM {table} WHERE run_id = ?1 ORDER BY position DESC LIMIT 1"),
+        [run_id],
+        |row| row.get::<_, String>(0),
+    )
+    .optional()?
+    .map(from_json)
+    .transpose()
+}
+
+#[async_trait::async_trait]
+impl Store for SqliteStore {
+    async fn add_workflow(&self, workflow: Workflow) {
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO workflows (name, workflow) VALUES (?1, ?2)",
+                params![key(&workflow.name), to_json(&workflow)],
+            )
+            .map(|_| ())
+        })
+        .await
+    }
+
+    async fn get_workflow_by_name(&self, name: &WorkflowName) -> Option<Workflow> {
+        let name = key(name);
+        self.with_conn(move |conn| {
+            query_one_json(conn, "SELECT workflow FROM workflows WHERE name = ?1", name)
+        })
+        .await
+    }
+
+    async fn add_activity(&self, activity: Activity) {
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO activities (name, activity) VALUES (?1, ?2)",
+                params![key(&activity.name), to_json(&activity)],
+            )
+            .map(|_| ())
+        })
+        .await
+    }
+
+    async fn get_activity_by_name(&self, name: &ActivityName) -> Option<Activity> {
+        let name = key(name);
+        self.with_conn(move |conn| {
+            query_one_json(
+                conn,
+                "SELECT activity FROM activities WHERE name = ?1",
+                name,
+            )
+        })
+        .await
+    }
+
+    async fn add_workflow_event(&self, event: WorkflowEvent) {
+        self.with_conn(move |conn| insert_workflow_event(conn, &event))
+            .await
+    }
+
+    async fn add_activity_events(&self, events: Vec<ActivityEvent>) {
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            for event in &events {
+                insert_activity_event(&tx, event)?;
+            }
+            tx.commit()
+        })
+        .await
+    }
+
+    async fn add_workflow_events_if_last(
+        &self,
+        expected: &WorkflowEvent,
+        events: Vec<WorkflowEvent>,
+    ) -> bool {
+        let expected = expected.clone();
+        self.with_conn(move |conn| {
+            let tx = write_transaction(conn)?;
+            let last: Option<WorkflowEvent> =
+                last_event(&tx, "workflow_events", key(&expected.run_id))?;
+            if !last.is_some_and(|last| is_same_event(&last, &expected)) {
+                return Ok(false);
+            }
+            for event in &events {
+                insert_workflow_event(&tx, event)?;
+            }
+            tx.commit()?;
+            Ok(true)
+        })
+        .await
+    }
+
+    async fn add_activity_events_if_last(
+        &self,
+        expected: &ActivityEvent,
+        events: Vec<ActivityEvent>,
+    ) -> bool {
+        let expected = expected.clone();
+        self.with_conn(move |conn| {
+            let tx = write_transaction(conn)?;
+            let last: Option<ActivityEvent> =
+                last_event(&tx, "activity_events", key(&expected.activity_run_id))?;
+            if !last.is_some_and(|last| is_same_event(&last, &expected)) {
+                return Ok(false);
+            }
+            for event in &events {
+                insert_activity_event(&tx, event)?;
+            }
+            tx.commit()?;
+            Ok(true)
+        })
+        .await
+    }
+
+    async fn get_workflow_runs(&self, workflow_id: WorkflowId) -> Vec<WorkflowRunId> {
+        let workflow_id = key(&workflow_id);
+        self.with_conn(move |conn| {
+            query_keys(
+                conn,
+                "SELECT run_id FROM workflow_events WHERE workflow_id = ?1
+                 GROUP BY run_id ORDER BY MIN(position)",
+                Some(workflow_id),
+            )
+        })
+        .await
+    }
+
+    async fn get_activity_runs(&self, activity_id: ActivityId) -> Vec<ActivityRunId> {
+        let activity_id = key(&activity_id);
+        self.with_conn(move |conn| {
+            query_keys(
+                conn,
+                "SELECT run_id FROM activity_events WHERE activity_id = ?1
+                 GROUP BY run_id ORDER BY MIN(position)",
+                Some(activity_id),
+            )
+        })
+        .await
+    }
+
+    async fn get_workflow_run_events(&self, workflow_run_id: WorkflowRunId) -> Vec<WorkflowEvent> {
+        let run_id = key(&workflow_run_id);
+        self.with_conn(move |conn| {
+            query_json(
+                conn,
+                "SELECT event FROM workflow_events WHERE run_id = ?1 ORDER BY position",
+                run_id,
+            )
+        })
+        .await
+    }
+
+    async fn get_activity_run_events(&self, activity_run_id: ActivityRunId) -> Vec<ActivityEvent> {
+        let run_id = key(&activity_run_id);
+        self.with_conn(move |conn| {
+            query_json(
+                conn,
+                "SELECT event FROM activity_events WHERE run_id = ?1 ORDER BY position",
+                run_id,
+            )
+        })
+        .await
+    }
+
+    async fn get_unfinished_workflow_runs(&self) -> Vec<WorkflowRunId> {
+        self.with_conn(|conn| {
+            query_keys(
+                conn,
+                "SELECT e.run_id FROM workflow_events e
+                 JOIN (SELECT MAX(position) AS position FROM workflow_events GROUP BY run_id) last
+                   ON e.position = last.position
+                 WHERE e.event_type IN ('Pending', 'Started', 'TimerStarted', 'TimerFired',
+                 'WaitingForChild', 'CancelRequested')",
+                None,
+            )
+        })
+        .await
+    }
+
+    async fn get_unfinished_activity_runs(&self) -> Vec<ActivityRunId> {
+        self.with_conn(|conn| {
+            query_keys(
+                conn,
+                "SELECT e.run_id FROM activity_events e
+                 JOIN (SELECT MAX(position) AS position FROM activity_events GROUP BY run_id) last
+                   ON e.position = last.position
+                 WHERE e.event_type IN ('Pending', 'Started')",
+                None,
+            )
+        })
+        .await
+    }
+
+    async fn claim_pending_workflow(&self, name: WorkflowName) -> Option<WorkflowEvent> {
+        let name = key(&name);
+        self.with_conn(move |conn| {
+            let tx = write_transaction(conn)?;
+            let workflow: Option<Workflow> =
+                query_one_json(&tx, "SELECT workflow FROM workflows WHERE name = ?1", name)?;
+            let Some(workflow) = workflow else {
+                return Ok(None);
+            };
+            let pending: Vec<WorkflowEvent> =
+                pending_last_events(&tx, "workflow_events", "workflow_id", key(&workflow.id))?;
+            let Some(pending) = pending.into_iter().next() else {
+                return Ok(None);
+            };
+            insert_workflow_event(&tx, &started_workflow_event(&pending))?;
+            tx.commit()?;
+            Ok(Some(pending))
+        })
+        .await
+    }
+
+    async fn claim_pending_activity(&self, name: ActivityName) -> Option<ActivityEvent> {
+        let name = key(&name);
+        self.with_conn(move |conn| {
+            let tx = write_transaction(conn)?;
+            let activity: Option<Activity> =
+                query_one_json(&tx, "SELECT activity FROM activities WHERE name = ?1", name)?;
+            let Some(activity) = activity else {
+                return Ok(None);
+            };
+            let now = Utc::now();
+            let pending: Vec<ActivityEvent> =
+                pending_last_events(&tx, "activity_events", "activity_id", key(&activity.id))?;
+            let Some(pending) = pending.into_iter().find(|pending| {
+                pending
+                    .scheduled_for
+                    .is_none_or(|scheduled_for| scheduled_for <= now)
+            }) else {
+                return Ok(None);
+            };
+            insert_activity_event(&tx, &started_activity_event(&pending))?;
+            tx.commit()?;
+            Ok(Some(pending))
+        })
+        .await
+    }
+
+    async fn add_workflow_command(&self, command: WorkflowCommand) -> bool {
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT OR IGNORE INTO workflow_commands (run_id, sequence, command)
+                 VALUES (?1, ?2, ?3)",
+                params![
+                    key(&command.workflow_run_id),
+                    command.sequence,
+                    to_json(&command)
+                ],
+            )
+            .map(|inserted| inserted == 1)
+        })
+        .await
+    }
+
+    async fn get_workflow_commands(&self, workflow_run_id: WorkflowRunId) -> Vec<WorkflowCommand> {
+        let run_id = key(&workflow_run_id);
+        self.with_conn(move |conn| {
+            query_json(
+                conn,
+                "SELECT command FROM workflow_commands WHERE run_id = ?1 ORDER BY sequence",
+                run_id,
+            )
+        })
+        .await
+    }
+
+    async fn add_workflow_signal(&self, signal: WorkflowSignal) {
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT INTO workflow_signals (run_id, signal) VALUES (?1, ?2)",
+                params![key(&signal.workflow_run_id), to_json(&signal)],
+            )
+            .map(|_| ())
+        })
+        .await
+    }
+
+    async fn get_workflow_signals(&self, workflow_run_id: WorkflowRunId) -> Vec<WorkflowSignal> {
+        let run_id = key(&workflow_run_id);
+        self.with_conn(move |conn| {
+            query_json(
+                conn,
+                "SELECT signal FROM workflow_signals WHERE run_id = ?1 ORDER BY position",
+                run_id,
+            )
+        })
+        .await
+    }
+
+    async fn record_workflow_heartbeat(&self, workflow_run_id: WorkflowRunId) {
+        let run_id = key(&workflow_run_id);
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO workflow_heartbeats (run_id, created_at) VALUES (?1, ?2)",
+                params![run_id, to_json(&Utc::now())],
+            )
+            .map(|_| ())
+        })
+        .await
+    }
+
+    async fn get_last_workflow_heartbeat(
+        &self,
+        workflow_run_id: WorkflowRunId,
+    ) -> Option<DateTime<Utc>> {
+        let run_id = key(&workflow_run_id);
+        self.with_conn(move |conn| {
+            conn.query_row(
+                "SELECT created_at FROM workflow_heartbeats WHERE run_id = ?1",
+                [run_id],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()?
+            .map(from_json)
+            .transpose()
+        })
+        .await
+    }
+
+    async fn record_activity_heartbeat(&self, heartbeat: ActivityHeartbeat) {
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO activity_heartbeats (run_id, heartbeat) VALUES (?1, ?2)",
+                params![key(&heartbeat.activity_run_id), to_json(&heartbeat)],
+            )
+            .map(|_| ())
+        })
+        .await
+    }
+
+    async fn get_last_activity_heartbeat(
+        &self,
+        activity_run_id: ActivityRunId,
+    ) -> Option<ActivityHeartbeat> {
+        let run_id = key(&activity_run_id);
+        self.with_conn(move |conn| {
+            conn.query_row(
+                "SELECT heartbeat FROM activity_heartbeats WHERE run_id = ?1",
+                [run_id],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()?
+            .map(from_json)
+            .transpose()
+        })
+        .await
+    }
+}
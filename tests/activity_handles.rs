@@ -0,0 +1,141 @@
+mod common;
+
+use std::sync::Mutex;
+use std::time::Duration;
+
+use jamesporal::core::payload::from_payload;
+use jamesporal::core::workflow::{WorkflowEventType, WorkflowOptions};
+use jamesporal::core::{
+    ActivityContext, ActivityHandle, ActivityHandler, Client, WorkflowContext, WorkflowHandler,
+};
+use jamesporal::inmemory_db::Db;
+
+/// Returns its tag after sleeping for the given number of milliseconds.
+struct Delay;
+
+#[async_trait::async_trait]
+impl ActivityHandler for Delay {
+    type Input = (u64, String);
+    type Output = String;
+    type Error = String;
+
+    async fn run(
+        &self,
+        _context: ActivityContext,
+        (millis, tag): (u64, String),
+    ) -> Result<String, String> {
+        tokio::time::sleep(Duration::from_millis(millis)).await;
+        Ok(tag)
+    }
+}
+
+/// Runs a `Delay` for each input at once and joins them.
+struct Joined;
+
+#[async_trait::async_trait]
+impl WorkflowHandler for Joined {
+    type Input = Vec<(u64, String)>;
+    type Output = Vec<String>;
+    type Error = String;
+
+    async fn run(
+        &self,
+        mut context: WorkflowContext,
+        inputs: Vec<(u64, String)>,
+    ) -> Result<Vec<String>, String> {
+        let mut handles = vec![];
+        for input in inputs {
+            handles.push(context.start_typed_activity(Delay, input).await?);
+        }
+        ActivityHandle::join_all(&handles)
+            .await
+            .into_iter()
+            .map(|result| result.map_err(|e| format!("{e:?}")))
+            .collect()
+    }
+}
+
+/// Indexes `Raced` picked, in the order its executions picked them.
+static SELECTED: Mutex<Vec<usize>> = Mutex::new(vec![]);
+
+/// Races a slow activity against a fast one, then sleeps so that the run is replayed
+/// once both have finished.
+struct Raced;
+
+#[async_trait::async_trait]
+impl WorkflowHandler for Raced {
+    type Input = ();
+    type Output = String;
+    type Error = String;
+
+    async fn run(&self, mut context: WorkflowContext, _input: ()) -> Result<String, String> {
+        let slow = context
+            .start_typed_activity(Delay, (500, "slow".to_string()))
+            .await?;
+        let fast = context
+            .start_typed_activity(Delay, (50, "fast".to_string()))
+            .await?;
+        let (index, result) = ActivityHandle::select(&[slow, fast]).await?;
+        SELECTED.lock().unwrap().push(index);
+
+        context.sleep(Duration::from_millis(800)).await?;
+        result.map_err(|e| format!("{e:?}"))
+    }
+}
+
+/// Workers run one activity of a kind at a time, so it takes several to run them at once.
+const WORKERS: usize = 4;
+
+async fn start_workers() -> Client {
+    let url = common::start_server(Db::new()).await;
+    for _ in 0..WORKERS {
+        let handlers = vec![
+            common::activity(Delay),
+            common::workflow(Joined),
+            common::workflow(Raced),
+        ];
+        common::start_worker(&url, handlers).await;
+    }
+    Client::new(url)
+}
+
+#[tokio::test]
+async fn joined_results_keep_the_order_of_the_handles() {
+    let client = start_workers().await;
+
+    let inputs = vec![
+        (300, "first".to_string()),
+        (0, "second".to_string()),
+        (150, "third".to_string()),
+        (0, "fourth".to_string()),
+    ];
+    let (_, completion) =
+        common::run_workflow(&client, &Joined, inputs, WorkflowOptions::default()).await;
+    assert_eq!(
+        completion.status,
+        WorkflowEventType::Succeeeded,
+        "{}",
+        completion.error
+    );
+    assert_eq!(
+        from_payload::<Vec<String>>(&completion.result).unwrap(),
+        ["first", "second", "third", "fourth"]
+    );
+}
+
+#[tokio::test]
+async fn selects_pick_the_same_activity_when_replayed() {
+    let client = start_workers().await;
+
+    let (_, completion) =
+        common::run_workflow(&client, &Raced, (), WorkflowOptions::default()).await;
+    assert_eq!(
+        completion.status,
+        WorkflowEventType::Succeeeded,
+        "{}",
+        completion.error
+    );
+    assert_eq!(from_payload::<String>(&completion.result).unwrap(), "fast");
+    // Once before the sleep, and once when replayed with both activities finished.
+    assert_eq!(*SELECTED.lock().unwrap(), [1, 1]);
+}
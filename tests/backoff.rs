@@ -0,0 +1,92 @@
+use std::time::Duration;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use jamesporal::core::RetryOptions;
+
+fn options(backoff_coefficient: f64, jitter: f64) -> RetryOptions {
+    RetryOptions {
+        max_attempts: 10,
+        initial_interval: Duration::from_secs(1),
+        backoff_coefficient,
+        maximum_interval: Duration::from_secs(30),
+        jitter,
+        non_retryable_error_kinds: vec![],
+    }
+}
+
+#[test]
+fn delay_grows_by_the_coefficient_up_to_the_maximum() {
+    let options = options(2.0, 0.0);
+    let delays: Vec<u64> = (1..=7)
+        .map(|attempt| options.next_retry_delay(attempt).as_secs())
+        .collect();
+    assert_eq!(delays, vec![1, 2, 4, 8, 16, 30, 30]);
+}
+
+#[test]
+fn huge_attempt_numbers_stay_at_the_maximum() {
+    let options = options(2.0, 0.0);
+    assert_eq!(options.next_retry_delay(i64::MAX), Duration::from_secs(30));
+    assert_eq!(options.next_retry_delay(0), Duration::from_secs(1));
+    assert_eq!(options.next_retry_delay(-5), Duration::from_secs(1));
+}
+
+#[test]
+fn jitter_stays_within_its_fraction_and_the_maximum() {
+    let options = options(1.0, 0.5);
+    for _ in 0..1000 {
+        let delay = options.next_retry_delay(1);
+        assert!(delay >= Duration::from_millis(500), "{delay:?}");
+        assert!(delay <= Duration::from_millis(1500), "{delay:?}");
+    }
+
+    let at_maximum = RetryOptions {
+        initial_interval: Duration::from_secs(30),
+        ..options
+    };
+    for _ in 0..1000 {
+        assert!(at_maximum.next_retry_delay(1) <= Duration::from_secs(30));
+    }
+}
+
+#[test]
+fn unvalidated_options_fall_back_to_the_maximum_instead_of_panicking() {
+    assert_eq!(
+        options(f64::NAN, 0.0).next_retry_delay(2),
+        Duration::from_secs(30)
+    );
+    assert!(options(1.0, f64::NAN).next_retry_delay(2) <= Duration::from_secs(30));
+}
+
+#[test]
+fn validate_rejects_non_finite_and_negative_options() {
+    assert!(options(2.0, 0.1).validate().is_ok());
+    assert!(options(0.0, 0.0).validate().is_ok());
+    for (backoff_coefficient, jitter) in [
+        (f64::NAN, 0.0),
+        (f64::INFINITY, 0.0),
+        (-1.0, 0.0),
+        (2.0, f64::NAN),
+        (2.0, f64::NEG_INFINITY),
+        (2.0, -0.1),
+    ] {
+        let error = options(backoff_coefficient, jitter).validate().unwrap_err();
+        assert!(error.starts_with("InvalidInput:"), "{error}");
+    }
+}
+
+#[test]
+fn retry_time_saturates_instead_of_wrapping_to_now() {
+    let now = Utc::now();
+    assert_eq!(
+        options(2.0, 0.0).next_retry_at(now, 3),
+        now + TimeDelta::seconds(4)
+    );
+
+    let forever = RetryOptions {
+        initial_interval: Duration::MAX,
+        maximum_interval: Duration::MAX,
+        ..options(2.0, 0.0)
+    };
+    assert_eq!(forever.next_retry_at(now, 1), DateTime::<Utc>::MAX_UTC);
+}
@@ -0,0 +1,174 @@
+mod common;
+
+use std::time::Duration;
+
+use jamesporal::core::activity::ActivityEventType;
+use jamesporal::core::workflow::{WorkflowEventType, WorkflowOptions};
+use jamesporal::core::{
+    ActivityContext, ActivityHandler, Client, HandlerError, WorkflowContext, WorkflowHandler,
+};
+use jamesporal::inmemory_db::Db;
+use jamesporal::store::{SqliteStore, Store};
+
+/// Runs until its attempt is cancelled.
+struct Blocker;
+
+#[async_trait::async_trait]
+impl ActivityHandler for Blocker {
+    type Input = ();
+    type Output = ();
+    type Error = String;
+
+    async fn run(&self, context: ActivityContext, _input: ()) -> Result<(), String> {
+        context.cancellation.cancelled().await;
+        Err("Stopped: the attempt was cancelled".to_string())
+    }
+}
+
+struct Cleanup;
+
+#[async_trait::async_trait]
+impl ActivityHandler for Cleanup {
+    type Input = ();
+    type Output = ();
+    type Error = String;
+
+    async fn run(&self, _context: ActivityContext, _input: ()) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Cleans up after its `Blocker` is cancelled, then fails with the cancellation.
+struct CleanedUp;
+
+#[async_trait::async_trait]
+impl WorkflowHandler for CleanedUp {
+    type Input = ();
+    type Output = ();
+    type Error = String;
+
+    async fn run(&self, mut context: WorkflowContext, _input: ()) -> Result<(), String> {
+        let error = match context.execute_typed_activity(Blocker, ()).await {
+            Err(HandlerError::Handler(error) | HandlerError::Other(error)) => error,
+            other => return Err(format!("Unexpected: {other:?}")),
+        };
+        context
+            .execute_typed_activity(Cleanup, ())
+            .await
+            .map_err(|e| format!("{e:?}"))?;
+        Err(error)
+    }
+}
+
+/// Waits for a signal that never comes.
+struct Idle;
+
+#[async_trait::async_trait]
+impl WorkflowHandler for Idle {
+    type Input = ();
+    type Output = ();
+    type Error = String;
+
+    async fn run(&self, mut context: WorkflowContext, _input: ()) -> Result<(), String> {
+        context.wait_for_signal("never").await?;
+        Ok(())
+    }
+}
+
+fn handlers() -> Vec<common::Handler> {
+    vec![
+        common::activity(Blocker),
+        common::activity(Cleanup),
+        common::workflow(CleanedUp),
+        common::workflow(Idle),
+    ]
+}
+
+#[tokio::test]
+async fn cancelled_runs_clean_up_before_they_end() {
+    let db = Db::new();
+    let client = common::start_worker(&common::start_server(db.clone()).await, handlers()).await;
+
+    let run_id = common::start_workflow(&client, &CleanedUp, (), WorkflowOptions::default()).await;
+    common::wait_until(|| async {
+        common::activity_events(&db, run_id)
+            .await
+            .first()
+            .and_then(|events| events.last())
+            .is_some_and(|event| event.event_type == ActivityEventType::Started)
+    })
+    .await;
+
+    client.cancel_workflow(run_id).await.unwrap();
+    let completion = common::wait_for_completion(&client, run_id).await;
+    assert_eq!(completion.status, WorkflowEventType::Cancelled);
+    assert!(
+        completion.error.starts_with("Cancelled"),
+        "{}",
+        completion.error
+    );
+
+    let runs = common::activity_events(&db, run_id).await;
+    let last_event_types: Vec<_> = runs
+        .iter()
+        .map(|events| events.last().unwrap().event_type.clone())
+        .collect();
+    assert_eq!(
+        last_event_types,
+        [ActivityEventType::Cancelled, ActivityEventType::Succeeeded]
+    );
+}
+
+/// Cancels an `Idle` run many times at once through servers sharing `stores`, and checks
+/// the request is recorded once.
+async fn assert_cancelled_once(stores: [impl Store + Clone + 'static; 2]) {
+    let [first, second] = stores;
+    let client = common::start_worker(
+        &common::start_server(first.clone()).await,
+        vec![common::workflow(Idle)],
+    )
+    .await;
+    let clients = [client, Client::new(common::start_server(second).await)];
+
+    let run_id = common::start_workflow(&clients[0], &Idle, (), WorkflowOptions::default()).await;
+    common::wait_until(|| async {
+        first
+            .get_last_workflow_run_event(run_id)
+            .await
+            .is_some_and(|event| event.event_type == WorkflowEventType::Started)
+    })
+    .await;
+    // Give the worker time to start waiting for the signal.
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let cancels = (0..32).map(|i| clients[i % clients.len()].cancel_workflow(run_id));
+    for cancelled in futures::future::join_all(cancels).await {
+        cancelled.unwrap();
+    }
+    let completion = common::wait_for_completion(&clients[0], run_id).await;
+    assert_eq!(completion.status, WorkflowEventType::Cancelled);
+
+    let requests = first
+        .get_workflow_run_events(run_id)
+        .await
+        .into_iter()
+        .filter(|event| event.event_type == WorkflowEventType::CancelRequested)
+        .count();
+    assert_eq!(requests, 1);
+}
+
+#[tokio::test]
+async fn concurrent_cancels_are_recorded_once() {
+    let db = Db::new();
+    assert_cancelled_once([db.clone(), db]).await;
+}
+
+#[tokio::test]
+async fn concurrent_cancels_through_sqlite_are_recorded_once() {
+    let path = common::temp_path("cancels.sqlite");
+    assert_cancelled_once([
+        SqliteStore::open(&path).unwrap(),
+        SqliteStore::open(&path).unwrap(),
+    ])
+    .await;
+}
@@ -0,0 +1,156 @@
+mod common;
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use jamesporal::core::activity::{ActivityEventType, ActivityRunId};
+use jamesporal::core::workflow::{WorkflowOptions, WorkflowRunId};
+use jamesporal::core::{
+    ActivityContext, ActivityHandler, ActivityOptions, Client, RetryOptions, WorkflowContext,
+    WorkflowHandler,
+};
+use jamesporal::inmemory_db::Db;
+
+/// Whether each `Watched` attempt saw its token fire, by activity run.
+static FIRED: Mutex<Option<HashMap<ActivityRunId, bool>>> = Mutex::new(None);
+
+fn fired(activity_run_id: ActivityRunId) -> Option<bool> {
+    FIRED
+        .lock()
+        .unwrap()
+        .get_or_insert_default()
+        .get(&activity_run_id)
+        .copied()
+}
+
+/// Runs until its token fires. With `heartbeat`, it heartbeats every 50ms instead and
+/// stops at the first failed heartbeat, recording whether the token had fired by then.
+struct Watched;
+
+#[async_trait::async_trait]
+impl ActivityHandler for Watched {
+    type Input = bool;
+    type Output = ();
+    type Error = String;
+
+    async fn run(&self, context: ActivityContext, heartbeat: bool) -> Result<(), String> {
+        if heartbeat {
+            while context.heartbeat("").await.is_ok() {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        } else {
+            context.cancellation.cancelled().await;
+        }
+        FIRED
+            .lock()
+            .unwrap()
+            .get_or_insert_default()
+            .insert(context.activity_run_id, context.cancellation.is_cancelled());
+        Err("Stopped: the attempt is no longer wanted".to_string())
+    }
+}
+
+/// Runs a `Watched` activity, which times out after `timeout_ms` if given.
+struct WatchedWorkflow;
+
+#[async_trait::async_trait]
+impl WorkflowHandler for WatchedWorkflow {
+    type Input = (bool, Option<u64>);
+    type Output = ();
+    type Error = String;
+
+    async fn run(
+        &self,
+        mut context: WorkflowContext,
+        (heartbeat, timeout_ms): (bool, Option<u64>),
+    ) -> Result<(), String> {
+        context.with_activity_options(ActivityOptions {
+            retry_policy: RetryOptions {
+                max_attempts: 1,
+                ..Default::default()
+            },
+            schedule_to_close_timeout: timeout_ms.map(Duration::from_millis),
+            ..Default::default()
+        });
+        context
+            .execute_typed_activity(Watched, heartbeat)
+            .await
+            .map_err(|e| format!("{e:?}"))
+    }
+}
+
+fn handlers() -> Vec<common::Handler> {
+    vec![common::activity(Watched), common::workflow(WatchedWorkflow)]
+}
+
+/// Starts a worker behind one server and returns a client of another one sharing its
+/// `Db`, so that the worker's server doesn't see what is done through the client.
+async fn start_worker_elsewhere(db: &Db) -> Client {
+    common::start_worker(&common::start_server(db.clone()).await, handlers()).await;
+    Client::new(common::start_server(db.clone()).await)
+}
+
+/// Starts a `WatchedWorkflow` and waits for its activity to start.
+async fn start_watched(
+    db: &Db,
+    client: &Client,
+    input: (bool, Option<u64>),
+) -> (WorkflowRunId, ActivityRunId) {
+    let run_id =
+        common::start_workflow(client, &WatchedWorkflow, input, WorkflowOptions::default()).await;
+    common::wait_until(|| async {
+        common::activity_events(db, run_id)
+            .await
+            .first()
+            .and_then(|events| events.last())
+            .is_some_and(|event| event.event_type == ActivityEventType::Started)
+    })
+    .await;
+    let events = common::activity_events(db, run_id).await;
+    (run_id, events[0][0].activity_run_id)
+}
+
+async fn assert_fired(activity_run_id: ActivityRunId) {
+    common::wait_until(|| async { fired(activity_run_id).is_some() }).await;
+    assert_eq!(fired(activity_run_id), Some(true));
+}
+
+#[tokio::test]
+async fn tokens_fire_when_the_workflow_is_cancelled() {
+    let db = Db::new();
+    let client = common::start_worker(&common::start_server(db.clone()).await, handlers()).await;
+
+    let (run_id, activity_run_id) = start_watched(&db, &client, (false, None)).await;
+    client.cancel_workflow(run_id).await.unwrap();
+    assert_fired(activity_run_id).await;
+}
+
+#[tokio::test]
+async fn tokens_fire_when_the_workflow_is_cancelled_through_another_server() {
+    let db = Db::new();
+    let client = start_worker_elsewhere(&db).await;
+
+    let (run_id, activity_run_id) = start_watched(&db, &client, (false, None)).await;
+    client.cancel_workflow(run_id).await.unwrap();
+    assert_fired(activity_run_id).await;
+}
+
+#[tokio::test]
+async fn tokens_fire_when_the_activity_times_out() {
+    let db = Db::new();
+    let client = common::start_worker(&common::start_server(db.clone()).await, handlers()).await;
+
+    let (_, activity_run_id) = start_watched(&db, &client, (false, Some(500))).await;
+    assert_fired(activity_run_id).await;
+}
+
+#[tokio::test]
+async fn failed_heartbeats_fire_the_token() {
+    let db = Db::new();
+    let client = start_worker_elsewhere(&db).await;
+
+    let (run_id, activity_run_id) = start_watched(&db, &client, (true, None)).await;
+    client.cancel_workflow(run_id).await.unwrap();
+    assert_fired(activity_run_id).await;
+}
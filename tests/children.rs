@@ -0,0 +1,188 @@
+mod common;
+
+use std::time::Duration;
+
+use jamesporal::core::payload::from_payload;
+use jamesporal::core::workflow::{
+    ParentClosePolicy, WorkflowCommandType, WorkflowEventType, WorkflowOptions, WorkflowRunId,
+};
+use jamesporal::core::{Client, WorkflowContext, WorkflowHandler};
+use jamesporal::inmemory_db::Db;
+use jamesporal::store::Store;
+
+/// Counts down from its input by starting a child of its own type for each step.
+struct Countdown;
+
+#[async_trait::async_trait]
+impl WorkflowHandler for Countdown {
+    type Input = u32;
+    type Output = u32;
+    type Error = String;
+
+    async fn run(&self, mut context: WorkflowContext, from: u32) -> Result<u32, String> {
+        if from == 0 {
+            return Ok(0);
+        }
+        let rest = context
+            .execute_typed_child_workflow(Countdown, from - 1, WorkflowOptions::default())
+            .await?;
+        Ok(rest + 1)
+    }
+}
+
+/// Finishes once released by a signal.
+struct Waiter;
+
+#[async_trait::async_trait]
+impl WorkflowHandler for Waiter {
+    type Input = ();
+    type Output = ();
+    type Error = String;
+
+    async fn run(&self, mut context: WorkflowContext, _input: ()) -> Result<(), String> {
+        context.wait_for_signal("release").await?;
+        Ok(())
+    }
+}
+
+/// Waits for a `Waiter` child that closes with it according to `policy`.
+struct Guardian;
+
+#[async_trait::async_trait]
+impl WorkflowHandler for Guardian {
+    type Input = ParentClosePolicy;
+    type Output = ();
+    type Error = String;
+
+    async fn run(
+        &self,
+        mut context: WorkflowContext,
+        policy: ParentClosePolicy,
+    ) -> Result<(), String> {
+        let options = WorkflowOptions {
+            parent_close_policy: policy,
+            ..WorkflowOptions::default()
+        };
+        context
+            .execute_typed_child_workflow(Waiter, (), options)
+            .await?;
+        Ok(())
+    }
+}
+
+fn handlers() -> Vec<common::Handler> {
+    vec![
+        common::workflow(Countdown),
+        common::workflow(Waiter),
+        common::workflow(Guardian),
+    ]
+}
+
+async fn child_run_id(db: &Db, parent_run_id: WorkflowRunId) -> Option<WorkflowRunId> {
+    db.get_workflow_commands(parent_run_id)
+        .await
+        .into_iter()
+        .find_map(|command| match command.command_type {
+            WorkflowCommandType::StartChildWorkflow { child_run_id, .. } => Some(child_run_id),
+            _ => None,
+        })
+}
+
+#[tokio::test]
+async fn children_of_the_same_type_run_on_the_parents_worker() {
+    let db = Db::new();
+    let client = common::start_worker(&common::start_server(db.clone()).await, handlers()).await;
+
+    let (run_id, completion) =
+        common::run_workflow(&client, &Countdown, 3u32, WorkflowOptions::default()).await;
+    assert_eq!(
+        completion.status,
+        WorkflowEventType::Succeeeded,
+        "{}",
+        completion.error
+    );
+    assert_eq!(from_payload::<u32>(&completion.result).unwrap(), 3);
+
+    // The parent let go of the run while it waited, and was handed it back afterwards.
+    let event_types: Vec<_> = db
+        .get_workflow_run_events(run_id)
+        .await
+        .into_iter()
+        .map(|event| event.event_type)
+        .collect();
+    assert_eq!(
+        event_types,
+        [
+            WorkflowEventType::Pending,
+            WorkflowEventType::Started,
+            WorkflowEventType::WaitingForChild,
+            WorkflowEventType::Pending,
+            WorkflowEventType::Started,
+            WorkflowEventType::Succeeeded,
+        ]
+    );
+}
+
+/// Runs a `Guardian` that times out while waiting for its child, and returns the child.
+async fn time_out_waiting_parent(
+    db: &Db,
+    client: &Client,
+    policy: ParentClosePolicy,
+) -> WorkflowRunId {
+    let options = WorkflowOptions {
+        execution_timeout: Some(Duration::from_secs(1)),
+        ..WorkflowOptions::default()
+    };
+    let run_id = common::start_workflow(client, &Guardian, policy, options).await;
+    common::wait_until(|| async {
+        db.get_last_workflow_run_event(run_id)
+            .await
+            .is_some_and(|event| event.event_type == WorkflowEventType::WaitingForChild)
+    })
+    .await;
+
+    let completion = common::wait_for_completion(client, run_id).await;
+    assert_eq!(completion.status, WorkflowEventType::TimedOut);
+    child_run_id(db, run_id).await.unwrap()
+}
+
+#[tokio::test]
+async fn waiting_parents_that_time_out_terminate_their_children() {
+    let db = Db::new();
+    let client = common::start_worker(&common::start_server(db.clone()).await, handlers()).await;
+
+    let child = time_out_waiting_parent(&db, &client, ParentClosePolicy::Terminate).await;
+    let completion = common::wait_for_completion(&client, child).await;
+    assert_eq!(completion.status, WorkflowEventType::Terminated);
+}
+
+#[tokio::test]
+async fn waiting_parents_that_time_out_cancel_their_children() {
+    let db = Db::new();
+    let client = common::start_worker(&common::start_server(db.clone()).await, handlers()).await;
+
+    let child = time_out_waiting_parent(&db, &client, ParentClosePolicy::RequestCancel).await;
+    let completion = common::wait_for_completion(&client, child).await;
+    assert_eq!(completion.status, WorkflowEventType::Cancelled);
+}
+
+#[tokio::test]
+async fn waiting_parents_that_time_out_abandon_their_children() {
+    let db = Db::new();
+    let client = common::start_worker(&common::start_server(db.clone()).await, handlers()).await;
+
+    let child = time_out_waiting_parent(&db, &client, ParentClosePolicy::Abandon).await;
+    assert!(!db
+        .get_last_workflow_run_event(child)
+        .await
+        .unwrap()
+        .event_type
+        .is_finished());
+
+    client
+        .signal_workflow(child, "release".to_string(), "".to_string())
+        .await
+        .unwrap();
+    let completion = common::wait_for_completion(&client, child).await;
+    assert_eq!(completion.status, WorkflowEventType::Succeeeded);
+}
@@ -0,0 +1,264 @@
+mod common;
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{TimeDelta, Utc};
+use jamesporal::core::activity::{
+    Activity, ActivityContext, ActivityEvent, ActivityEventType, ActivityId, ActivityName,
+    ActivityRunId,
+};
+use jamesporal::core::workflow::{
+    Workflow, WorkflowContext, WorkflowEvent, WorkflowEventType, WorkflowId, WorkflowName,
+    WorkflowRunId,
+};
+use jamesporal::core::{AbstractActivityHandler, AbstractWorkflowHandler};
+use jamesporal::inmemory_db::Db;
+use jamesporal::store::{SqliteStore, Store};
+use tokio::sync::Barrier;
+
+const RUNS: usize = 500;
+const POLLERS: usize = 16;
+
+struct StressActivity;
+
+#[async_trait::async_trait]
+impl AbstractActivityHandler for StressActivity {
+    async fn run(&self, _context: ActivityContext, input: String) -> Result<String, String> {
+        Ok(input)
+    }
+}
+
+struct StressWorkflow;
+
+#[async_trait::async_trait]
+impl AbstractWorkflowHandler for StressWorkflow {
+    async fn run(&self, _context: WorkflowContext, input: String) -> Result<String, String> {
+        Ok(input)
+    }
+}
+
+/// Enqueues `RUNS` activity runs, lets `POLLERS` tasks claim them concurrently and checks
+/// that every run was handed out exactly once. The pollers take turns using each of
+/// `stores`, which must all be backed by the same data.
+async fn assert_activities_claimed_once(stores: &[Arc<dyn Store>]) {
+    let store = &stores[0];
+    let name = ActivityName::from(&StressActivity);
+    let activity = match store.get_activity_by_name(&name).await {
+        Some(activity) => activity,
+        None => {
+            let activity = Activity {
+                id: ActivityId::new(),
+                name: name.clone(),
+            };
+            store.add_activity(activity.clone()).await;
+            activity
+        }
+    };
+
+    let mut enqueued = HashSet::new();
+    for _ in 0..RUNS {
+        let activity_run_id = ActivityRunId::new();
+        store
+            .add_activity_event(ActivityEvent {
+                activity_id: activity.id,
+                activity_run_id,
+                workflow_run_id: WorkflowRunId::new(),
+                event_type: ActivityEventType::Pending,
+                payload: "".to_string(),
+                created_at: Utc::now(),
+                attempt_number: 1,
+                sequence: 1,
+                options: Default::default(),
+                scheduled_for: None,
+            })
+            .await;
+        enqueued.insert(activity_run_id);
+    }
+
+    let barrier = Arc::new(Barrier::new(POLLERS));
+    let pollers = (0..POLLERS).map(|i| {
+        let store = stores[i % stores.len()].clone();
+        let name = name.clone();
+        let barrier = barrier.clone();
+        tokio::spawn(async move {
+            barrier.wait().await;
+            let mut claimed = vec![];
+            while let Some(pending) = store.claim_pending_activity(name.clone()).await {
+                claimed.push(pending.activity_run_id);
+            }
+            claimed
+        })
+    });
+
+    let mut claims: HashMap<ActivityRunId, usize> = HashMap::new();
+    for poller in futures::future::join_all(pollers).await {
+        for activity_run_id in poller.unwrap() {
+            *claims.entry(activity_run_id).or_default() += 1;
+        }
+    }
+
+    let double_claims: Vec<_> = claims.iter().filter(|(_, count)| **count > 1).collect();
+    assert!(double_claims.is_empty(), "claimed twice: {double_claims:?}");
+    for activity_run_id in &enqueued {
+        assert_eq!(claims.get(activity_run_id), Some(&1), "{activity_run_id}");
+    }
+}
+
+/// Same as `assert_activities_claimed_once`, for workflow runs.
+async fn assert_workflows_claimed_once(stores: &[Arc<dyn Store>]) {
+    let store = &stores[0];
+    let name = WorkflowName::from(&StressWorkflow);
+    let workflow = match store.get_workflow_by_name(&name).await {
+        Some(workflow) => workflow,
+        None => {
+            let workflow = Workflow {
+                id: WorkflowId::new(),
+                name: name.clone(),
+            };
+            store.add_workflow(workflow.clone()).await;
+            workflow
+        }
+    };
+
+    let mut enqueued = HashSet::new();
+    for _ in 0..RUNS {
+        let run_id = WorkflowRunId::new();
+        store
+            .add_workflow_event(WorkflowEvent {
+                workflow_id: workflow.id,
+                run_id,
+                event_type: WorkflowEventType::Pending,
+                payload: "".to_string(),
+                rerun_of: None,
+                rerun_checkpoint: None,
+                created_at: Utc::now(),
+                options: Default::default(),
+                fire_at: None,
+                child_run_id: None,
+                parent_run_id: None,
+            })
+            .await;
+        enqueued.insert(run_id);
+    }
+
+    let barrier = Arc::new(Barrier::new(POLLERS));
+    let pollers = (0..POLLERS).map(|i| {
+        let store = stores[i % stores.len()].clone();
+        let name = name.clone();
+        let barrier = barrier.clone();
+        tokio::spawn(async move {
+            barrier.wait().await;
+            let mut claimed = vec![];
+            while let Some(pending) = store.claim_pending_workflow(name.clone()).await {
+                claimed.push(pending.run_id);
+            }
+            claimed
+        })
+    });
+
+    let mut claims: HashMap<WorkflowRunId, usize> = HashMap::new();
+    for poller in futures::future::join_all(pollers).await {
+        for run_id in poller.unwrap() {
+            *claims.entry(run_id).or_default() += 1;
+        }
+    }
+
+    let double_claims: Vec<_> = claims.iter().filter(|(_, count)| **count > 1).collect();
+    assert!(double_claims.is_empty(), "claimed twice: {double_claims:?}");
+    for run_id in &enqueued {
+        assert_eq!(claims.get(run_id), Some(&1), "{run_id}");
+    }
+}
+
+/// Attempts waiting out a retry backoff are only claimed once due, in the order they
+/// become due, and don't hold up the attempts queued behind them.
+async fn assert_scheduled_attempts_wait(store: Arc<dyn Store>) {
+    let name = ActivityName::from(&StressActivity);
+    let activity = Activity {
+        id: ActivityId::new(),
+        name: name.clone(),
+    };
+    store.add_activity(activity.clone()).await;
+
+    let attempt = |delay_ms: Option<i64>| ActivityEvent {
+        activity_id: activity.id,
+        activity_run_id: ActivityRunId::new(),
+        workflow_run_id: WorkflowRunId::new(),
+        event_type: ActivityEventType::Pending,
+        payload: "".to_string(),
+        created_at: Utc::now(),
+        attempt_number: 2,
+        sequence: 1,
+        options: Default::default(),
+        scheduled_for: delay_ms.map(|ms| Utc::now() + TimeDelta::milliseconds(ms)),
+    };
+    let last = attempt(Some(600));
+    let second = attempt(Some(300));
+    let first = attempt(None);
+    for attempt in [&last, &second, &first] {
+        store.add_activity_event(attempt.clone()).await;
+    }
+
+    for expected in [&first, &second, &last] {
+        let claimed = loop {
+            if let Some(claimed) = store.claim_pending_activity(name.clone()).await {
+                break claimed;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        };
+        assert_eq!(claimed.activity_run_id, expected.activity_run_id);
+        if let Some(scheduled_for) = expected.scheduled_for {
+            assert!(Utc::now() >= scheduled_for);
+        }
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+async fn inmemory_db_claims_each_activity_once() {
+    assert_activities_claimed_once(&[Arc::new(Db::new())]).await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+async fn inmemory_db_claims_each_workflow_once() {
+    assert_workflows_claimed_once(&[Arc::new(Db::new())]).await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+async fn sqlite_store_claims_each_run_once() {
+    let stores: [Arc<dyn Store>; 1] = [Arc::new(
+        SqliteStore::open(common::temp_path("claims.sqlite")).unwrap(),
+    )];
+    assert_activities_claimed_once(&stores).await;
+    assert_workflows_claimed_once(&stores).await;
+}
+
+#[tokio::test]
+async fn scheduled_attempts_wait_until_due() {
+    assert_scheduled_attempts_wait(Arc::new(Db::new())).await;
+    let path = common::temp_path("scheduled.sqlite");
+    assert_scheduled_attempts_wait(Arc::new(SqliteStore::open(path).unwrap())).await;
+}
+
+/// Two stores on the same file stand in for two server processes.
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+async fn sqlite_stores_sharing_a_file_claim_each_run_once() {
+    let path = common::temp_path("shared-claims.sqlite");
+    let stores: [Arc<dyn Store>; 2] = [
+        Arc::new(SqliteStore::open(&path).unwrap()),
+        Arc::new(SqliteStore::open(&path).unwrap()),
+    ];
+    assert_activities_claimed_once(&stores).await;
+    assert_workflows_claimed_once(&stores).await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+async fn postgres_store_claims_each_run_once() {
+    let Some(store) = common::test_postgres().await else {
+        return;
+    };
+    let stores: [Arc<dyn Store>; 1] = [Arc::new(store)];
+    assert_activities_claimed_once(&stores).await;
+    assert_workflows_claimed_once(&stores).await;
+}
@@ -0,0 +1,246 @@
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::Utc;
+use jamesporal::core::activity::{
+    Activity, ActivityEvent, ActivityEventType, ActivityId, ActivityName, ActivityRunId,
+};
+use jamesporal::core::workflow::{
+    Workflow, WorkflowEvent, WorkflowEventType, WorkflowId, WorkflowName, WorkflowOptions,
+    WorkflowRunId,
+};
+use jamesporal::core::{
+    ActivityContext, ActivityHandle, ActivityHandler, ActivityOptions, WorkflowContext,
+    WorkflowHandler,
+};
+use jamesporal::inmemory_db::Db;
+use jamesporal::store::{SqliteStore, Store};
+use tokio::sync::Barrier;
+
+const RACERS: usize = 16;
+
+/// Sleeps for as long as its start-to-close timeout, so completing it races timing it out.
+struct Photofinish;
+
+#[async_trait::async_trait]
+impl ActivityHandler for Photofinish {
+    type Input = u64;
+    type Output = u64;
+    type Error = String;
+
+    async fn run(&self, _context: ActivityContext, millis: u64) -> Result<u64, String> {
+        tokio::time::sleep(Duration::from_millis(millis)).await;
+        Ok(millis)
+    }
+}
+
+struct PhotofinishWorkflow;
+
+#[async_trait::async_trait]
+impl WorkflowHandler for PhotofinishWorkflow {
+    type Input = (usize, u64);
+    type Output = ();
+    type Error = String;
+
+    async fn run(
+        &self,
+        mut context: WorkflowContext,
+        (runs, millis): (usize, u64),
+    ) -> Result<(), String> {
+        context.with_activity_options(ActivityOptions {
+            start_to_close_timeout: Some(Duration::from_millis(millis)),
+            ..Default::default()
+        });
+        let mut handles = vec![];
+        for _ in 0..runs {
+            handles.push(context.start_typed_activity(Photofinish, millis).await?);
+        }
+        ActivityHandle::join_all(&handles).await;
+        Ok(())
+    }
+}
+
+/// Records a started activity attempt and lets `RACERS` tasks try to end it at once, each
+/// with the event it saw last. Exactly one of them may win.
+async fn assert_one_activity_outcome(store: Arc<dyn Store>) {
+    let activity = Activity {
+        id: ActivityId::new(),
+        name: ActivityName::from(&Photofinish),
+    };
+    store.add_activity(activity.clone()).await;
+    let pending = ActivityEvent {
+        activity_id: activity.id,
+        activity_run_id: ActivityRunId::new(),
+        workflow_run_id: WorkflowRunId::new(),
+        event_type: ActivityEventType::Pending,
+        payload: "".to_string(),
+        created_at: Utc::now(),
+        attempt_number: 1,
+        sequence: 1,
+        options: Default::default(),
+        scheduled_for: None,
+    };
+    store.add_activity_event(pending.clone()).await;
+    store.claim_pending_activity(activity.name.clone()).await;
+    let started = store
+        .get_last_activity_run_event(pending.activity_run_id)
+        .await
+        .unwrap();
+    assert_eq!(started.event_type, ActivityEventType::Started);
+
+    let barrier = Arc::new(Barrier::new(RACERS));
+    let racers = (0..RACERS).map(|i| {
+        let store = store.clone();
+        let barrier = barrier.clone();
+        let started = started.clone();
+        tokio::spawn(async move {
+            let event_type = if i % 2 == 0 {
+                ActivityEventType::Succeeeded
+            } else {
+                ActivityEventType::TimedOut
+            };
+            let outcome = ActivityEvent {
+                event_type,
+                payload: i.to_string(),
+                created_at: Utc::now(),
+                ..started.clone()
+            };
+            barrier.wait().await;
+            store
+                .add_activity_events_if_last(&started, vec![outcome])
+                .await
+        })
+    });
+    let wins = futures::future::join_all(racers)
+        .await
+        .into_iter()
+        .filter(|won| *won.as_ref().unwrap())
+        .count();
+    assert_eq!(wins, 1);
+
+    let events = store.get_activity_run_events(pending.activity_run_id).await;
+    assert_eq!(events.len(), 3, "{events:?}");
+    assert!(events[2].event_type.is_finished());
+}
+
+/// Same as `assert_one_activity_outcome`, for a started workflow run.
+async fn assert_one_workflow_outcome(store: Arc<dyn Store>) {
+    let workflow = Workflow {
+        id: WorkflowId::new(),
+        name: WorkflowName::from(&PhotofinishWorkflow),
+    };
+    store.add_workflow(workflow.clone()).await;
+    let started = WorkflowEvent {
+        workflow_id: workflow.id,
+        run_id: WorkflowRunId::new(),
+        event_type: WorkflowEventType::Started,
+        payload: "".to_string(),
+        rerun_of: None,
+        rerun_checkpoint: None,
+        created_at: Utc::now(),
+        options: Default::default(),
+        fire_at: None,
+        child_run_id: None,
+        parent_run_id: None,
+    };
+    store.add_workflow_event(started.clone()).await;
+    let started = store
+        .get_last_workflow_run_event(started.run_id)
+        .await
+        .unwrap();
+
+    let barrier = Arc::new(Barrier::new(RACERS));
+    let racers = (0..RACERS).map(|i| {
+        let store = store.clone();
+        let barrier = barrier.clone();
+        let started = started.clone();
+        tokio::spawn(async move {
+            let event_type = if i % 2 == 0 {
+                WorkflowEventType::Succeeeded
+            } else {
+                WorkflowEventType::TimedOut
+            };
+            let outcome = WorkflowEvent {
+                event_type,
+                payload: i.to_string(),
+                created_at: Utc::now(),
+                ..started.clone()
+            };
+            barrier.wait().await;
+            store
+                .add_workflow_events_if_last(&started, vec![outcome])
+                .await
+        })
+    });
+    let wins = futures::future::join_all(racers)
+        .await
+        .into_iter()
+        .filter(|won| *won.as_ref().unwrap())
+        .count();
+    assert_eq!(wins, 1);
+    assert_eq!(store.get_workflow_run_events(started.run_id).await.len(), 2);
+}
+
+async fn assert_one_outcome(store: Arc<dyn Store>) {
+    for _ in 0..20 {
+        assert_one_activity_outcome(store.clone()).await;
+        assert_one_workflow_outcome(store.clone()).await;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+async fn inmemory_db_appends_one_outcome() {
+    assert_one_outcome(Arc::new(Db::new())).await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+async fn logged_db_appends_one_outcome() {
+    let dir = common::temp_path("conditional-append");
+    assert_one_outcome(Arc::new(Db::open(&dir).unwrap())).await;
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+async fn sqlite_store_appends_one_outcome() {
+    let path = common::temp_path("conditional-append.db");
+    assert_one_outcome(Arc::new(SqliteStore::open(&path).unwrap())).await;
+    let _ = std::fs::remove_file(path);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+async fn postgres_store_appends_one_outcome() {
+    let Some(store) = common::test_postgres().await else {
+        return;
+    };
+    assert_one_outcome(Arc::new(store)).await;
+}
+
+/// Activities that finish right at their start-to-close timeout are either completed by
+/// their worker or timed out by the server, never both.
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn completion_and_timeout_never_both_end_an_attempt() {
+    let db = Db::new();
+    let handlers = vec![
+        common::activity(Photofinish),
+        common::workflow(PhotofinishWorkflow),
+    ];
+    let client = common::start_worker(&common::start_server(db.clone()).await, handlers).await;
+
+    let (run_id, completion) = common::run_workflow(
+        &client,
+        &PhotofinishWorkflow,
+        (20, 100),
+        WorkflowOptions::default(),
+    )
+    .await;
+    assert_eq!(completion.status, WorkflowEventType::Succeeeded);
+
+    for events in common::activity_events(&db, run_id).await {
+        let event_types: Vec<_> = events.iter().map(|e| e.event_type.clone()).collect();
+        assert_eq!(event_types.len(), 3, "{event_types:?}");
+        assert_eq!(event_types[1], ActivityEventType::Started);
+        assert!(event_types[2].is_finished(), "{event_types:?}");
+    }
+}
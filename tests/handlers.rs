@@ -0,0 +1,149 @@
+mod common;
+
+use jamesporal::core::payload::from_payload;
+use jamesporal::core::workflow::{WorkflowEventType, WorkflowOptions};
+use jamesporal::core::{
+    AbstractActivityHandler, AbstractWorkflowHandler, ActivityContext, ActivityHandle,
+    ActivityHandler, ErrorKind, HandlerError, Worker, WorkflowContext, WorkflowHandler,
+};
+use jamesporal::inmemory_db::Db;
+use serde::{Deserialize, Serialize};
+
+/// Takes and returns plain text, which isn't JSON.
+struct Shout;
+
+#[async_trait::async_trait]
+impl AbstractActivityHandler for Shout {
+    async fn run(&self, _context: ActivityContext, input: String) -> Result<String, String> {
+        if input.is_empty() {
+            return Err("EmptyInput: nothing to shout".to_string());
+        }
+        Ok(format!("{}!", input.to_uppercase()))
+    }
+}
+
+struct Double;
+
+#[async_trait::async_trait]
+impl ActivityHandler for Double {
+    type Input = u64;
+    type Output = u64;
+    type Error = String;
+
+    async fn run(&self, _context: ActivityContext, input: u64) -> Result<u64, String> {
+        Ok(input * 2)
+    }
+}
+
+/// Shouts its input, then shouts nothing and doubles 21 side by side, all through the
+/// untyped entry points.
+struct Untyped;
+
+#[async_trait::async_trait]
+impl AbstractWorkflowHandler for Untyped {
+    async fn run(&self, mut context: WorkflowContext, input: String) -> Result<String, String> {
+        let shouted = context.execute_activity(Shout, input).await?;
+        let handles = vec![
+            context.start_activity(Shout, "".to_string()).await?,
+            context.start_activity(Double, "21".to_string()).await?,
+        ];
+        let results = ActivityHandle::join_all(&handles).await;
+        let results: Vec<String> = results
+            .into_iter()
+            .map(|result| result.unwrap_or_else(|e| e.to_string()))
+            .collect();
+        Ok(format!("{shouted} {results:?}"))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+enum DoublingError {
+    TooLarge { limit: u64 },
+}
+
+impl ErrorKind for DoublingError {
+    fn kind(&self) -> &str {
+        "TooLarge"
+    }
+}
+
+/// Doubles its input, unless it is over the limit.
+struct Doubling;
+
+#[async_trait::async_trait]
+impl WorkflowHandler for Doubling {
+    type Input = (u64, u64);
+    type Output = u64;
+    type Error = DoublingError;
+
+    async fn run(
+        &self,
+        mut context: WorkflowContext,
+        (input, limit): (u64, u64),
+    ) -> Result<u64, DoublingError> {
+        if input > limit {
+            return Err(DoublingError::TooLarge { limit });
+        }
+        Ok(context.execute_typed_activity(Double, input).await.unwrap())
+    }
+}
+
+#[tokio::test]
+async fn untyped_handlers_pass_strings_as_they_are() {
+    let url = common::start_server(Db::new()).await;
+    let handlers = vec![
+        common::activity(Shout),
+        common::activity(Double),
+        common::workflow(Untyped),
+    ];
+    let client = common::start_worker(&url, handlers).await;
+
+    let result = common::within_test_timeout(Worker::new(client).execute_workflow(
+        Untyped,
+        "hello".to_string(),
+        WorkflowOptions::default(),
+    ))
+    .await;
+    assert_eq!(
+        result.unwrap(),
+        r#"HELLO! ["EmptyInput: nothing to shout", "42"]"#
+    );
+}
+
+#[tokio::test]
+async fn typed_workflows_take_and_return_their_own_types() {
+    let url = common::start_server(Db::new()).await;
+    let handlers = vec![common::activity(Double), common::workflow(Doubling)];
+    let mut client = common::start_worker(&url, handlers).await;
+    let mut worker = Worker::new(client.clone());
+
+    let doubled = common::within_test_timeout(worker.execute_typed_workflow(
+        Doubling,
+        (21, 100),
+        WorkflowOptions::default(),
+    ))
+    .await;
+    assert_eq!(doubled.unwrap(), 42);
+
+    let too_large = common::within_test_timeout(worker.execute_typed_workflow(
+        Doubling,
+        (200, 100),
+        WorkflowOptions::default(),
+    ))
+    .await;
+    assert!(matches!(
+        too_large,
+        Err(HandlerError::Handler(DoublingError::TooLarge {
+            limit: 100
+        }))
+    ));
+
+    // The client only starts the run, with the input encoded the same way.
+    let run_id = client
+        .execute_typed_workflow(&Doubling, &(5, 100), WorkflowOptions::default())
+        .await
+        .unwrap();
+    let completion = common::wait_for_completion(&client, run_id).await;
+    assert_eq!(completion.status, WorkflowEventType::Succeeeded);
+    assert_eq!(from_payload::<u64>(&completion.result).unwrap(), 10);
+}
@@ -0,0 +1,163 @@
+mod common;
+
+use std::time::Duration;
+
+use chrono::Utc;
+use jamesporal::core::activity::{ActivityEventType, ActivityHeartbeat, ActivityRunId};
+use jamesporal::core::payload::from_payload;
+use jamesporal::core::workflow::{WorkflowEventType, WorkflowOptions};
+use jamesporal::core::{
+    ActivityContext, ActivityHandler, ActivityOptions, RetryOptions, WorkflowContext,
+    WorkflowHandler,
+};
+use jamesporal::inmemory_db::Db;
+use jamesporal::store::Store;
+
+/// Works through `steps` steps of 50ms, heartbeating its progress after each unless told
+/// to go quiet. Resumes from the progress of an earlier attempt, and goes quiet at step
+/// `stall_at` of the first attempt until the attempt is cancelled.
+struct Steps;
+
+#[async_trait::async_trait]
+impl ActivityHandler for Steps {
+    type Input = (u32, Option<u32>);
+    type Output = String;
+    type Error = String;
+
+    async fn run(
+        &self,
+        context: ActivityContext,
+        (steps, stall_at): (u32, Option<u32>),
+    ) -> Result<String, String> {
+        let resumed_from: u32 = context
+            .heartbeat_details
+            .as_deref()
+            .map_or(0, |details| details.parse().unwrap());
+        for step in resumed_from..steps {
+            if context.attempt_number == 1 && Some(step) == stall_at {
+                context.cancellation.cancelled().await;
+                return Err("Stalled: gave up once the attempt timed out".to_string());
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            context.heartbeat((step + 1).to_string()).await?;
+        }
+        Ok(format!(
+            "attempt {} resumed from {resumed_from}",
+            context.attempt_number
+        ))
+    }
+}
+
+struct StepsWorkflow;
+
+#[async_trait::async_trait]
+impl WorkflowHandler for StepsWorkflow {
+    type Input = (u32, Option<u32>);
+    type Output = String;
+    type Error = String;
+
+    async fn run(
+        &self,
+        mut context: WorkflowContext,
+        input: (u32, Option<u32>),
+    ) -> Result<String, String> {
+        context.with_activity_options(ActivityOptions {
+            retry_policy: RetryOptions {
+                max_attempts: 2,
+                initial_interval: Duration::from_millis(10),
+                ..Default::default()
+            },
+            heartbeat_timeout: Some(Duration::from_millis(300)),
+            ..Default::default()
+        });
+        Ok(context.execute_typed_activity(Steps, input).await?)
+    }
+}
+
+fn handlers() -> Vec<common::Handler> {
+    vec![common::activity(Steps), common::workflow(StepsWorkflow)]
+}
+
+#[tokio::test]
+async fn heartbeating_activities_may_outlive_their_heartbeat_timeout() {
+    let db = Db::new();
+    let client = common::start_worker(&common::start_server(db.clone()).await, handlers()).await;
+
+    let (run_id, completion) = common::run_workflow(
+        &client,
+        &StepsWorkflow,
+        (16, None::<u32>),
+        WorkflowOptions::default(),
+    )
+    .await;
+    assert_eq!(completion.status, WorkflowEventType::Succeeeded);
+    assert_eq!(
+        from_payload::<String>(&completion.result).unwrap(),
+        "attempt 1 resumed from 0"
+    );
+
+    let heartbeat = db
+        .get_last_activity_heartbeat(
+            common::activity_events(&db, run_id).await[0][0].activity_run_id,
+        )
+        .await
+        .unwrap();
+    assert_eq!(heartbeat.details, "16");
+}
+
+#[tokio::test]
+async fn missed_heartbeats_time_out_the_attempt_and_the_retry_resumes() {
+    let db = Db::new();
+    let client = common::start_worker(&common::start_server(db.clone()).await, handlers()).await;
+
+    let (run_id, completion) = common::run_workflow(
+        &client,
+        &StepsWorkflow,
+        (6, Some(3)),
+        WorkflowOptions::default(),
+    )
+    .await;
+    assert_eq!(completion.status, WorkflowEventType::Succeeeded);
+    assert_eq!(
+        from_payload::<String>(&completion.result).unwrap(),
+        "attempt 2 resumed from 3"
+    );
+
+    let events = &common::activity_events(&db, run_id).await[0];
+    let timed_out = events
+        .iter()
+        .find(|e| e.event_type == ActivityEventType::TimedOut)
+        .unwrap();
+    assert_eq!(timed_out.attempt_number, 1);
+    assert!(
+        timed_out.payload.contains("heartbeat"),
+        "{}",
+        timed_out.payload
+    );
+}
+
+#[tokio::test]
+async fn heartbeats_survive_a_restart() {
+    let dir = common::temp_path("heartbeats");
+    let activity_run_id = ActivityRunId::new();
+    let heartbeat = ActivityHeartbeat {
+        activity_run_id,
+        attempt_number: 2,
+        details: "halfway".to_string(),
+        created_at: Utc::now(),
+    };
+    {
+        let db = Db::open(&dir).unwrap();
+        db.record_activity_heartbeat(heartbeat.clone()).await;
+    }
+
+    let db = Db::open(&dir).unwrap();
+    let restored = db
+        .get_last_activity_heartbeat(activity_run_id)
+        .await
+        .unwrap();
+    assert_eq!(restored.details, heartbeat.details);
+    assert_eq!(restored.attempt_number, heartbeat.attempt_number);
+    assert_eq!(restored.created_at, heartbeat.created_at);
+    let _ = std::fs::remove_dir_all(dir);
+}
@@ -0,0 +1,175 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use jamesporal::core::payload::{from_payload, to_payload};
+use jamesporal::core::worker_events::{PollWorkflowResponse, ServerEvent, WorkerEvent};
+use jamesporal::core::workflow::{WorkflowName, WorkflowOptions, WorkflowRunId};
+use jamesporal::core::{Client, Worker, WorkflowContext, WorkflowHandler};
+use jamesporal::inmemory_db::Db;
+
+/// Polls that aren't woken up still notice new work once a second, so anything well
+/// below that means the poll was woken.
+const WOKEN_WITHIN: Duration = Duration::from_millis(500);
+
+/// Only ever claimed by the raw polls in these tests.
+struct Polled;
+
+#[async_trait::async_trait]
+impl WorkflowHandler for Polled {
+    type Input = ();
+    type Output = ();
+    type Error = String;
+
+    async fn run(&self, _context: WorkflowContext, _input: ()) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Run by a worker, unlike `Polled`.
+struct Greet;
+
+#[async_trait::async_trait]
+impl WorkflowHandler for Greet {
+    type Input = String;
+    type Output = String;
+    type Error = String;
+
+    async fn run(&self, _context: WorkflowContext, name: String) -> Result<String, String> {
+        if name.is_empty() {
+            return Err("NoName: nobody to greet".to_string());
+        }
+        Ok(format!("hello {name}"))
+    }
+}
+
+async fn register(url: &str) -> Client {
+    let client = Client::new(url);
+    client
+        .register_workflow(WorkflowName::from(&Polled))
+        .await
+        .unwrap();
+    client
+}
+
+/// Starts polling for `Polled` in the background and gives the poll time to start
+/// waiting.
+async fn start_poll(url: &str) -> tokio::task::JoinHandle<PollWorkflowResponse> {
+    let url = url.to_string();
+    let poll = tokio::spawn(async move {
+        let event = WorkerEvent::PollWorkflow {
+            name: WorkflowName::from(&Polled),
+        };
+        match common::post_worker_event(&url, &event).await {
+            ServerEvent::PollWorkflowResponse(response) => response,
+            _ => panic!("expected a workflow task"),
+        }
+    });
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    poll
+}
+
+async fn enqueue(client: &Client) -> (WorkflowRunId, Instant) {
+    let run_id = common::start_workflow(client, &Polled, (), WorkflowOptions::default()).await;
+    (run_id, Instant::now())
+}
+
+#[tokio::test]
+async fn polls_wake_up_when_work_is_enqueued() {
+    let url = common::start_server(Db::new()).await;
+    let client = register(&url).await;
+
+    let poll = start_poll(&url).await;
+    let (run_id, enqueued_at) = enqueue(&client).await;
+    let task = common::within_test_timeout(poll).await.unwrap();
+
+    assert_eq!(task.workflow_run_id, run_id);
+    assert!(
+        enqueued_at.elapsed() < WOKEN_WITHIN,
+        "{:?}",
+        enqueued_at.elapsed()
+    );
+}
+
+#[tokio::test]
+async fn polls_see_work_enqueued_through_another_server() {
+    let db = Db::new();
+    let polled_url = common::start_server(db.clone()).await;
+    let other = register(&common::start_server(db).await).await;
+
+    let poll = start_poll(&polled_url).await;
+    let (run_id, enqueued_at) = enqueue(&other).await;
+    let task = common::within_test_timeout(poll).await.unwrap();
+
+    // Nothing wakes the poll, so it takes until the next recheck.
+    assert_eq!(task.workflow_run_id, run_id);
+    assert!(
+        enqueued_at.elapsed() < Duration::from_secs(3),
+        "{:?}",
+        enqueued_at.elapsed()
+    );
+}
+
+#[tokio::test]
+async fn completion_polls_wake_up_when_the_run_finishes() {
+    let url = common::start_server(Db::new()).await;
+    let client = register(&url).await;
+
+    let poll = start_poll(&url).await;
+    let (run_id, _) = enqueue(&client).await;
+    let task = common::within_test_timeout(poll).await.unwrap();
+
+    let completion = tokio::spawn({
+        let client = client.clone();
+        async move { common::wait_for_completion(&client, run_id).await }
+    });
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let completed_at = Instant::now();
+    let event = WorkerEvent::CompleteWorkflow {
+        result: to_payload(&()).unwrap(),
+        error: "".to_string(),
+        workflow_id: task.workflow_id,
+        workflow_run_id: run_id,
+        rerun_of_workflow_run_id: None,
+        started_at: task.started_at,
+    };
+    assert!(matches!(
+        common::post_worker_event(&url, &event).await,
+        ServerEvent::GeneralSuccess { success: true }
+    ));
+
+    let completion = completion.await.unwrap();
+    assert_eq!(completion.workflow_run_id, run_id);
+    assert!(
+        completed_at.elapsed() < WOKEN_WITHIN,
+        "{:?}",
+        completed_at.elapsed()
+    );
+}
+
+#[tokio::test]
+async fn executing_a_workflow_waits_for_a_worker_behind_another_server() {
+    let db = Db::new();
+    let url = common::start_server(db.clone()).await;
+    common::start_worker(&url, vec![common::workflow(Greet)]).await;
+    let mut other = Worker::new(Client::new(common::start_server(db).await));
+
+    let greeting = common::within_test_timeout(other.execute_workflow(
+        Greet,
+        to_payload(&"world").unwrap(),
+        WorkflowOptions::default(),
+    ))
+    .await
+    .unwrap();
+    assert_eq!(from_payload::<String>(&greeting).unwrap(), "hello world");
+
+    let error = common::within_test_timeout(other.execute_workflow(
+        Greet,
+        to_payload(&"").unwrap(),
+        WorkflowOptions::default(),
+    ))
+    .await
+    .unwrap_err();
+    assert!(error.contains("NoName"), "{error}");
+}
@@ -0,0 +1,107 @@
+use jamesporal::core::activity::error_kind;
+use jamesporal::core::payload::{
+    from_error_payload, from_payload, to_error_payload, to_payload, SERIALIZATION_ERROR,
+};
+use jamesporal::core::{ErrorKind, HandlerError};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+enum TransferError {
+    InsufficientFunds { missing: u64 },
+    AccountClosed,
+}
+
+impl ErrorKind for TransferError {
+    fn kind(&self) -> &str {
+        match self {
+            TransferError::InsufficientFunds { .. } => "InsufficientFunds",
+            TransferError::AccountClosed => "AccountClosed",
+        }
+    }
+}
+
+#[test]
+fn payloads_are_always_json() {
+    assert_eq!(to_payload(&"hello").unwrap(), r#""hello""#);
+    assert_eq!(to_payload(&3).unwrap(), "3");
+    assert_eq!(to_payload(&()).unwrap(), "null");
+    assert_eq!(to_payload(&(1, "two".to_string())).unwrap(), r#"[1,"two"]"#);
+
+    assert_eq!(from_payload::<String>(r#""hello""#).unwrap(), "hello");
+    assert_eq!(from_payload::<i32>("3").unwrap(), 3);
+    assert!(from_payload::<String>("hello").is_err());
+}
+
+#[test]
+fn string_errors_travel_unchanged() {
+    let error = "ValidationError: not a number".to_string();
+    assert_eq!(error.kind(), "ValidationError");
+
+    let payload = to_error_payload(&error).unwrap();
+    assert_eq!(payload, error);
+    assert_eq!(error_kind(&payload), "ValidationError");
+    assert_eq!(from_error_payload::<String>(&payload).unwrap(), error);
+}
+
+#[test]
+fn typed_errors_travel_with_their_kind() {
+    let error = TransferError::InsufficientFunds { missing: 5 };
+    let payload = to_error_payload(&error).unwrap();
+    assert_eq!(
+        payload,
+        r#"InsufficientFunds: {"InsufficientFunds":{"missing":5}}"#
+    );
+    assert_eq!(error_kind(&payload), "InsufficientFunds");
+    assert_eq!(
+        from_error_payload::<TransferError>(&payload).unwrap(),
+        error
+    );
+
+    // Unit variants serialize to their name, which is also their kind.
+    let payload = to_error_payload(&TransferError::AccountClosed).unwrap();
+    assert_eq!(payload, "AccountClosed");
+    assert_eq!(
+        from_error_payload::<TransferError>(&payload).unwrap(),
+        TransferError::AccountClosed
+    );
+}
+
+#[test]
+fn reported_errors_are_sorted_by_origin() {
+    let payload = to_error_payload(&TransferError::AccountClosed).unwrap();
+    assert!(matches!(
+        HandlerError::<TransferError>::from_payload(payload),
+        HandlerError::Handler(TransferError::AccountClosed)
+    ));
+
+    let serialization = format!("{SERIALIZATION_ERROR}: invalid activity output: oops");
+    assert!(matches!(
+        HandlerError::<TransferError>::from_payload(serialization),
+        HandlerError::Serialization(_)
+    ));
+
+    // Errors the server or the context report aren't JSON after their kind.
+    let timed_out = "TimedOut: activity attempt 1 timed out".to_string();
+    match HandlerError::<TransferError>::from_payload(timed_out.clone()) {
+        HandlerError::Other(error) => assert_eq!(error, timed_out),
+        other => panic!("Unexpected: {other:?}"),
+    }
+}
+
+#[test]
+fn handler_errors_display_as_strings_or_as_they_travel() {
+    let error = "Flaky: attempt 2".to_string();
+    let payload = to_error_payload(&error).unwrap();
+    let reported = HandlerError::<String>::from_payload(payload);
+    assert_eq!(String::from(reported), error);
+
+    // Even when what follows their kind happens to be JSON.
+    let error = r#"Flaky: "quoted""#.to_string();
+    let payload = to_error_payload(&error).unwrap();
+    assert_eq!(from_error_payload::<String>(&payload).unwrap(), error);
+
+    let error = TransferError::InsufficientFunds { missing: 5 };
+    let payload = to_error_payload(&error).unwrap();
+    let reported = HandlerError::<TransferError>::from_payload(payload.clone());
+    assert_eq!(reported.to_string(), payload);
+}
@@ -0,0 +1,302 @@
+//! Runs against the database in `JAMESPORAL_TEST_POSTGRES`; see `common::test_postgres`.
+
+mod common;
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use chrono::{TimeDelta, Utc};
+use jamesporal::core::activity::{
+    Activity, ActivityEvent, ActivityEventType, ActivityHeartbeat, ActivityId, ActivityName,
+    ActivityRunId,
+};
+use jamesporal::core::workflow::{
+    WorkflowCommand, WorkflowCommandType, WorkflowEventType, WorkflowOptions, WorkflowRunId,
+    WorkflowSignal,
+};
+use jamesporal::core::{
+    ActivityContext, ActivityHandler, ActivityOptions, RetryOptions, WorkflowContext,
+    WorkflowHandler,
+};
+use jamesporal::store::Store;
+
+/// How many times `Counted` ran for each input.
+static EXECUTIONS: Mutex<Option<HashMap<String, usize>>> = Mutex::new(None);
+
+fn executions(input: &str) -> usize {
+    EXECUTIONS
+        .lock()
+        .unwrap()
+        .get_or_insert_default()
+        .get(input)
+        .copied()
+        .unwrap_or_default()
+}
+
+/// Fails the first attempt for every input, so each run goes through a retry.
+struct Counted;
+
+#[async_trait::async_trait]
+impl ActivityHandler for Counted {
+    type Input = String;
+    type Output = String;
+    type Error = String;
+
+    async fn run(&self, _context: ActivityContext, input: String) -> Result<String, String> {
+        let attempt = {
+            let mut executions = EXECUTIONS.lock().unwrap();
+            let count = executions
+                .get_or_insert_default()
+                .entry(input.clone())
+                .or_default();
+            *count += 1;
+            *count
+        };
+        if attempt == 1 {
+            return Err("FirstTryError: not yet".to_string());
+        }
+        Ok(input.to_uppercase())
+    }
+}
+
+/// Runs `Counted` with retries, then sleeps on a durable timer.
+struct PostgresWorkflow;
+
+#[async_trait::async_trait]
+impl WorkflowHandler for PostgresWorkflow {
+    type Input = String;
+    type Output = String;
+    type Error = String;
+
+    async fn run(&self, mut context: WorkflowContext, input: String) -> Result<String, String> {
+        context.with_activity_options(ActivityOptions {
+            retry_policy: RetryOptions {
+                max_attempts: 3,
+                initial_interval: Duration::from_millis(10),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        let output = context.execute_typed_activity(Counted, input).await?;
+        context.sleep(Duration::from_millis(100)).await?;
+        Ok(output)
+    }
+}
+
+fn handlers() -> Vec<common::Handler> {
+    vec![
+        common::activity(Counted),
+        common::workflow(PostgresWorkflow),
+    ]
+}
+
+#[tokio::test]
+async fn workflows_run_to_completion_on_postgres() {
+    let Some(store) = common::test_postgres().await else {
+        return;
+    };
+    let client = common::start_worker(&common::start_server(store.clone()).await, handlers()).await;
+
+    let input = uuid::Uuid::new_v4().to_string();
+    let (run_id, completion) = common::run_workflow(
+        &client,
+        &PostgresWorkflow,
+        &input,
+        WorkflowOptions::default(),
+    )
+    .await;
+    assert_eq!(
+        completion.status,
+        WorkflowEventType::Succeeeded,
+        "{}",
+        completion.error
+    );
+    assert_eq!(
+        jamesporal::core::payload::from_payload::<String>(&completion.result).unwrap(),
+        input.to_uppercase()
+    );
+
+    let events = &common::activity_events(&store, run_id).await[0];
+    let event_types: Vec<_> = events.iter().map(|e| e.event_type.clone()).collect();
+    assert_eq!(
+        event_types,
+        vec![
+            ActivityEventType::Pending,
+            ActivityEventType::Started,
+            ActivityEventType::Failed,
+            ActivityEventType::Pending,
+            ActivityEventType::Started,
+            ActivityEventType::Succeeeded,
+        ]
+    );
+    assert!(!store.get_unfinished_workflow_runs().await.contains(&run_id));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn servers_sharing_postgres_execute_each_attempt_once() {
+    let Some(store) = common::test_postgres().await else {
+        return;
+    };
+    let clients = [
+        common::start_worker(&common::start_server(store.clone()).await, handlers()).await,
+        common::start_worker(&common::start_server(store).await, handlers()).await,
+    ];
+
+    let inputs: Vec<_> = (0..10).map(|_| uuid::Uuid::new_v4().to_string()).collect();
+    let mut run_ids = vec![];
+    for (i, input) in inputs.iter().enumerate() {
+        run_ids.push(
+            common::start_workflow(
+                &clients[i % 2],
+                &PostgresWorkflow,
+                input,
+                WorkflowOptions::default(),
+            )
+            .await,
+        );
+    }
+
+    for (i, run_id) in run_ids.into_iter().enumerate() {
+        let completion = common::wait_for_completion(&clients[(i + 1) % 2], run_id).await;
+        assert_eq!(
+            completion.status,
+            WorkflowEventType::Succeeeded,
+            "{}",
+            completion.error
+        );
+    }
+    for input in &inputs {
+        assert_eq!(executions(input), 2, "{input}");
+    }
+}
+
+#[tokio::test]
+async fn postgres_store_orders_commands_signals_and_heartbeats() {
+    let Some(store) = common::test_postgres().await else {
+        return;
+    };
+    let run_id = WorkflowRunId::new();
+
+    for sequence in [3, 1, 2] {
+        store
+            .add_workflow_command(WorkflowCommand {
+                workflow_run_id: run_id,
+                sequence,
+                command_type: WorkflowCommandType::StartTimer {
+                    fire_at: Utc::now(),
+                },
+                created_at: Utc::now(),
+            })
+            .await;
+    }
+    let sequences: Vec<_> = store
+        .get_workflow_commands(run_id)
+        .await
+        .iter()
+        .map(|c| c.sequence)
+        .collect();
+    assert_eq!(sequences, [1, 2, 3]);
+
+    for payload in ["b", "a", "c"] {
+        store
+            .add_workflow_signal(WorkflowSignal {
+                workflow_run_id: run_id,
+                signal_name: "letters".to_string(),
+                payload: payload.to_string(),
+                created_at: Utc::now(),
+            })
+            .await;
+    }
+    let payloads: Vec<_> = store
+        .get_workflow_signals(run_id)
+        .await
+        .into_iter()
+        .map(|s| s.payload)
+        .collect();
+    assert_eq!(payloads, ["b", "a", "c"]);
+
+    assert!(store.get_last_workflow_heartbeat(run_id).await.is_none());
+    store.record_workflow_heartbeat(run_id).await;
+    assert!(store.get_last_workflow_heartbeat(run_id).await.is_some());
+
+    let activity_run_id = ActivityRunId::new();
+    for details in ["first", "second"] {
+        store
+            .record_activity_heartbeat(ActivityHeartbeat {
+                activity_run_id,
+                attempt_number: 1,
+                details: details.to_string(),
+                created_at: Utc::now(),
+            })
+            .await;
+    }
+    assert_eq!(
+        store
+            .get_last_activity_heartbeat(activity_run_id)
+            .await
+            .unwrap()
+            .details,
+        "second"
+    );
+}
+
+/// Never registered with a worker, so only this test claims its runs.
+struct Unpolled;
+
+#[async_trait::async_trait]
+impl ActivityHandler for Unpolled {
+    type Input = ();
+    type Output = ();
+    type Error = String;
+
+    async fn run(&self, _context: ActivityContext, _input: ()) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn postgres_store_skips_attempts_that_are_not_due() {
+    let Some(store) = common::test_postgres().await else {
+        return;
+    };
+    let name = ActivityName::from(&Unpolled);
+    let activity = match store.get_activity_by_name(&name).await {
+        Some(activity) => activity,
+        None => {
+            let activity = Activity {
+                id: ActivityId::new(),
+                name: name.clone(),
+            };
+            store.add_activity(activity.clone()).await;
+            activity
+        }
+    };
+    // Leftovers from earlier runs of this test would be claimed first.
+    while store.claim_pending_activity(name.clone()).await.is_some() {}
+
+    let pending = |scheduled_for| ActivityEvent {
+        activity_id: activity.id,
+        activity_run_id: ActivityRunId::new(),
+        workflow_run_id: WorkflowRunId::new(),
+        event_type: ActivityEventType::Pending,
+        payload: "".to_string(),
+        created_at: Utc::now(),
+        attempt_number: 1,
+        sequence: 1,
+        options: Default::default(),
+        scheduled_for,
+    };
+    let later = pending(Some(Utc::now() + TimeDelta::milliseconds(500)));
+    let now = pending(None);
+    store.add_activity_event(later.clone()).await;
+    store.add_activity_event(now.clone()).await;
+
+    let claimed = store.claim_pending_activity(name.clone()).await.unwrap();
+    assert_eq!(claimed.activity_run_id, now.activity_run_id);
+    assert!(store.claim_pending_activity(name.clone()).await.is_none());
+
+    tokio::time::sleep(Duration::from_millis(600)).await;
+    let claimed = store.claim_pending_activity(name).await.unwrap();
+    assert_eq!(claimed.activity_run_id, later.activity_run_id);
+}
@@ -0,0 +1,92 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use jamesporal::core::workflow::{WorkflowEventType, WorkflowName, WorkflowOptions, WorkflowRunId};
+use jamesporal::core::{Client, WorkflowContext, WorkflowHandler};
+use jamesporal::inmemory_db::Db;
+
+/// Answers `status` queries until it gets a `done` signal.
+struct Answering;
+
+#[async_trait::async_trait]
+impl WorkflowHandler for Answering {
+    type Input = ();
+    type Output = ();
+    type Error = String;
+
+    async fn run(&self, mut context: WorkflowContext, _input: ()) -> Result<(), String> {
+        context.register_query("status", |args| Ok(format!("waiting for {args}")));
+        context.wait_for_signal("done").await?;
+        Ok(())
+    }
+}
+
+/// Never executed by a worker in these tests.
+struct Unserved;
+
+#[async_trait::async_trait]
+impl WorkflowHandler for Unserved {
+    type Input = ();
+    type Output = ();
+    type Error = String;
+
+    async fn run(&self, _context: WorkflowContext, _input: ()) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Well below the server's query timeout, so a query that was queued anyway fails the
+/// test.
+const ANSWERED_WITHIN: Duration = Duration::from_secs(2);
+
+/// Starts a worker for `Answering`, and registers `Unserved` without one.
+async fn start_worker() -> Client {
+    let url = common::start_server(Db::new()).await;
+    let client = common::start_worker(&url, vec![common::workflow(Answering)]).await;
+    client
+        .register_workflow(WorkflowName::from(&Unserved))
+        .await
+        .unwrap();
+    client
+}
+
+async fn query(client: &Client, run_id: WorkflowRunId) -> String {
+    let asked_at = Instant::now();
+    let answer = client
+        .query_workflow(run_id, "status".to_string(), "approval".to_string())
+        .await;
+    assert!(
+        asked_at.elapsed() < ANSWERED_WITHIN,
+        "{:?}",
+        asked_at.elapsed()
+    );
+    answer.unwrap_or_else(|error| error)
+}
+
+#[tokio::test]
+async fn running_workflows_answer_queries() {
+    let client = start_worker().await;
+    let run_id = common::start_workflow(&client, &Answering, (), WorkflowOptions::default()).await;
+
+    common::wait_until(|| async { query(&client, run_id).await == "waiting for approval" }).await;
+}
+
+#[tokio::test]
+async fn queries_about_runs_no_worker_is_executing_fail_immediately() {
+    let client = start_worker().await;
+
+    let queued = common::start_workflow(&client, &Unserved, (), WorkflowOptions::default()).await;
+    let error = query(&client, queued).await;
+    assert!(error.starts_with("NotRunning"), "{error}");
+
+    let run_id = common::start_workflow(&client, &Answering, (), WorkflowOptions::default()).await;
+    client
+        .signal_workflow(run_id, "done".to_string(), "".to_string())
+        .await
+        .unwrap();
+    let completion = common::wait_for_completion(&client, run_id).await;
+    assert_eq!(completion.status, WorkflowEventType::Succeeeded);
+    let error = query(&client, run_id).await;
+    assert!(error.starts_with("NotRunning"), "{error}");
+}
@@ -0,0 +1,226 @@
+mod common;
+
+use std::time::Duration;
+
+use chrono::{TimeDelta, Utc};
+use jamesporal::core::worker_events::{PollWorkflowResponse, ServerEvent, WorkerEvent};
+use jamesporal::core::workflow::{
+    WorkflowCommand, WorkflowCommandType, WorkflowEvent, WorkflowEventType, WorkflowName,
+    WorkflowOptions, WorkflowRunId,
+};
+use jamesporal::core::{Client, WorkflowContext, WorkflowHandler};
+use jamesporal::inmemory_db::Db;
+use jamesporal::store::{SqliteStore, Store};
+
+/// Never finishes if asked to hang, so only the server can take the run away from its
+/// worker.
+struct MaybeHang;
+
+#[async_trait::async_trait]
+impl WorkflowHandler for MaybeHang {
+    type Input = bool;
+    type Output = ();
+    type Error = String;
+
+    async fn run(&self, _context: WorkflowContext, hang: bool) -> Result<(), String> {
+        if hang {
+            std::future::pending::<()>().await;
+        }
+        Ok(())
+    }
+}
+
+async fn claim(client: &Client) -> PollWorkflowResponse {
+    common::within_test_timeout(async {
+        loop {
+            if let Some(delivery) = client
+                .poll_workflow(WorkflowName::from(&MaybeHang))
+                .await
+                .unwrap()
+            {
+                return delivery;
+            }
+        }
+    })
+    .await
+}
+
+async fn complete(url: &str, delivery: &PollWorkflowResponse, result: &str) -> bool {
+    let response = common::post_worker_event(
+        url,
+        &WorkerEvent::CompleteWorkflow {
+            result: result.to_string(),
+            error: "".to_string(),
+            workflow_id: delivery.workflow_id,
+            workflow_run_id: delivery.workflow_run_id,
+            rerun_of_workflow_run_id: delivery.rerun_of_workflow_run_id,
+            started_at: delivery.started_at,
+        },
+    )
+    .await;
+    !matches!(response, ServerEvent::GeneralSuccess { success: false })
+}
+
+/// Hands the run to another worker, as the server does once its worker goes quiet.
+async fn redeliver(db: &Db, run_id: WorkflowRunId) {
+    let enqueued = db.get_first_workflow_run_event(run_id).await.unwrap();
+    let started = db.get_last_workflow_run_event(run_id).await.unwrap();
+    assert_eq!(started.event_type, WorkflowEventType::Started);
+    db.add_workflow_event(WorkflowEvent {
+        event_type: WorkflowEventType::Pending,
+        payload: enqueued.payload,
+        created_at: Utc::now(),
+        ..started
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn only_the_latest_delivery_can_complete_a_run() {
+    let db = Db::new();
+    let url = common::start_server(db.clone()).await;
+    let client = Client::new(&url);
+    client
+        .register_workflow(WorkflowName::from(&MaybeHang))
+        .await
+        .unwrap();
+    let run_id =
+        common::start_workflow(&client, &MaybeHang, false, WorkflowOptions::default()).await;
+
+    // The first worker never heartbeats, so the server redelivers the run.
+    let stale = claim(&client).await;
+    let current = claim(&client).await;
+    assert_eq!(current.workflow_run_id, run_id);
+    assert_ne!(current.started_at, stale.started_at);
+
+    assert!(!complete(&url, &stale, "stale").await);
+    assert!(db.get_completed_workflow(run_id).await.is_none());
+    assert!(client
+        .record_workflow_heartbeat(run_id, stale.started_at)
+        .await
+        .is_err_and(|e| e.starts_with("NotRunning")));
+
+    assert!(complete(&url, &current, "current").await);
+    let completion = common::wait_for_completion(&client, run_id).await;
+    assert_eq!(completion.status, WorkflowEventType::Succeeeded);
+    assert_eq!(completion.result, "current");
+}
+
+#[tokio::test]
+async fn workers_drop_runs_that_were_handed_to_another_worker() {
+    let db = Db::new();
+    let url = common::start_server(db.clone()).await;
+    let client = common::start_worker(&url, vec![common::workflow(MaybeHang)]).await;
+
+    let hanging =
+        common::start_workflow(&client, &MaybeHang, true, WorkflowOptions::default()).await;
+    common::wait_until(|| async {
+        db.get_last_workflow_run_event(hanging)
+            .await
+            .is_some_and(|e| e.event_type == WorkflowEventType::Started)
+    })
+    .await;
+
+    // Another worker takes over the run; the first one learns from its next heartbeat.
+    redeliver(&db, hanging).await;
+    let takeover = claim(&client).await;
+    assert_eq!(takeover.workflow_run_id, hanging);
+
+    // The worker only polls for another run of the workflow once it dropped this one.
+    let next = common::start_workflow(&client, &MaybeHang, false, WorkflowOptions::default()).await;
+    let completion = tokio::time::timeout(
+        Duration::from_secs(10),
+        common::wait_for_completion(&client, next),
+    )
+    .await
+    .expect("the worker kept executing the run it lost");
+    assert_eq!(completion.status, WorkflowEventType::Succeeeded);
+    assert!(db.get_completed_workflow(hanging).await.is_none());
+}
+
+/// Starts a timer that is already due, so the run doesn't go to sleep.
+fn due_timer(delivery: &PollWorkflowResponse) -> WorkerEvent {
+    WorkerEvent::StartTimer {
+        workflow_run_id: delivery.workflow_run_id,
+        started_at: delivery.started_at,
+        sequence: 1,
+        fire_at: Utc::now() - TimeDelta::seconds(1),
+    }
+}
+
+#[tokio::test]
+async fn replaced_deliveries_cannot_issue_commands() {
+    let db = Db::new();
+    let url = common::start_server(db.clone()).await;
+    let client = Client::new(&url);
+    client
+        .register_workflow(WorkflowName::from(&MaybeHang))
+        .await
+        .unwrap();
+    let run_id =
+        common::start_workflow(&client, &MaybeHang, false, WorkflowOptions::default()).await;
+    let stale = claim(&client).await;
+    let current = claim(&client).await;
+
+    let receive = WorkerEvent::ReceiveSignals {
+        workflow_run_id: run_id,
+        started_at: stale.started_at,
+        sequence: 1,
+        signal_name: "approval".to_string(),
+        wait: false,
+    };
+    for event in [due_timer(&stale), receive] {
+        assert!(matches!(
+            common::post_worker_event(&url, &event).await,
+            ServerEvent::GeneralSuccess { success: false }
+        ));
+    }
+    assert!(db.get_workflow_commands(run_id).await.is_empty());
+
+    assert!(matches!(
+        common::post_worker_event(&url, &due_timer(&current)).await,
+        ServerEvent::TimerStarted { sleeping: false }
+    ));
+    assert_eq!(db.get_workflow_commands(run_id).await.len(), 1);
+}
+
+/// Records commands for the same sequence of a run at once, and checks only one sticks.
+async fn assert_one_command_per_sequence(store: impl Store + Clone + 'static) {
+    let workflow_run_id = WorkflowRunId::new();
+    let attempts = (0..16).map(|i| {
+        let store = store.clone();
+        tokio::spawn(async move {
+            store
+                .add_workflow_command(WorkflowCommand {
+                    workflow_run_id,
+                    sequence: 1,
+                    command_type: WorkflowCommandType::ReceiveSignals {
+                        signal_name: i.to_string(),
+                        payloads: vec![],
+                        memoized: false,
+                    },
+                    created_at: Utc::now(),
+                })
+                .await
+        })
+    });
+    let recorded = futures::future::join_all(attempts)
+        .await
+        .into_iter()
+        .filter(|recorded| *recorded.as_ref().unwrap())
+        .count();
+    assert_eq!(recorded, 1);
+    assert_eq!(store.get_workflow_commands(workflow_run_id).await.len(), 1);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn stores_keep_one_command_per_sequence() {
+    assert_one_command_per_sequence(Db::new()).await;
+    let dir = common::temp_path("commands");
+    assert_one_command_per_sequence(Db::open(&dir).unwrap()).await;
+    let path = common::temp_path("commands.sqlite");
+    assert_one_command_per_sequence(SqliteStore::open(&path).unwrap()).await;
+    if let Some(store) = common::test_postgres().await {
+        assert_one_command_per_sequence(store).await;
+    }
+}
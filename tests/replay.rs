@@ -0,0 +1,131 @@
+mod common;
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use jamesporal::core::workflow::{WorkflowEventType, WorkflowOptions};
+use jamesporal::core::{ActivityContext, ActivityHandler, WorkflowContext, WorkflowHandler};
+use jamesporal::inmemory_db::Db;
+
+static EXECUTED_ACTIVITIES: AtomicUsize = AtomicUsize::new(0);
+static WORKFLOW_DELIVERIES: AtomicUsize = AtomicUsize::new(0);
+static REPLAYING: AtomicBool = AtomicBool::new(false);
+
+struct Counted;
+
+#[async_trait::async_trait]
+impl ActivityHandler for Counted {
+    type Input = String;
+    type Output = String;
+    type Error = String;
+
+    async fn run(&self, _context: ActivityContext, input: String) -> Result<String, String> {
+        EXECUTED_ACTIVITIES.fetch_add(1, Ordering::SeqCst);
+        Ok(input.to_uppercase())
+    }
+}
+
+/// Runs an activity before and after a sleep, which hands the run back to the server, so
+/// the second delivery replays the first activity from history.
+struct SleepyWorkflow;
+
+#[async_trait::async_trait]
+impl WorkflowHandler for SleepyWorkflow {
+    type Input = ();
+    type Output = (String, String);
+    type Error = String;
+
+    async fn run(
+        &self,
+        mut context: WorkflowContext,
+        _input: (),
+    ) -> Result<(String, String), String> {
+        WORKFLOW_DELIVERIES.fetch_add(1, Ordering::SeqCst);
+        let before = context
+            .execute_typed_activity(Counted, "before".to_string())
+            .await?;
+        context.sleep(Duration::from_millis(100)).await?;
+        let after = context
+            .execute_typed_activity(Counted, "after".to_string())
+            .await?;
+        Ok((before, after))
+    }
+}
+
+struct Echo;
+
+#[async_trait::async_trait]
+impl ActivityHandler for Echo {
+    type Input = String;
+    type Output = String;
+    type Error = String;
+
+    async fn run(&self, _context: ActivityContext, input: String) -> Result<String, String> {
+        Ok(input)
+    }
+}
+
+/// Schedules a different activity input once it is replayed.
+struct FickleWorkflow;
+
+#[async_trait::async_trait]
+impl WorkflowHandler for FickleWorkflow {
+    type Input = ();
+    type Output = String;
+    type Error = String;
+
+    async fn run(&self, mut context: WorkflowContext, _input: ()) -> Result<String, String> {
+        let input = if REPLAYING.swap(true, Ordering::SeqCst) {
+            "second thoughts"
+        } else {
+            "first idea"
+        };
+        let echoed = context
+            .execute_typed_activity(Echo, input.to_string())
+            .await?;
+        context.sleep(Duration::from_millis(100)).await?;
+        Ok(echoed)
+    }
+}
+
+fn handlers() -> Vec<common::Handler> {
+    vec![
+        common::activity(Counted),
+        common::activity(Echo),
+        common::workflow(SleepyWorkflow),
+        common::workflow(FickleWorkflow),
+    ]
+}
+
+#[tokio::test]
+async fn replays_reuse_recorded_activity_results() {
+    let db = Db::new();
+    let client = common::start_worker(&common::start_server(db.clone()).await, handlers()).await;
+
+    let (run_id, completion) =
+        common::run_workflow(&client, &SleepyWorkflow, (), WorkflowOptions::default()).await;
+    assert_eq!(completion.status, WorkflowEventType::Succeeeded);
+    assert_eq!(
+        jamesporal::core::payload::from_payload::<(String, String)>(&completion.result).unwrap(),
+        ("BEFORE".to_string(), "AFTER".to_string())
+    );
+
+    assert_eq!(WORKFLOW_DELIVERIES.load(Ordering::SeqCst), 2);
+    assert_eq!(EXECUTED_ACTIVITIES.load(Ordering::SeqCst), 2);
+    assert_eq!(common::activity_events(&db, run_id).await.len(), 2);
+}
+
+#[tokio::test]
+async fn replays_that_diverge_from_history_fail() {
+    let db = Db::new();
+    let client = common::start_worker(&common::start_server(db.clone()).await, handlers()).await;
+
+    let (_, completion) =
+        common::run_workflow(&client, &FickleWorkflow, (), WorkflowOptions::default()).await;
+    assert_eq!(completion.status, WorkflowEventType::Failed);
+    assert!(
+        completion.error.contains("NonDeterminism"),
+        "unexpected error: {}",
+        completion.error
+    );
+}
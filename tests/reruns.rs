@@ -0,0 +1,440 @@
+mod common;
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use jamesporal::core::payload::from_payload;
+use jamesporal::core::workflow::{WorkflowEventType, WorkflowOptions, WorkflowRunId};
+use jamesporal::core::{
+    ActivityContext, ActivityHandler, Client, WorkflowContext, WorkflowHandler,
+};
+use jamesporal::inmemory_db::Db;
+use serde_json::{json, Value};
+
+/// What `PlannedWorkflow` does for a given plan key. Reruns reuse the original input, so
+/// tests change the plan between runs instead.
+#[derive(Clone)]
+struct Plan {
+    values: Vec<String>,
+    end: End,
+}
+
+/// What `PlannedWorkflow` does after running its activities.
+#[derive(Clone, Copy)]
+enum End {
+    Succeed,
+    Fail,
+    /// Waits for a signal that never comes, until the run is cancelled or times out.
+    Wait,
+}
+
+static PLANS: Mutex<Option<HashMap<String, Plan>>> = Mutex::new(None);
+static EXECUTIONS: Mutex<Option<HashMap<String, usize>>> = Mutex::new(None);
+
+fn set_plan(key: &str, values: &[&str], end: End) {
+    PLANS.lock().unwrap().get_or_insert_default().insert(
+        key.to_string(),
+        Plan {
+            values: values.iter().map(|v| v.to_string()).collect(),
+            end,
+        },
+    );
+}
+
+fn plan(key: &str) -> Plan {
+    PLANS.lock().unwrap().get_or_insert_default()[key].clone()
+}
+
+fn executions(key: &str) -> usize {
+    EXECUTIONS
+        .lock()
+        .unwrap()
+        .get_or_insert_default()
+        .get(key)
+        .copied()
+        .unwrap_or_default()
+}
+
+/// Tags its input with how many times it has run for the plan key, so a memoized result
+/// can be told apart from a fresh one.
+struct Tally;
+
+#[async_trait::async_trait]
+impl ActivityHandler for Tally {
+    type Input = (String, String);
+    type Output = String;
+    type Error = String;
+
+    async fn run(
+        &self,
+        _context: ActivityContext,
+        (key, value): (String, String),
+    ) -> Result<String, String> {
+        let mut executions = EXECUTIONS.lock().unwrap();
+        let count = executions.get_or_insert_default().entry(key).or_default();
+        *count += 1;
+        Ok(format!("{value}#{count}"))
+    }
+}
+
+/// Runs `Tally` for every value of its plan, then ends the way the plan says.
+struct PlannedWorkflow;
+
+#[async_trait::async_trait]
+impl WorkflowHandler for PlannedWorkflow {
+    type Input = String;
+    type Output = Vec<String>;
+    type Error = String;
+
+    async fn run(&self, mut context: WorkflowContext, key: String) -> Result<Vec<String>, String> {
+        let plan = plan(&key);
+        let mut results = vec![];
+        for value in plan.values {
+            results.push(
+                context
+                    .execute_typed_activity(Tally, (key.clone(), value))
+                    .await?,
+            );
+        }
+        match plan.end {
+            End::Succeed => Ok(results),
+            End::Fail => Err(format!("PlanError: {results:?}")),
+            End::Wait => {
+                context.wait_for_signal("never").await?;
+                Ok(results)
+            }
+        }
+    }
+}
+
+/// Takes an approval, then fails if the plan says so and takes a second one otherwise.
+struct ApprovedWorkflow;
+
+#[async_trait::async_trait]
+impl WorkflowHandler for ApprovedWorkflow {
+    type Input = String;
+    type Output = Vec<String>;
+    type Error = String;
+
+    async fn run(&self, mut context: WorkflowContext, key: String) -> Result<Vec<String>, String> {
+        let first = context.wait_for_signal("approval").await?;
+        if let End::Fail = plan(&key).end {
+            return Err(format!("PlanError: approved by {first}"));
+        }
+        let second = context.wait_for_signal("approval").await?;
+        Ok(vec![first, second])
+    }
+}
+
+fn handlers() -> Vec<common::Handler> {
+    vec![
+        common::activity(Tally),
+        common::workflow(PlannedWorkflow),
+        common::workflow(ApprovedWorkflow),
+    ]
+}
+
+/// Posts a rerun request, returning the response status and body.
+async fn rerun(url: &str, request: Value) -> (u16, Value) {
+    let res = reqwest::Client::new()
+        .post(format!("{url}/rerun_workflow"))
+        .json(&request)
+        .send()
+        .await
+        .unwrap();
+    (res.status().as_u16(), res.json().await.unwrap())
+}
+
+/// Reruns `workflow_run_id` and waits for the new run to finish.
+async fn run_rerun(url: &str, client: &Client, request: Value) -> Vec<String> {
+    let (status, body) = rerun(url, request).await;
+    assert_eq!(status, 200, "rerun rejected: {body}");
+    let new_run_id: WorkflowRunId =
+        serde_json::from_value(body["new_workflow_run_id"].clone()).unwrap();
+
+    let completion = common::wait_for_completion(client, new_run_id).await;
+    assert_eq!(
+        completion.status,
+        WorkflowEventType::Succeeeded,
+        "{}",
+        completion.error
+    );
+    from_payload(&completion.result).unwrap()
+}
+
+async fn finished_run(
+    client: &Client,
+    key: &str,
+    options: WorkflowOptions,
+    status: WorkflowEventType,
+) -> WorkflowRunId {
+    let (run_id, completion) = common::run_workflow(client, &PlannedWorkflow, key, options).await;
+    assert_eq!(completion.status, status, "{}", completion.error);
+    run_id
+}
+
+async fn failed_run(client: &Client, key: &str) -> WorkflowRunId {
+    finished_run(
+        client,
+        key,
+        WorkflowOptions::default(),
+        WorkflowEventType::Failed,
+    )
+    .await
+}
+
+#[tokio::test]
+async fn reruns_match_repeated_identical_activities_by_sequence() {
+    let db = Db::new();
+    let url = common::start_server(db.clone()).await;
+    let client = common::start_worker(&url, handlers()).await;
+
+    let key = "identical";
+    set_plan(key, &["a", "a", "b", "a"], End::Fail);
+    let run_id = failed_run(&client, key).await;
+    assert_eq!(executions(key), 4);
+
+    set_plan(key, &["a", "a", "b", "a"], End::Succeed);
+    let results = run_rerun(&url, &client, json!({ "workflow_run_id": run_id })).await;
+    assert_eq!(results, ["a#1", "a#2", "b#3", "a#4"]);
+    assert_eq!(executions(key), 4);
+}
+
+#[tokio::test]
+async fn reruns_that_reorder_activities_re_execute_from_the_divergence() {
+    let db = Db::new();
+    let url = common::start_server(db.clone()).await;
+    let client = common::start_worker(&url, handlers()).await;
+
+    let key = "reordered";
+    set_plan(key, &["a", "b", "c"], End::Fail);
+    let run_id = failed_run(&client, key).await;
+
+    // Same activities and inputs, but the second and third swap places.
+    set_plan(key, &["a", "c", "b"], End::Succeed);
+    let results = run_rerun(&url, &client, json!({ "workflow_run_id": run_id })).await;
+    assert_eq!(results, ["a#1", "c#4", "b#5"]);
+}
+
+#[tokio::test]
+async fn reruns_stop_reusing_results_once_an_input_changes() {
+    let db = Db::new();
+    let url = common::start_server(db.clone()).await;
+    let client = common::start_worker(&url, handlers()).await;
+
+    let key = "changed-input";
+    set_plan(key, &["a", "b", "a"], End::Fail);
+    let run_id = failed_run(&client, key).await;
+
+    // The third activity is the same as before, but it comes after the divergence.
+    set_plan(key, &["a", "x", "a"], End::Succeed);
+    let results = run_rerun(&url, &client, json!({ "workflow_run_id": run_id })).await;
+    assert_eq!(results, ["a#1", "x#4", "a#5"]);
+}
+
+#[tokio::test]
+async fn reruns_re_execute_everything_from_the_checkpoint() {
+    let db = Db::new();
+    let url = common::start_server(db.clone()).await;
+    let client = common::start_worker(&url, handlers()).await;
+
+    let key = "checkpoint-sequence";
+    set_plan(key, &["a", "b", "c"], End::Fail);
+    let run_id = failed_run(&client, key).await;
+    set_plan(key, &["a", "b", "c"], End::Succeed);
+
+    let second = common::activity_events(&db, run_id).await[1][0].clone();
+    let results = run_rerun(
+        &url,
+        &client,
+        json!({ "workflow_run_id": run_id, "checkpoint": { "sequence": second.sequence } }),
+    )
+    .await;
+    assert_eq!(results, ["a#1", "b#4", "c#5"]);
+
+    // The same checkpoint, given as the activity run it starts from.
+    let results = run_rerun(
+        &url,
+        &client,
+        json!({
+            "workflow_run_id": run_id,
+            "checkpoint": { "activity_run_id": second.activity_run_id },
+        }),
+    )
+    .await;
+    assert_eq!(results, ["a#1", "b#6", "c#7"]);
+}
+
+#[tokio::test]
+async fn reruns_reject_checkpoints_outside_the_run() {
+    let db = Db::new();
+    let url = common::start_server(db.clone()).await;
+    let client = common::start_worker(&url, handlers()).await;
+
+    let key = "checkpoint-elsewhere";
+    set_plan(key, &["a"], End::Fail);
+    let run_id = failed_run(&client, key).await;
+    let other_run_id = failed_run(&client, key).await;
+    let elsewhere = common::activity_events(&db, other_run_id).await[0][0].activity_run_id;
+
+    let (status, body) = rerun(
+        &url,
+        json!({ "workflow_run_id": run_id, "checkpoint": { "activity_run_id": elsewhere } }),
+    )
+    .await;
+    assert_eq!(status, 404);
+    assert_eq!(body["error"], "checkpoint_not_found");
+
+    // The run only has an activity at sequence 1.
+    let (status, body) = rerun(
+        &url,
+        json!({ "workflow_run_id": run_id, "checkpoint": { "sequence": 2 } }),
+    )
+    .await;
+    assert_eq!(status, 404);
+    assert_eq!(body["error"], "checkpoint_not_found");
+
+    let (status, body) = rerun(
+        &url,
+        json!({
+            "workflow_run_id": run_id,
+            "mode": "force_full_reexecution",
+            "checkpoint": { "sequence": 1 },
+        }),
+    )
+    .await;
+    assert_eq!(status, 422);
+    assert_eq!(body["error"], "mode_not_allowed");
+}
+
+#[tokio::test]
+async fn reruns_of_succeeded_runs_re_execute_everything() {
+    let db = Db::new();
+    let url = common::start_server(db.clone()).await;
+    let client = common::start_worker(&url, handlers()).await;
+
+    let key = "succeeded";
+    set_plan(key, &["a", "b"], End::Succeed);
+    let run_id = finished_run(
+        &client,
+        key,
+        WorkflowOptions::default(),
+        WorkflowEventType::Succeeeded,
+    )
+    .await;
+
+    let (status, body) = rerun(&url, json!({ "workflow_run_id": run_id })).await;
+    assert_eq!(status, 422);
+    assert_eq!(body["error"], "mode_not_allowed");
+
+    let results = run_rerun(
+        &url,
+        &client,
+        json!({ "workflow_run_id": run_id, "mode": "force_full_reexecution" }),
+    )
+    .await;
+    assert_eq!(results, ["a#3", "b#4"]);
+
+    let other_key = "succeeded-new-input";
+    set_plan(other_key, &["c"], End::Succeed);
+    let new_input = jamesporal::core::payload::to_payload(&other_key).unwrap();
+    let results = run_rerun(
+        &url,
+        &client,
+        json!({ "workflow_run_id": run_id, "mode": { "new_input": { "input": new_input } } }),
+    )
+    .await;
+    assert_eq!(results, ["c#1"]);
+}
+
+#[tokio::test]
+async fn reruns_of_cancelled_and_timed_out_runs_reuse_results() {
+    let db = Db::new();
+    let url = common::start_server(db.clone()).await;
+    let client = common::start_worker(&url, handlers()).await;
+
+    let key = "cancelled";
+    set_plan(key, &["a"], End::Wait);
+    let options = WorkflowOptions::default();
+    let run_id = common::start_workflow(&client, &PlannedWorkflow, key, options).await;
+
+    let (status, body) = rerun(&url, json!({ "workflow_run_id": run_id })).await;
+    assert_eq!(status, 409);
+    assert_eq!(body["error"], "run_in_progress");
+
+    common::wait_until(|| async { executions(key) == 1 }).await;
+    client.cancel_workflow(run_id).await.unwrap();
+    let completion = common::wait_for_completion(&client, run_id).await;
+    assert_eq!(completion.status, WorkflowEventType::Cancelled);
+
+    set_plan(key, &["a"], End::Succeed);
+    let results = run_rerun(&url, &client, json!({ "workflow_run_id": run_id })).await;
+    assert_eq!(results, ["a#1"]);
+
+    let key = "timed-out";
+    set_plan(key, &["a"], End::Wait);
+    // The rerun inherits the timeout, and may wait for the worker to notice the first run
+    // timed out before it gets picked up.
+    let options = WorkflowOptions {
+        execution_timeout: Some(Duration::from_secs(5)),
+        ..WorkflowOptions::default()
+    };
+    let run_id = finished_run(&client, key, options, WorkflowEventType::TimedOut).await;
+
+    set_plan(key, &["a"], End::Succeed);
+    let results = run_rerun(&url, &client, json!({ "workflow_run_id": run_id })).await;
+    assert_eq!(results, ["a#1"]);
+}
+
+#[tokio::test]
+async fn reruns_take_new_signals_after_the_memoized_ones() {
+    let db = Db::new();
+    let url = common::start_server(db.clone()).await;
+    let client = common::start_worker(&url, handlers()).await;
+
+    let key = "approved";
+    set_plan(key, &[], End::Fail);
+    let options = WorkflowOptions::default();
+    let run_id = common::start_workflow(&client, &ApprovedWorkflow, key, options).await;
+    client
+        .signal_workflow(run_id, "approval".to_string(), "alice".to_string())
+        .await
+        .unwrap();
+    let completion = common::wait_for_completion(&client, run_id).await;
+    assert_eq!(completion.status, WorkflowEventType::Failed);
+
+    set_plan(key, &[], End::Succeed);
+    let (status, body) = rerun(&url, json!({ "workflow_run_id": run_id })).await;
+    assert_eq!(status, 200, "rerun rejected: {body}");
+    let new_run_id: WorkflowRunId =
+        serde_json::from_value(body["new_workflow_run_id"].clone()).unwrap();
+
+    // The rerun reuses alice's approval, so bob's is the first one it takes itself.
+    client
+        .signal_workflow(new_run_id, "approval".to_string(), "bob".to_string())
+        .await
+        .unwrap();
+    let completion = common::wait_for_completion(&client, new_run_id).await;
+    assert_eq!(
+        completion.status,
+        WorkflowEventType::Succeeeded,
+        "{}",
+        completion.error
+    );
+    assert_eq!(
+        from_payload::<Vec<String>>(&completion.result).unwrap(),
+        ["alice", "bob"]
+    );
+}
+
+#[tokio::test]
+async fn reruns_of_unknown_runs_are_not_found() {
+    let db = Db::new();
+    let url = common::start_server(db.clone()).await;
+    common::start_worker(&url, handlers()).await;
+
+    let (status, body) = rerun(&url, json!({ "workflow_run_id": WorkflowRunId::new() })).await;
+    assert_eq!(status, 404);
+    assert_eq!(body["error"], "run_not_found");
+}
@@ -0,0 +1,250 @@
+mod common;
+
+use std::time::Duration;
+
+use chrono::Utc;
+
+use jamesporal::core::activity::{ActivityEvent, ActivityEventType, ActivityName};
+use jamesporal::core::worker_events::{ServerEvent, WorkerEvent};
+use jamesporal::core::workflow::{WorkflowEventType, WorkflowOptions};
+use jamesporal::core::{
+    ActivityContext, ActivityHandler, ActivityOptions, Client, RetryOptions, WorkflowContext,
+    WorkflowHandler,
+};
+use jamesporal::inmemory_db::Db;
+use jamesporal::store::Store;
+
+/// Fails every attempt before `succeed_on`.
+struct Flaky;
+
+#[async_trait::async_trait]
+impl ActivityHandler for Flaky {
+    type Input = i64;
+    type Output = i64;
+    type Error = String;
+
+    async fn run(&self, context: ActivityContext, succeed_on: i64) -> Result<i64, String> {
+        if context.attempt_number < succeed_on {
+            return Err(format!("Flaky: attempt {}", context.attempt_number));
+        }
+        Ok(context.attempt_number)
+    }
+}
+
+/// Runs `Flaky` with up to `max_attempts` attempts.
+struct RetryingWorkflow;
+
+#[async_trait::async_trait]
+impl WorkflowHandler for RetryingWorkflow {
+    type Input = (i64, i64);
+    type Output = i64;
+    type Error = String;
+
+    async fn run(
+        &self,
+        mut context: WorkflowContext,
+        (succeed_on, max_attempts): (i64, i64),
+    ) -> Result<i64, String> {
+        context.with_activity_options(ActivityOptions {
+            retry_policy: RetryOptions {
+                max_attempts,
+                initial_interval: Duration::from_millis(100),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        Ok(context.execute_typed_activity(Flaky, succeed_on).await?)
+    }
+}
+
+/// Schedules `Flaky` with a negative backoff coefficient.
+struct InvalidOptionsWorkflow;
+
+#[async_trait::async_trait]
+impl WorkflowHandler for InvalidOptionsWorkflow {
+    type Input = ();
+    type Output = i64;
+    type Error = String;
+
+    async fn run(&self, mut context: WorkflowContext, _input: ()) -> Result<i64, String> {
+        context.with_activity_options(ActivityOptions {
+            retry_policy: RetryOptions {
+                backoff_coefficient: -1.0,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        Ok(context.execute_typed_activity(Flaky, 1).await?)
+    }
+}
+
+fn handlers() -> Vec<common::Handler> {
+    vec![
+        common::activity(Flaky),
+        common::workflow(RetryingWorkflow),
+        common::workflow(InvalidOptionsWorkflow),
+    ]
+}
+
+fn summary(events: &[ActivityEvent]) -> Vec<(ActivityEventType, i64)> {
+    events
+        .iter()
+        .map(|event| (event.event_type.clone(), event.attempt_number))
+        .collect()
+}
+
+#[tokio::test]
+async fn failed_attempts_are_retried_after_a_backoff() {
+    let db = Db::new();
+    let client = common::start_worker(&common::start_server(db.clone()).await, handlers()).await;
+
+    let (run_id, completion) = common::run_workflow(
+        &client,
+        &RetryingWorkflow,
+        (3, 5),
+        WorkflowOptions::default(),
+    )
+    .await;
+    assert_eq!(completion.status, WorkflowEventType::Succeeeded);
+    assert_eq!(completion.result, "3");
+
+    let runs = common::activity_events(&db, run_id).await;
+    assert_eq!(runs.len(), 1);
+    let events = &runs[0];
+    assert_eq!(
+        summary(events),
+        vec![
+            (ActivityEventType::Pending, 1),
+            (ActivityEventType::Started, 1),
+            (ActivityEventType::Failed, 1),
+            (ActivityEventType::Pending, 2),
+            (ActivityEventType::Started, 2),
+            (ActivityEventType::Failed, 2),
+            (ActivityEventType::Pending, 3),
+            (ActivityEventType::Started, 3),
+            (ActivityEventType::Succeeeded, 3),
+        ]
+    );
+
+    // Retries wait out the backoff: 100ms after the first failure, 200ms after the second.
+    for (failed, retry, backoff) in [(2, 3, 100), (5, 6, 200)] {
+        let scheduled_for = events[retry]
+            .scheduled_for
+            .expect("retries are scheduled for later");
+        let delay = scheduled_for - events[failed].created_at;
+        assert!(delay >= chrono::TimeDelta::milliseconds(backoff - 1));
+        assert!(delay < chrono::TimeDelta::milliseconds(backoff + 50));
+        assert!(events[retry + 1].created_at >= scheduled_for);
+    }
+}
+
+#[tokio::test]
+async fn the_last_failure_is_reported_once_attempts_run_out() {
+    let db = Db::new();
+    let client = common::start_worker(&common::start_server(db.clone()).await, handlers()).await;
+
+    let (run_id, completion) = common::run_workflow(
+        &client,
+        &RetryingWorkflow,
+        (10, 2),
+        WorkflowOptions::default(),
+    )
+    .await;
+    assert_eq!(completion.status, WorkflowEventType::Failed);
+    assert!(completion.error.contains("Flaky: attempt 2"));
+
+    let runs = common::activity_events(&db, run_id).await;
+    let attempts = summary(&runs[0]);
+    assert_eq!(attempts.last(), Some(&(ActivityEventType::Failed, 2)));
+    assert_eq!(
+        attempts
+            .iter()
+            .filter(|(event_type, _)| *event_type == ActivityEventType::Started)
+            .count(),
+        2
+    );
+}
+
+#[tokio::test]
+async fn activities_are_not_retried_by_default() {
+    let db = Db::new();
+    let client = common::start_worker(&common::start_server(db.clone()).await, handlers()).await;
+
+    let (run_id, completion) = common::run_workflow(
+        &client,
+        &RetryingWorkflow,
+        (2, 1),
+        WorkflowOptions::default(),
+    )
+    .await;
+    assert_eq!(completion.status, WorkflowEventType::Failed);
+
+    let runs = common::activity_events(&db, run_id).await;
+    assert_eq!(
+        summary(&runs[0]),
+        vec![
+            (ActivityEventType::Pending, 1),
+            (ActivityEventType::Started, 1),
+            (ActivityEventType::Failed, 1),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn invalid_retry_options_fail_the_workflow() {
+    let db = Db::new();
+    let client = common::start_worker(&common::start_server(db.clone()).await, handlers()).await;
+
+    let (run_id, completion) = common::run_workflow(
+        &client,
+        &InvalidOptionsWorkflow,
+        (),
+        WorkflowOptions::default(),
+    )
+    .await;
+    assert_eq!(completion.status, WorkflowEventType::Failed);
+    assert!(
+        completion.error.contains("InvalidInput"),
+        "{}",
+        completion.error
+    );
+    assert!(common::activity_events(&db, run_id).await.is_empty());
+}
+
+#[tokio::test]
+async fn the_server_rejects_invalid_retry_options() {
+    let db = Db::new();
+    let url = common::start_server(db.clone()).await;
+    let run_id = common::start_workflow(
+        &Client::new(&url),
+        &RetryingWorkflow,
+        (1, 1),
+        WorkflowOptions::default(),
+    )
+    .await;
+
+    let response = common::post_worker_event(
+        &url,
+        &WorkerEvent::EnqueuActivity {
+            name: ActivityName::from(&Flaky),
+            input: "1".to_string(),
+            activity_run_id: Default::default(),
+            workflow_run_id: run_id,
+            started_at: Utc::now(),
+            sequence: 1,
+            options: ActivityOptions {
+                retry_policy: RetryOptions {
+                    jitter: -0.5,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        },
+    )
+    .await;
+    match response {
+        ServerEvent::Rejected { error } => assert!(error.starts_with("InvalidInput:"), "{error}"),
+        _ => panic!("expected a rejection"),
+    }
+    assert!(db.get_workflow_commands(run_id).await.is_empty());
+}
@@ -0,0 +1,137 @@
+mod common;
+
+use std::time::Duration;
+
+use jamesporal::core::workflow::{WorkflowEventType, WorkflowName, WorkflowOptions, WorkflowRunId};
+use jamesporal::core::{Client, WorkflowContext, WorkflowHandler};
+use jamesporal::inmemory_db::Db;
+use jamesporal::store::{SqliteStore, Store};
+use serde_json::{json, Value};
+
+/// Waits for a signal that never comes.
+struct Idle;
+
+#[async_trait::async_trait]
+impl WorkflowHandler for Idle {
+    type Input = ();
+    type Output = ();
+    type Error = String;
+
+    async fn run(&self, mut context: WorkflowContext, _input: ()) -> Result<(), String> {
+        context.wait_for_signal("never").await?;
+        Ok(())
+    }
+}
+
+/// Posts a terminate request, returning the response status and body.
+async fn terminate(url: &str, request: Value) -> (u16, Value) {
+    let res = reqwest::Client::new()
+        .post(format!("{url}/terminate_workflow"))
+        .json(&request)
+        .send()
+        .await
+        .unwrap();
+    (res.status().as_u16(), res.json().await.unwrap())
+}
+
+async fn wait_until_started(store: &impl Store, run_id: WorkflowRunId) {
+    common::wait_until(|| async {
+        store
+            .get_last_workflow_run_event(run_id)
+            .await
+            .is_some_and(|event| event.event_type == WorkflowEventType::Started)
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn terminated_runs_report_why_they_ended() {
+    let db = Db::new();
+    let url = common::start_server(db.clone()).await;
+    let client = common::start_worker(&url, vec![common::workflow(Idle)]).await;
+
+    let run_id = common::start_workflow(&client, &Idle, (), WorkflowOptions::default()).await;
+    wait_until_started(&db, run_id).await;
+    let completion = tokio::spawn({
+        let client = client.clone();
+        async move { common::wait_for_completion(&client, run_id).await }
+    });
+
+    let request = json!({ "workflow_run_id": run_id, "reason": "maintenance" });
+    let (status, body) = terminate(&url, request.clone()).await;
+    assert_eq!(status, 200, "{body}");
+    assert_eq!(body["terminated"], json!(run_id));
+
+    let completion = completion.await.unwrap();
+    assert_eq!(completion.status, WorkflowEventType::Terminated);
+    assert_eq!(completion.error, "Terminated: maintenance");
+
+    let (status, body) = terminate(&url, request).await;
+    assert_eq!(status, 409);
+    assert_eq!(body["error"], "run_finished");
+}
+
+#[tokio::test]
+async fn runs_no_worker_holds_can_be_terminated() {
+    let url = common::start_server(Db::new()).await;
+    let client = Client::new(url.clone());
+    client
+        .register_workflow(WorkflowName::from(&Idle))
+        .await
+        .unwrap();
+
+    let run_id = common::start_workflow(&client, &Idle, (), WorkflowOptions::default()).await;
+    let (status, body) = terminate(&url, json!({ "workflow_run_id": run_id })).await;
+    assert_eq!(status, 200, "{body}");
+
+    let completion = common::wait_for_completion(&client, run_id).await;
+    assert_eq!(completion.status, WorkflowEventType::Terminated);
+    assert!(
+        completion.error.starts_with("Terminated"),
+        "{}",
+        completion.error
+    );
+
+    let (status, body) = terminate(&url, json!({ "workflow_run_id": WorkflowRunId::new() })).await;
+    assert_eq!(status, 404);
+    assert_eq!(body["error"], "run_not_found");
+}
+
+#[tokio::test]
+async fn concurrent_terminates_end_the_run_once() {
+    let path = common::temp_path("terminates.sqlite");
+    let store = SqliteStore::open(&path).unwrap();
+    let urls = [
+        common::start_server(store.clone()).await,
+        common::start_server(SqliteStore::open(&path).unwrap()).await,
+    ];
+    let client = common::start_worker(&urls[0], vec![common::workflow(Idle)]).await;
+
+    let run_id = common::start_workflow(&client, &Idle, (), WorkflowOptions::default()).await;
+    wait_until_started(&store, run_id).await;
+    // Give the worker time to start waiting for the signal.
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let requests =
+        (0..16).map(|i| terminate(&urls[i % urls.len()], json!({ "workflow_run_id": run_id })));
+    let statuses: Vec<u16> = futures::future::join_all(requests)
+        .await
+        .into_iter()
+        .map(|(status, _)| status)
+        .collect();
+    assert_eq!(statuses.iter().filter(|&&status| status == 200).count(), 1);
+    assert!(
+        statuses
+            .iter()
+            .all(|&status| status == 200 || status == 409),
+        "{statuses:?}"
+    );
+
+    let terminated = store
+        .get_workflow_run_events(run_id)
+        .await
+        .into_iter()
+        .filter(|event| event.event_type == WorkflowEventType::Terminated)
+        .count();
+    assert_eq!(terminated, 1);
+}
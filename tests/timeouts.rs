@@ -0,0 +1,370 @@
+mod common;
+
+use std::time::Duration;
+
+use jamesporal::core::activity::{ActivityEventType, ActivityName};
+use jamesporal::core::workflow::{WorkflowEventType, WorkflowName, WorkflowOptions};
+use jamesporal::core::{
+    ActivityContext, ActivityHandler, ActivityOptions, Client, RetryOptions, WorkflowContext,
+    WorkflowHandler,
+};
+use jamesporal::inmemory_db::Db;
+use jamesporal::store::{SqliteStore, Store};
+
+/// Sleeps for the given number of milliseconds, then fails if asked to.
+struct Nap;
+
+#[async_trait::async_trait]
+impl ActivityHandler for Nap {
+    type Input = (u64, bool);
+    type Output = ();
+    type Error = String;
+
+    async fn run(
+        &self,
+        _context: ActivityContext,
+        (millis, fail): (u64, bool),
+    ) -> Result<(), String> {
+        tokio::time::sleep(Duration::from_millis(millis)).await;
+        if fail {
+            return Err("NapError: woke up grumpy".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Registered with the server, but no worker ever polls for it.
+struct Unhandled;
+
+#[async_trait::async_trait]
+impl ActivityHandler for Unhandled {
+    type Input = ();
+    type Output = ();
+    type Error = String;
+
+    async fn run(&self, _context: ActivityContext, _input: ()) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Runs `Nap` (or `Unhandled`, without a nap) with the given options.
+struct NapWorkflow;
+
+#[async_trait::async_trait]
+impl WorkflowHandler for NapWorkflow {
+    type Input = (ActivityOptions, Option<(u64, bool)>);
+    type Output = ();
+    type Error = String;
+
+    async fn run(
+        &self,
+        mut context: WorkflowContext,
+        (options, nap): (ActivityOptions, Option<(u64, bool)>),
+    ) -> Result<(), String> {
+        context.with_activity_options(options);
+        match nap {
+            Some(nap) => Ok(context.execute_typed_activity(Nap, nap).await?),
+            None => Ok(context.execute_typed_activity(Unhandled, ()).await?),
+        }
+    }
+}
+
+/// Waits for the given number of milliseconds, optionally inside a long activity.
+struct SlowWorkflow;
+
+#[async_trait::async_trait]
+impl WorkflowHandler for SlowWorkflow {
+    type Input = (u64, bool);
+    type Output = ();
+    type Error = String;
+
+    async fn run(
+        &self,
+        mut context: WorkflowContext,
+        (millis, in_activity): (u64, bool),
+    ) -> Result<(), String> {
+        if in_activity {
+            context.execute_typed_activity(Nap, (millis, false)).await?;
+        } else {
+            tokio::time::sleep(Duration::from_millis(millis)).await;
+        }
+        Ok(())
+    }
+}
+
+/// Registered with the server, but no worker ever polls for it.
+struct UnhandledWorkflow;
+
+#[async_trait::async_trait]
+impl WorkflowHandler for UnhandledWorkflow {
+    type Input = ();
+    type Output = ();
+    type Error = String;
+
+    async fn run(&self, _context: WorkflowContext, _input: ()) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Starts a worker for the handlers above, and registers `Unhandled` and
+/// `UnhandledWorkflow` without one.
+async fn start_worker(db: &Db) -> Client {
+    let url = common::start_server(db.clone()).await;
+    let handlers = vec![
+        common::activity(Nap),
+        common::workflow(NapWorkflow),
+        common::workflow(SlowWorkflow),
+    ];
+    let client = common::start_worker(&url, handlers).await;
+    client
+        .register_activity(ActivityName::from(&Unhandled))
+        .await
+        .unwrap();
+    client
+        .register_workflow(WorkflowName::from(&UnhandledWorkflow))
+        .await
+        .unwrap();
+    client
+}
+
+fn retries(max_attempts: i64, initial_interval: Duration) -> RetryOptions {
+    RetryOptions {
+        max_attempts,
+        initial_interval,
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn attempts_that_run_too_long_time_out_and_are_retried() {
+    let db = Db::new();
+    let client = start_worker(&db).await;
+
+    let options = ActivityOptions {
+        retry_policy: retries(2, Duration::from_millis(10)),
+        start_to_close_timeout: Some(Duration::from_millis(100)),
+        ..Default::default()
+    };
+    let (run_id, completion) = common::run_workflow(
+        &client,
+        &NapWorkflow,
+        (options, Some((2_000, false))),
+        WorkflowOptions::default(),
+    )
+    .await;
+    assert_eq!(completion.status, WorkflowEventType::Failed);
+    assert!(
+        completion.error.contains("TimedOut"),
+        "{}",
+        completion.error
+    );
+
+    let events = &common::activity_events(&db, run_id).await[0];
+    let event_types: Vec<_> = events.iter().map(|e| e.event_type.clone()).collect();
+    assert_eq!(
+        event_types,
+        vec![
+            ActivityEventType::Pending,
+            ActivityEventType::Started,
+            ActivityEventType::TimedOut,
+            ActivityEventType::Pending,
+            ActivityEventType::Started,
+            ActivityEventType::TimedOut,
+        ]
+    );
+}
+
+#[tokio::test]
+async fn schedule_to_close_timeout_stops_retries() {
+    let db = Db::new();
+    let client = start_worker(&db).await;
+
+    let options = ActivityOptions {
+        retry_policy: retries(100, Duration::from_millis(200)),
+        schedule_to_close_timeout: Some(Duration::from_millis(500)),
+        ..Default::default()
+    };
+    let (run_id, completion) = common::run_workflow(
+        &client,
+        &NapWorkflow,
+        (options, Some((0, true))),
+        WorkflowOptions::default(),
+    )
+    .await;
+    assert_eq!(completion.status, WorkflowEventType::Failed);
+
+    let events = &common::activity_events(&db, run_id).await[0];
+    let attempts = events
+        .iter()
+        .filter(|e| e.event_type == ActivityEventType::Started)
+        .count();
+    assert!((1..=4).contains(&attempts), "{attempts} attempts");
+    assert!(events.last().unwrap().event_type.is_finished());
+}
+
+#[tokio::test]
+async fn activities_nobody_picks_up_time_out() {
+    let db = Db::new();
+    let client = start_worker(&db).await;
+
+    let options = ActivityOptions {
+        retry_policy: retries(5, Duration::from_millis(10)),
+        schedule_to_start_timeout: Some(Duration::from_millis(200)),
+        ..Default::default()
+    };
+    let (run_id, completion) = common::run_workflow(
+        &client,
+        &NapWorkflow,
+        (options, None::<(u64, bool)>),
+        WorkflowOptions::default(),
+    )
+    .await;
+    assert_eq!(completion.status, WorkflowEventType::Failed);
+    assert!(
+        completion.error.contains("schedule-to-start"),
+        "{}",
+        completion.error
+    );
+
+    // Not retried, since another attempt would wait in the same queue.
+    let events = &common::activity_events(&db, run_id).await[0];
+    let event_types: Vec<_> = events.iter().map(|e| e.event_type.clone()).collect();
+    assert_eq!(
+        event_types,
+        vec![ActivityEventType::Pending, ActivityEventType::TimedOut]
+    );
+}
+
+fn timeouts(execution: Option<u64>, run: Option<u64>) -> WorkflowOptions {
+    WorkflowOptions {
+        execution_timeout: execution.map(Duration::from_millis),
+        run_timeout: run.map(Duration::from_millis),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn workflows_time_out_after_their_execution_timeout() {
+    let db = Db::new();
+    let client = start_worker(&db).await;
+
+    let (_, completion) = common::run_workflow(
+        &client,
+        &SlowWorkflow,
+        (5_000, false),
+        timeouts(Some(300), None),
+    )
+    .await;
+    assert_eq!(completion.status, WorkflowEventType::TimedOut);
+    assert!(
+        completion.error.contains("execution"),
+        "{}",
+        completion.error
+    );
+}
+
+#[tokio::test]
+async fn workflows_time_out_after_their_run_timeout() {
+    let db = Db::new();
+    let client = start_worker(&db).await;
+
+    let (_, completion) = common::run_workflow(
+        &client,
+        &SlowWorkflow,
+        (5_000, false),
+        timeouts(None, Some(300)),
+    )
+    .await;
+    assert_eq!(completion.status, WorkflowEventType::TimedOut);
+    assert!(completion.error.contains("run"), "{}", completion.error);
+}
+
+#[tokio::test]
+async fn execution_timeout_includes_time_spent_queued() {
+    let db = Db::new();
+    let client = start_worker(&db).await;
+
+    let (run_id, completion) = common::run_workflow(
+        &client,
+        &UnhandledWorkflow,
+        (),
+        timeouts(Some(300), Some(300)),
+    )
+    .await;
+    assert_eq!(completion.status, WorkflowEventType::TimedOut);
+    assert!(
+        completion.error.contains("execution"),
+        "{}",
+        completion.error
+    );
+
+    let event_types: Vec<_> = db
+        .get_workflow_run_events(run_id)
+        .await
+        .into_iter()
+        .map(|e| e.event_type)
+        .collect();
+    assert_eq!(
+        event_types,
+        vec![WorkflowEventType::Pending, WorkflowEventType::TimedOut]
+    );
+}
+
+#[tokio::test]
+async fn workflows_finishing_in_time_are_not_timed_out() {
+    let db = Db::new();
+    let client = start_worker(&db).await;
+
+    let (_, completion) = common::run_workflow(
+        &client,
+        &SlowWorkflow,
+        (50, true),
+        timeouts(Some(5_000), Some(5_000)),
+    )
+    .await;
+    assert_eq!(completion.status, WorkflowEventType::Succeeeded);
+}
+
+#[tokio::test]
+async fn timing_out_cancels_outstanding_activities() {
+    let db = Db::new();
+    let client = start_worker(&db).await;
+
+    let (run_id, completion) = common::run_workflow(
+        &client,
+        &SlowWorkflow,
+        (5_000, true),
+        timeouts(Some(300), None),
+    )
+    .await;
+    assert_eq!(completion.status, WorkflowEventType::TimedOut);
+
+    let events = &common::activity_events(&db, run_id).await[0];
+    assert_eq!(
+        events.last().unwrap().event_type,
+        ActivityEventType::Cancelled
+    );
+}
+
+#[tokio::test]
+async fn timeouts_are_enforced_again_once_the_store_recovers() {
+    let path = common::temp_path("enforcer.sqlite");
+    let client = Client::new(common::start_server(SqliteStore::open(&path).unwrap()).await);
+    client
+        .register_workflow(WorkflowName::from(&UnhandledWorkflow))
+        .await
+        .unwrap();
+    let run_id =
+        common::start_workflow(&client, &UnhandledWorkflow, (), timeouts(Some(1_000), None)).await;
+
+    // Every store call panics while the table is gone, including the enforcer's.
+    let conn = rusqlite::Connection::open(&path).unwrap();
+    conn.execute_batch("ALTER TABLE workflow_events RENAME TO workflow_events_hidden")
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    conn.execute_batch("ALTER TABLE workflow_events_hidden RENAME TO workflow_events")
+        .unwrap();
+
+    let completion = common::wait_for_completion(&client, run_id).await;
+    assert_eq!(completion.status, WorkflowEventType::TimedOut);
+}
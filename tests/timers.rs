@@ -0,0 +1,86 @@
+mod common;
+
+use std::time::Duration;
+
+use chrono::{TimeDelta, Utc};
+use jamesporal::core::payload::from_payload;
+use jamesporal::core::worker_events::{ServerEvent, WorkerEvent};
+use jamesporal::core::workflow::{WorkflowEventType, WorkflowName, WorkflowOptions};
+use jamesporal::core::{Client, WorkflowContext, WorkflowHandler};
+use jamesporal::server::Server;
+use jamesporal::store::{SqliteStore, Store};
+use tokio::net::TcpListener;
+
+struct Sleeper;
+
+#[async_trait::async_trait]
+impl WorkflowHandler for Sleeper {
+    type Input = ();
+    type Output = String;
+    type Error = String;
+
+    async fn run(&self, mut context: WorkflowContext, _input: ()) -> Result<String, String> {
+        context.sleep(Duration::from_millis(500)).await?;
+        Ok("rested".to_string())
+    }
+}
+
+#[tokio::test]
+async fn timers_started_before_a_restart_fire_afterwards() {
+    let path = common::temp_path("timers.sqlite");
+
+    // A worker picks the run up and starts its timer, then the server goes down.
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let server = tokio::spawn(Server::new(SqliteStore::open(&path).unwrap()).serve(listener));
+    let client = Client::new(url.clone());
+    client
+        .register_workflow(WorkflowName::from(&Sleeper))
+        .await
+        .unwrap();
+    let run_id = common::start_workflow(&client, &Sleeper, (), WorkflowOptions::default()).await;
+    let poll = WorkerEvent::PollWorkflow {
+        name: WorkflowName::from(&Sleeper),
+    };
+    let ServerEvent::PollWorkflowResponse(task) = common::post_worker_event(&url, &poll).await
+    else {
+        panic!("expected a workflow task");
+    };
+    assert_eq!(task.workflow_run_id, run_id);
+    let start_timer = WorkerEvent::StartTimer {
+        workflow_run_id: run_id,
+        started_at: task.started_at,
+        sequence: 1,
+        fire_at: Utc::now() + TimeDelta::milliseconds(500),
+    };
+    assert!(matches!(
+        common::post_worker_event(&url, &start_timer).await,
+        ServerEvent::TimerStarted { sleeping: true }
+    ));
+    server.abort();
+    let _ = server.await;
+
+    let store = SqliteStore::open(&path).unwrap();
+    let url = common::start_server(store.clone()).await;
+    let client = common::start_worker(&url, vec![common::workflow(Sleeper)]).await;
+
+    let completion = common::wait_for_completion(&client, run_id).await;
+    assert_eq!(
+        completion.status,
+        WorkflowEventType::Succeeeded,
+        "{}",
+        completion.error
+    );
+    assert_eq!(
+        from_payload::<String>(&completion.result).unwrap(),
+        "rested"
+    );
+
+    let fired = store
+        .get_workflow_run_events(run_id)
+        .await
+        .into_iter()
+        .filter(|event| event.event_type == WorkflowEventType::TimerFired)
+        .count();
+    assert_eq!(fired, 1);
+}
@@ -0,0 +1,322 @@
+mod common;
+
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+
+use chrono::Utc;
+use jamesporal::core::activity::{
+    Activity, ActivityContext, ActivityEvent, ActivityEventType, ActivityHeartbeat, ActivityId,
+    ActivityName, ActivityRunId,
+};
+use jamesporal::core::workflow::{
+    Workflow, WorkflowContext, WorkflowEvent, WorkflowEventType, WorkflowId, WorkflowName,
+    WorkflowRunId, WorkflowSignal,
+};
+use jamesporal::core::{AbstractActivityHandler, AbstractWorkflowHandler};
+use jamesporal::inmemory_db::Db;
+use jamesporal::store::Store;
+
+struct LoggedActivity;
+
+#[async_trait::async_trait]
+impl AbstractActivityHandler for LoggedActivity {
+    async fn run(&self, _context: ActivityContext, input: String) -> Result<String, String> {
+        Ok(input)
+    }
+}
+
+struct LoggedWorkflow;
+
+#[async_trait::async_trait]
+impl AbstractWorkflowHandler for LoggedWorkflow {
+    async fn run(&self, _context: WorkflowContext, input: String) -> Result<String, String> {
+        Ok(input)
+    }
+}
+
+fn json<T: serde::Serialize>(value: &T) -> String {
+    serde_json::to_string(value).unwrap()
+}
+
+fn workflow_event(workflow_id: WorkflowId, run_id: WorkflowRunId) -> WorkflowEvent {
+    WorkflowEvent {
+        workflow_id,
+        run_id,
+        event_type: WorkflowEventType::Pending,
+        rerun_of: None,
+        rerun_checkpoint: None,
+        payload: "input".to_string(),
+        created_at: Utc::now(),
+        options: Default::default(),
+        fire_at: None,
+        child_run_id: None,
+        parent_run_id: None,
+    }
+}
+
+fn activity_event(activity_id: ActivityId, workflow_run_id: WorkflowRunId) -> ActivityEvent {
+    ActivityEvent {
+        activity_id,
+        activity_run_id: ActivityRunId::new(),
+        workflow_run_id,
+        event_type: ActivityEventType::Pending,
+        payload: "input".to_string(),
+        created_at: Utc::now(),
+        attempt_number: 1,
+        sequence: 1,
+        options: Default::default(),
+        scheduled_for: None,
+    }
+}
+
+fn signal(workflow_run_id: WorkflowRunId, payload: usize) -> WorkflowSignal {
+    WorkflowSignal {
+        workflow_run_id,
+        signal_name: "tick".to_string(),
+        payload: payload.to_string(),
+        created_at: Utc::now(),
+    }
+}
+
+/// What `populate` wrote, to check a reopened db against.
+struct Written {
+    workflow: Workflow,
+    activity: Activity,
+    claimed_run: WorkflowRunId,
+    pending_run: WorkflowRunId,
+    activity_run: ActivityRunId,
+}
+
+/// Writes one of everything the log records, including conditional appends and claims.
+async fn populate(db: &Db) -> Written {
+    let workflow = Workflow {
+        id: WorkflowId::new(),
+        name: WorkflowName::from(&LoggedWorkflow),
+    };
+    let activity = Activity {
+        id: ActivityId::new(),
+        name: ActivityName::from(&LoggedActivity),
+    };
+    db.add_workflow(workflow.clone()).await;
+    db.add_activity(activity.clone()).await;
+
+    let claimed_run = WorkflowRunId::new();
+    let pending_run = WorkflowRunId::new();
+    db.add_workflow_event(workflow_event(workflow.id, claimed_run))
+        .await;
+    db.add_workflow_event(workflow_event(workflow.id, pending_run))
+        .await;
+    let claimed = db
+        .claim_pending_workflow(workflow.name.clone())
+        .await
+        .unwrap();
+    assert_eq!(claimed.run_id, claimed_run);
+    db.add_workflow_signal(signal(claimed_run, 0)).await;
+
+    let pending = activity_event(activity.id, claimed_run);
+    db.add_activity_event(pending.clone()).await;
+    let started = db
+        .claim_pending_activity(activity.name.clone())
+        .await
+        .unwrap();
+    let started = db
+        .get_last_activity_run_event(started.activity_run_id)
+        .await
+        .unwrap();
+    db.record_activity_heartbeat(ActivityHeartbeat {
+        activity_run_id: pending.activity_run_id,
+        attempt_number: 1,
+        details: "halfway".to_string(),
+        created_at: Utc::now(),
+    })
+    .await;
+    let succeeded = ActivityEvent {
+        event_type: ActivityEventType::Succeeeded,
+        payload: "output".to_string(),
+        created_at: Utc::now(),
+        ..started.clone()
+    };
+    assert!(
+        db.add_activity_events_if_last(&started, vec![succeeded])
+            .await
+    );
+
+    Written {
+        workflow,
+        activity,
+        claimed_run,
+        pending_run,
+        activity_run: pending.activity_run_id,
+    }
+}
+
+/// Checks that `db` holds exactly what `original` held after `populate`.
+async fn assert_restored(db: &Db, original: &Db, written: &Written) {
+    assert_eq!(
+        json(&db.get_workflow_by_name(&written.workflow.name).await),
+        json(&Some(&written.workflow))
+    );
+    assert_eq!(
+        json(&db.get_activity_by_name(&written.activity.name).await),
+        json(&Some(&written.activity))
+    );
+    for run_id in [written.claimed_run, written.pending_run] {
+        assert_eq!(
+            json(&db.get_workflow_run_events(run_id).await),
+            json(&original.get_workflow_run_events(run_id).await)
+        );
+        assert_eq!(
+            json(&db.get_workflow_signals(run_id).await),
+            json(&original.get_workflow_signals(run_id).await)
+        );
+    }
+    assert_eq!(
+        json(&db.get_activity_run_events(written.activity_run).await),
+        json(&original.get_activity_run_events(written.activity_run).await)
+    );
+    assert_eq!(
+        db.get_last_activity_heartbeat(written.activity_run)
+            .await
+            .unwrap()
+            .details,
+        "halfway"
+    );
+}
+
+fn log_files(dir: &Path) -> Vec<String> {
+    let mut names: Vec<_> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+    names
+}
+
+#[tokio::test]
+async fn reopening_replays_the_log() {
+    let dir = common::temp_path("wal-replay");
+    let db = Db::open(&dir).unwrap();
+    let written = populate(&db).await;
+
+    let reopened = Db::open(&dir).unwrap();
+    assert_restored(&reopened, &db, &written).await;
+
+    // Only the run that was never claimed is handed out again.
+    let claimed = reopened
+        .claim_pending_workflow(written.workflow.name.clone())
+        .await
+        .unwrap();
+    assert_eq!(claimed.run_id, written.pending_run);
+    assert!(reopened
+        .claim_pending_workflow(written.workflow.name.clone())
+        .await
+        .is_none());
+    assert!(reopened
+        .claim_pending_activity(written.activity.name.clone())
+        .await
+        .is_none());
+}
+
+#[tokio::test]
+async fn reopening_ignores_a_torn_trailing_record() {
+    let dir = common::temp_path("wal-torn");
+    let db = Db::open(&dir).unwrap();
+    let written = populate(&db).await;
+
+    // The server died halfway through appending a signal.
+    let log = log_files(&dir)
+        .into_iter()
+        .find(|name| name.starts_with("log-"))
+        .unwrap();
+    let line = json(&signal(written.claimed_run, 1));
+    let mut file = OpenOptions::new().append(true).open(dir.join(log)).unwrap();
+    write!(file, "{{\"WorkflowSignal\":{}", &line[..line.len() / 2]).unwrap();
+    drop(file);
+
+    let reopened = Db::open(&dir).unwrap();
+    assert_restored(&reopened, &db, &written).await;
+
+    // Writes after the torn record survive the next restart.
+    reopened
+        .add_workflow_signal(signal(written.claimed_run, 2))
+        .await;
+    let restarted = Db::open(&dir).unwrap();
+    let payloads: Vec<_> = restarted
+        .get_workflow_signals(written.claimed_run)
+        .await
+        .into_iter()
+        .map(|signal| signal.payload)
+        .collect();
+    assert_eq!(payloads, ["0", "2"]);
+}
+
+#[tokio::test]
+async fn long_logs_are_compacted_into_a_snapshot() {
+    let dir = common::temp_path("wal-compaction");
+    let db = Db::open(&dir).unwrap();
+    let written = populate(&db).await;
+
+    for payload in 1..=10_050 {
+        db.add_workflow_signal(signal(written.claimed_run, payload))
+            .await;
+    }
+    // The snapshot is written in the background.
+    common::wait_until(|| async { !log_files(&dir).contains(&"log-0.jsonl".to_string()) }).await;
+    let files = log_files(&dir);
+    assert!(files.contains(&"snapshot-1.jsonl".to_string()), "{files:?}");
+
+    let reopened = Db::open(&dir).unwrap();
+    assert_restored(&reopened, &db, &written).await;
+    assert_eq!(
+        reopened
+            .get_workflow_signals(written.claimed_run)
+            .await
+            .len(),
+        10_051
+    );
+}
+
+#[tokio::test]
+async fn writes_go_on_while_snapshots_are_written() {
+    let dir = common::temp_path("wal-compaction-threshold");
+    let db = Db::open_compacting_after(&dir, 100).unwrap();
+    let written = populate(&db).await;
+
+    for payload in 1..=1_000 {
+        db.add_workflow_signal(signal(written.claimed_run, payload))
+            .await;
+    }
+    // Done once only the latest generation is left.
+    common::wait_until(|| async {
+        let files = log_files(&dir);
+        files.len() == 2 && files[1].starts_with("snapshot-")
+    })
+    .await;
+
+    let reopened = Db::open(&dir).unwrap();
+    assert_restored(&reopened, &db, &written).await;
+    assert_eq!(
+        reopened
+            .get_workflow_signals(written.claimed_run)
+            .await
+            .len(),
+        1_001
+    );
+}
+
+#[tokio::test]
+async fn logs_of_unfinished_snapshots_are_replayed() {
+    let dir = common::temp_path("wal-unfinished-snapshot");
+    let db = Db::open(&dir).unwrap();
+    let written = populate(&db).await;
+
+    // As if the server died after starting generation 1 but before its snapshot was
+    // complete: generation 0 is still there and generation 1 has a log of its own.
+    std::fs::copy(dir.join("log-0.jsonl"), dir.join("log-1.jsonl")).unwrap();
+    std::fs::write(dir.join("log-0.jsonl"), "").unwrap();
+    std::fs::write(dir.join("snapshot-1.jsonl.tmp"), "{").unwrap();
+
+    let reopened = Db::open(&dir).unwrap();
+    assert_restored(&reopened, &db, &written).await;
+}